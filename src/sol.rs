@@ -32,6 +32,9 @@ pub enum SolError {
 }
 pub mod geyser;
 pub mod sol_events;
+pub mod sol_math;
+pub mod sol_reserves;
+pub mod sol_state;
 
 impl SolMut {
     pub fn get_solana_client() -> RpcClient {
@@ -1,23 +1,35 @@
-use std::{collections::HashMap, time::Duration};
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    pin::Pin,
+    sync::Arc,
+    time::{Duration, Instant},
+};
 
-use super::sol_events::sol_platforms::{
-    orca::ORCA_ADDRESS, pump_fun::PUMPFUN_ADDRESS, raydium_clmm::RAYDIUM_CLMM_ADDRESS,
+use super::sol_events::sol_platforms::DECODED_PROGRAMS;
+use crossbeam_queue::ArrayQueue;
+use futures::{Sink, SinkExt, Stream, StreamExt, channel::mpsc::SendError};
+use serde::{Deserialize, Serialize};
+use yellowstone_grpc_client::{
+    ClientTlsConfig, GeyserGrpcClient, GeyserGrpcClientError, Interceptor,
 };
-use futures::Stream;
-use yellowstone_grpc_client::{ClientTlsConfig, GeyserGrpcBuilder, GeyserGrpcClient, Interceptor};
 use yellowstone_grpc_proto::{
+    geyser::subscribe_update::UpdateOneof,
     geyser::{
-        CommitmentLevel, SubscribeRequest, SubscribeRequestFilterTransactions, SubscribeUpdate,
-        geyser_client::GeyserClient,
+        CommitmentLevel, SubscribeRequest, SubscribeRequestFilterAccounts,
+        SubscribeRequestFilterAccountsFilter, SubscribeRequestFilterAccountsFilterMemcmp,
+        SubscribeRequestFilterBlocks, SubscribeRequestFilterBlocksMeta,
+        SubscribeRequestFilterEntry, SubscribeRequestFilterSlots,
+        SubscribeRequestFilterTransactions, SubscribeRequestPing, SubscribeUpdate,
+        subscribe_request_filter_accounts_filter::Filter,
+        subscribe_request_filter_accounts_filter_memcmp::Data,
     },
-    tonic::Status,
+    tonic::{Code, Status},
 };
 pub struct Geyser;
 impl Geyser {
     pub async fn build_config() -> Result<GeyserGrpcClient<impl Interceptor>, ()> {
-        rustls::crypto::ring::default_provider()
-            .install_default()
-            .expect("Failed to install rustls crypto provider");
+        // already installed when reconnecting
+        let _ = rustls::crypto::ring::default_provider().install_default();
 
         let tls_config = ClientTlsConfig::new().with_native_roots();
         let client = GeyserGrpcClient::build_from_shared(
@@ -31,49 +43,837 @@ impl Geyser {
         .map_err(|_| ())?
         .connect_timeout(Duration::from_secs(10));
 
-        Ok(client.connect().await.map_err(|_| ())?)
+        client.connect().await.map_err(|_| ())
     }
     pub async fn get_stream(
-        mut client: GeyserGrpcClient<impl Interceptor>,
+        client: GeyserGrpcClient<impl Interceptor>,
     ) -> Result<impl Stream<Item = Result<SubscribeUpdate, Status>>, ()> {
-        let mut transactions = HashMap::new();
-        let programs_to_listen = vec![
-            PUMPFUN_ADDRESS.to_string(),
-            ORCA_ADDRESS.to_string(),
-            RAYDIUM_CLMM_ADDRESS.to_string(),
-            RAYDIUM_CLMM_ADDRESS.to_owned(),
-        ];
-        transactions.insert(
-            "client".to_owned(),
-            SubscribeRequestFilterTransactions {
-                vote: Some(false),
-                failed: Some(false),
-                account_include: programs_to_listen, // radium // pump
-                account_exclude: vec![],
-                account_required: vec![],
-                signature: None,
-            },
-        );
+        Self::subscribe(client, GeyserSubscription::default()).await
+    }
+    /// `include_failed` also streams the transactions that landed with an error,
+    /// they are emitted as `MutEvents::Failed`
+    pub async fn get_stream_with(
+        client: GeyserGrpcClient<impl Interceptor>,
+        include_failed: bool,
+    ) -> Result<impl Stream<Item = Result<SubscribeUpdate, Status>>, ()> {
+        let filter =
+            TransactionFilter::default().failed(if include_failed { None } else { Some(false) });
+        let subscription = GeyserSubscription::new().filter("client", filter);
+        Self::subscribe(client, subscription).await
+    }
+    /// Pings are answered and keepalives sent while the stream is polled
+    pub async fn subscribe(
+        client: GeyserGrpcClient<impl Interceptor>,
+        subscription: GeyserSubscription,
+    ) -> Result<impl Stream<Item = Result<SubscribeUpdate, Status>>, ()> {
+        Ok(Self::subscribe_handle(client, subscription)
+            .await?
+            .into_stream())
+    }
+    /// Keeps the request sink to update the subscription in place
+    pub async fn subscribe_handle(
+        client: GeyserGrpcClient<impl Interceptor>,
+        subscription: GeyserSubscription,
+    ) -> Result<GeyserHandle, ()> {
+        Self::open(client, subscription).await.map_err(|_| ())
+    }
+    async fn open(
+        mut client: GeyserGrpcClient<impl Interceptor>,
+        subscription: GeyserSubscription,
+    ) -> Result<GeyserHandle, GeyserGrpcClientError> {
+        let (sink, stream) = client
+            .subscribe_with_request(Some(subscription.request()))
+            .await?;
+
+        Ok(GeyserHandle {
+            sink: Box::pin(sink),
+            stream: Box::pin(stream),
+            subscription,
+            keepalive: KEEPALIVE_INTERVAL,
+            last_ping: Instant::now(),
+        })
+    }
+}
+
+/// A named transaction filter, a transaction matches when it touches any `include`
+/// account, none of the `exclude` ones and all the `require` ones
+#[derive(Debug, Clone)]
+pub struct TransactionFilter {
+    vote: Option<bool>,
+    failed: Option<bool>,
+    account_include: Vec<String>,
+    account_exclude: Vec<String>,
+    account_required: Vec<String>,
+}
 
-        let subreq = SubscribeRequest {
-            accounts: HashMap::default(),
-            slots: HashMap::default(),
-            transactions,
-            transactions_status: HashMap::default(),
-            blocks: HashMap::default(),
-            blocks_meta: HashMap::default(),
-            entry: HashMap::default(),
-            commitment: Some(CommitmentLevel::Processed as i32),
+impl Default for TransactionFilter {
+    /// Successful transactions of every decoded platform
+    fn default() -> Self {
+        TransactionFilter::new().include(DECODED_PROGRAMS)
+    }
+}
+
+impl TransactionFilter {
+    /// Successful non vote transactions, without account constraints
+    pub fn new() -> Self {
+        TransactionFilter {
+            vote: Some(false),
+            failed: Some(false),
+            account_include: Vec::new(),
+            account_exclude: Vec::new(),
+            account_required: Vec::new(),
+        }
+    }
+    /// Accounts or programs, added to the current ones
+    pub fn include<S: ToString>(mut self, accounts: impl IntoIterator<Item = S>) -> Self {
+        extend_unique(&mut self.account_include, accounts);
+        self
+    }
+    pub fn exclude<S: ToString>(mut self, accounts: impl IntoIterator<Item = S>) -> Self {
+        extend_unique(&mut self.account_exclude, accounts);
+        self
+    }
+    pub fn require<S: ToString>(mut self, accounts: impl IntoIterator<Item = S>) -> Self {
+        extend_unique(&mut self.account_required, accounts);
+        self
+    }
+    /// Drops the accounts from the include, exclude and require lists
+    pub fn remove<S: ToString>(mut self, accounts: impl IntoIterator<Item = S>) -> Self {
+        let accounts: Vec<String> = accounts.into_iter().map(|x| x.to_string()).collect();
+        for list in [
+            &mut self.account_include,
+            &mut self.account_exclude,
+            &mut self.account_required,
+        ] {
+            list.retain(|x| !accounts.contains(x));
+        }
+        self
+    }
+    /// `None` streams both vote and non vote transactions
+    pub fn vote(mut self, vote: Option<bool>) -> Self {
+        self.vote = vote;
+        self
+    }
+    /// `None` streams both failed and successful transactions
+    pub fn failed(mut self, failed: Option<bool>) -> Self {
+        self.failed = failed;
+        self
+    }
+    /// Without include or require accounts every non vote transaction matches
+    fn is_constrained(&self) -> bool {
+        !self.account_include.is_empty() || !self.account_required.is_empty()
+    }
+}
+
+fn extend_unique<S: ToString>(list: &mut Vec<String>, accounts: impl IntoIterator<Item = S>) {
+    for account in accounts {
+        let account = account.to_string();
+        if !list.contains(&account) {
+            list.push(account);
+        }
+    }
+}
+
+impl From<TransactionFilter> for SubscribeRequestFilterTransactions {
+    fn from(value: TransactionFilter) -> Self {
+        SubscribeRequestFilterTransactions {
+            vote: value.vote,
+            failed: value.failed,
+            account_include: value.account_include,
+            account_exclude: value.account_exclude,
+            account_required: value.account_required,
+            signature: None,
+        }
+    }
+}
+
+/// Accounts listed by address or owned by a program, narrowed to the ones starting
+/// with `discriminator` when set
+#[derive(Debug, Clone, Default)]
+pub struct AccountFilter {
+    accounts: Vec<String>,
+    owners: Vec<String>,
+    discriminator: Option<[u8; 8]>,
+}
+
+impl AccountFilter {
+    pub fn new() -> Self {
+        AccountFilter::default()
+    }
+    pub fn accounts<S: ToString>(mut self, accounts: impl IntoIterator<Item = S>) -> Self {
+        extend_unique(&mut self.accounts, accounts);
+        self
+    }
+    pub fn owners<S: ToString>(mut self, owners: impl IntoIterator<Item = S>) -> Self {
+        extend_unique(&mut self.owners, owners);
+        self
+    }
+    pub fn discriminator(mut self, discriminator: [u8; 8]) -> Self {
+        self.discriminator = Some(discriminator);
+        self
+    }
+}
+
+impl From<AccountFilter> for SubscribeRequestFilterAccounts {
+    fn from(value: AccountFilter) -> Self {
+        let filters = value
+            .discriminator
+            .map(|x| SubscribeRequestFilterAccountsFilter {
+                filter: Some(Filter::Memcmp(SubscribeRequestFilterAccountsFilterMemcmp {
+                    offset: 0,
+                    data: Some(Data::Bytes(x.to_vec())),
+                })),
+            })
+            .into_iter()
+            .collect();
+        SubscribeRequestFilterAccounts {
+            account: value.accounts,
+            owner: value.owners,
+            filters,
+            ..Default::default()
+        }
+    }
+}
+
+/// Builds the `SubscribeRequest` sent to the geyser endpoint
+#[derive(Debug, Clone)]
+pub struct GeyserSubscription {
+    transactions: HashMap<String, TransactionFilter>,
+    commitment: CommitmentLevel,
+    from_slot: Option<u64>,
+    accounts: HashMap<String, AccountFilter>,
+    slots: bool,
+    blocks_meta: bool,
+    blocks: bool,
+    entries: bool,
+    transactions_status: HashMap<String, TransactionFilter>,
+}
+
+impl Default for GeyserSubscription {
+    /// A single "client" filter over the decoded platforms, at processed commitment
+    fn default() -> Self {
+        GeyserSubscription::new().filter("client", TransactionFilter::default())
+    }
+}
+
+impl GeyserSubscription {
+    /// No filter, at processed commitment
+    pub fn new() -> Self {
+        GeyserSubscription {
+            transactions: HashMap::new(),
+            commitment: CommitmentLevel::Processed,
+            from_slot: None,
+            accounts: HashMap::new(),
+            slots: false,
+            blocks_meta: false,
+            blocks: false,
+            entries: false,
+            transactions_status: HashMap::new(),
+        }
+    }
+    /// Updates carry the names of the filters they matched, a filter replaces any
+    /// previous one of the same name
+    pub fn filter(mut self, name: impl Into<String>, filter: TransactionFilter) -> Self {
+        self.transactions.insert(name.into(), filter);
+        self
+    }
+    pub fn commitment(mut self, commitment: CommitmentLevel) -> Self {
+        self.commitment = commitment;
+        self
+    }
+    /// Replays from `slot`, within what the endpoint still retains
+    pub fn from_slot(mut self, slot: Option<u64>) -> Self {
+        self.from_slot = slot;
+        self
+    }
+    /// Account updates matching `filter`, a filter replaces any previous one of the
+    /// same name
+    pub fn account_filter(mut self, name: impl Into<String>, filter: AccountFilter) -> Self {
+        self.accounts.insert(name.into(), filter);
+        self
+    }
+    /// Slot status updates, for slot tracking
+    pub fn slots(mut self, slots: bool) -> Self {
+        self.slots = slots;
+        self
+    }
+    /// Block meta updates, they carry the block time
+    pub fn blocks_meta(mut self, blocks_meta: bool) -> Self {
+        self.blocks_meta = blocks_meta;
+        self
+    }
+    /// Full blocks with their transactions, without accounts and entries
+    pub fn blocks(mut self, blocks: bool) -> Self {
+        self.blocks = blocks;
+        self
+    }
+    pub fn entries(mut self, entries: bool) -> Self {
+        self.entries = entries;
+        self
+    }
+    /// Status updates of the transactions matching `filter`, lighter than the
+    /// transactions themselves. A filter replaces any previous one of the same name.
+    pub fn status_filter(mut self, name: impl Into<String>, filter: TransactionFilter) -> Self {
+        self.transactions_status.insert(name.into(), filter);
+        self
+    }
+    /// Applies `f` to every transaction filter
+    pub fn map_filters(mut self, f: impl Fn(TransactionFilter) -> TransactionFilter) -> Self {
+        self.transactions = self
+            .transactions
+            .into_iter()
+            .map(|(name, filter)| (name, f(filter)))
+            .collect();
+        self
+    }
+    /// Adds the programs to every transaction filter
+    pub fn add_programs<S: ToString>(self, programs: impl IntoIterator<Item = S>) -> Self {
+        let programs: Vec<String> = programs.into_iter().map(|x| x.to_string()).collect();
+        self.map_filters(|x| x.include(programs.iter()))
+    }
+    /// Drops the accounts from every transaction filter. A filter left without include
+    /// or require accounts is dropped too, it would match the whole chain.
+    pub fn remove_accounts<S: ToString>(mut self, accounts: impl IntoIterator<Item = S>) -> Self {
+        let accounts: Vec<String> = accounts.into_iter().map(|x| x.to_string()).collect();
+        self.transactions = self
+            .transactions
+            .into_iter()
+            .filter_map(|(name, filter)| {
+                let constrained = filter.is_constrained();
+                let filter = filter.remove(accounts.iter());
+                (!constrained || filter.is_constrained()).then_some((name, filter))
+            })
+            .collect();
+        self
+    }
+    pub fn request(&self) -> SubscribeRequest {
+        SubscribeRequest {
+            accounts: self
+                .accounts
+                .iter()
+                .map(|(name, filter)| (name.clone(), filter.clone().into()))
+                .collect(),
+            slots: if self.slots {
+                HashMap::from([("client".to_owned(), SubscribeRequestFilterSlots::default())])
+            } else {
+                HashMap::default()
+            },
+            transactions: self
+                .transactions
+                .iter()
+                .map(|(name, filter)| (name.clone(), filter.clone().into()))
+                .collect(),
+            transactions_status: self
+                .transactions_status
+                .iter()
+                .map(|(name, filter)| (name.clone(), filter.clone().into()))
+                .collect(),
+            blocks: if self.blocks {
+                HashMap::from([(
+                    "client".to_owned(),
+                    SubscribeRequestFilterBlocks {
+                        include_transactions: Some(true),
+                        include_accounts: Some(false),
+                        include_entries: Some(false),
+                        ..Default::default()
+                    },
+                )])
+            } else {
+                HashMap::default()
+            },
+            blocks_meta: if self.blocks_meta {
+                HashMap::from([(
+                    "client".to_owned(),
+                    SubscribeRequestFilterBlocksMeta::default(),
+                )])
+            } else {
+                HashMap::default()
+            },
+            entry: if self.entries {
+                HashMap::from([("client".to_owned(), SubscribeRequestFilterEntry::default())])
+            } else {
+                HashMap::default()
+            },
+            commitment: Some(self.commitment as i32),
             accounts_data_slice: Vec::default(),
             ping: None,
-            from_slot: None,
+            from_slot: self.from_slot,
+        }
+    }
+}
+
+/// Interval of the pings sent on quiet streams, load balancers drop idle streams
+pub const KEEPALIVE_INTERVAL: Duration = Duration::from_secs(15);
+
+type RequestSink = Pin<Box<dyn Sink<SubscribeRequest, Error = SendError> + Send>>;
+type UpdateStream = Pin<Box<dyn Stream<Item = Result<SubscribeUpdate, Status>> + Send>>;
+
+/// A live subscription, its filters can be changed without reconnecting
+pub struct GeyserHandle {
+    sink: RequestSink,
+    stream: UpdateStream,
+    subscription: GeyserSubscription,
+    keepalive: Duration,
+    last_ping: Instant,
+}
+
+impl GeyserHandle {
+    pub fn keepalive(mut self, keepalive: Duration) -> Self {
+        self.keepalive = keepalive;
+        self
+    }
+    pub fn subscription(&self) -> &GeyserSubscription {
+        &self.subscription
+    }
+
+    async fn ping(&mut self) -> Result<(), ()> {
+        self.last_ping = Instant::now();
+        let request = SubscribeRequest {
+            ping: Some(SubscribeRequestPing { id: 1 }),
+            ..Default::default()
         };
+        self.sink.send(request).await.map_err(|_| ())
+    }
+
+    /// Next update, `None` once the stream ended. Server pings are answered, the
+    /// pongs to our keepalives are passed through.
+    pub async fn next(&mut self) -> Option<Result<SubscribeUpdate, Status>> {
+        loop {
+            let remaining = self.keepalive.saturating_sub(self.last_ping.elapsed());
+            let Ok(update) = tokio::time::timeout(remaining, self.stream.next()).await else {
+                self.ping().await.ok()?;
+                continue;
+            };
+            if let Some(Ok(SubscribeUpdate {
+                update_oneof: Some(UpdateOneof::Ping(_)),
+                ..
+            })) = &update
+            {
+                self.ping().await.ok()?;
+            }
+            return update;
+        }
+    }
 
-        let (_sink, stream) = client
-            .subscribe_with_request(Some(subreq))
+    /// Replaces the subscription, the server applies it to the open stream. Its
+    /// `from_slot` is cleared, a filter change must not replay.
+    pub async fn update(&mut self, subscription: GeyserSubscription) -> Result<(), ()> {
+        let subscription = subscription.from_slot(None);
+        self.sink
+            .send(subscription.request())
             .await
             .map_err(|_| ())?;
+        self.subscription = subscription;
+        Ok(())
+    }
+
+    /// Adds the programs to every transaction filter
+    pub async fn add_programs<S: ToString>(
+        &mut self,
+        programs: impl IntoIterator<Item = S>,
+    ) -> Result<(), ()> {
+        let subscription = self.subscription.clone().add_programs(programs);
+        self.update(subscription).await
+    }
+
+    /// Drops the accounts from every transaction filter, see
+    /// `GeyserSubscription::remove_accounts`
+    pub async fn remove_accounts<S: ToString>(
+        &mut self,
+        accounts: impl IntoIterator<Item = S>,
+    ) -> Result<(), ()> {
+        let subscription = self.subscription.clone().remove_accounts(accounts);
+        self.update(subscription).await
+    }
+
+    pub fn into_stream(self) -> impl Stream<Item = Result<SubscribeUpdate, Status>> {
+        futures::stream::unfold(self, |mut handle| async move {
+            let update = handle.next().await?;
+            Some((update, handle))
+        })
+    }
+}
+
+/// Slots kept to drop the transactions replayed after a reconnect
+pub const DEDUPE_SLOTS: u64 = 150;
+
+/// Wait between reconnect attempts, doubling from `initial_backoff` up to `max_backoff`
+#[derive(Debug, Clone, Copy)]
+pub struct ReconnectPolicy {
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+/// Transactions after `last_processed_slot` and before `resumed_slot` may be missing,
+/// the endpoint could not replay them
+#[derive(Debug, Clone, Copy, Deserialize, Serialize)]
+pub struct SlotGap {
+    pub last_processed_slot: u64,
+    pub resumed_slot: u64,
+}
+
+/// The codes an endpoint answers a `from_slot` it no longer holds with
+fn rejects_replay(status: &Status) -> bool {
+    matches!(status.code(), Code::InvalidArgument | Code::OutOfRange)
+}
+
+/// A subscription that reconnects when its stream ends, resuming from the last
+/// processed slot
+pub struct SupervisedStream {
+    subscription: GeyserSubscription,
+    policy: ReconnectPolicy,
+    gaps: Arc<ArrayQueue<SlotGap>>,
+    stream: Option<GeyserHandle>,
+    backoff: Duration,
+    last_slot: Option<u64>,
+    /// Cleared when the endpoint rejected a replay from `last_slot`, the next
+    /// connection resumes live
+    replay: bool,
+    /// Set until the first update of a replaying connection
+    replaying: bool,
+    /// Slot a live resume happened after, reported with the next transaction
+    pending_gap: Option<u64>,
+    seen: BTreeMap<u64, HashSet<Vec<u8>>>,
+}
+
+impl SupervisedStream {
+    pub fn new(
+        subscription: GeyserSubscription,
+        policy: ReconnectPolicy,
+        gaps: Arc<ArrayQueue<SlotGap>>,
+    ) -> Self {
+        SupervisedStream {
+            subscription,
+            policy,
+            gaps,
+            stream: None,
+            backoff: policy.initial_backoff,
+            last_slot: None,
+            replay: true,
+            replaying: false,
+            pending_gap: None,
+            seen: BTreeMap::new(),
+        }
+    }
+
+    async fn wait(&mut self) {
+        tokio::time::sleep(self.backoff).await;
+        self.backoff = (self.backoff * 2).min(self.policy.max_backoff);
+    }
+
+    /// Slot the next connection replays from, a live resume reports the gap with
+    /// its first transaction
+    fn resume_slot(&mut self) -> Option<u64> {
+        let from_slot = self.last_slot.filter(|_| self.replay);
+        if from_slot.is_none() && self.last_slot.is_some() {
+            self.pending_gap = self.last_slot;
+        }
+        self.replaying = from_slot.is_some();
+        from_slot
+    }
+
+    async fn connect(&mut self) {
+        loop {
+            let from_slot = self.resume_slot();
+            let subscription = self.subscription.clone().from_slot(from_slot);
+            let status = match Geyser::build_config().await {
+                Ok(client) => match Geyser::open(client, subscription).await {
+                    Ok(handle) => {
+                        self.stream = Some(handle);
+                        return;
+                    }
+                    Err(GeyserGrpcClientError::TonicStatus(status)) => Some(status),
+                    Err(_) => None,
+                },
+                Err(()) => None,
+            };
+            self.fail(status.as_ref());
+            self.wait().await;
+        }
+    }
+
+    /// Drops the stream. Only an endpoint rejecting the slot stops the replay, after a
+    /// transport error the next connection replays again.
+    fn fail(&mut self, status: Option<&Status>) {
+        self.stream = None;
+        if self.replaying && status.is_some_and(rejects_replay) {
+            self.replay = false;
+        }
+    }
+
+    /// Drops replayed transactions, tracks the last slot and reports gaps
+    fn accept(&mut self, update: &SubscribeUpdate) -> bool {
+        let Some(UpdateOneof::Transaction(tx)) = &update.update_oneof else {
+            return true;
+        };
+        let Some(info) = &tx.transaction else {
+            return true;
+        };
+        if let Some(last_processed_slot) = self.pending_gap.take() {
+            let _ = self.gaps.force_push(SlotGap {
+                last_processed_slot,
+                resumed_slot: tx.slot,
+            });
+        }
+        if !self
+            .seen
+            .entry(tx.slot)
+            .or_default()
+            .insert(info.signature.clone())
+        {
+            return false;
+        }
+        let last_slot = self.last_slot.map_or(tx.slot, |x| x.max(tx.slot));
+        self.last_slot = Some(last_slot);
+        self.seen = self.seen.split_off(&last_slot.saturating_sub(DEDUPE_SLOTS));
+        true
+    }
+
+    /// Next update, reconnecting as many times as needed
+    pub async fn next(&mut self) -> SubscribeUpdate {
+        loop {
+            let Some(stream) = self.stream.as_mut() else {
+                self.connect().await;
+                continue;
+            };
+            match stream.next().await {
+                Some(Ok(update)) => {
+                    self.replaying = false;
+                    self.replay = true;
+                    self.backoff = self.policy.initial_backoff;
+                    if self.accept(&update) {
+                        return update;
+                    }
+                }
+                Some(Err(status)) => {
+                    self.fail(Some(&status));
+                    // a rejected subscription ends the stream right away, the backoff
+                    // only resets after an update came through
+                    self.wait().await;
+                }
+                None => {
+                    self.fail(None);
+                    self.wait().await;
+                }
+            }
+        }
+    }
+
+    pub fn subscription(&self) -> &GeyserSubscription {
+        &self.subscription
+    }
+
+    /// Sends the stored subscription to the open stream, a stream that can't take
+    /// it is dropped and the next connection starts with it
+    async fn resubscribe(&mut self) {
+        let Some(stream) = self.stream.as_mut() else {
+            return;
+        };
+        if stream.update(self.subscription.clone()).await.is_err() {
+            self.stream = None;
+        }
+    }
+
+    /// Adds the programs to every transaction filter, kept across reconnects
+    pub async fn add_programs<S: ToString>(&mut self, programs: impl IntoIterator<Item = S>) {
+        self.subscription = self.subscription.clone().add_programs(programs);
+        self.resubscribe().await
+    }
+
+    /// Drops the accounts from every transaction filter, kept across reconnects, see
+    /// `GeyserSubscription::remove_accounts`
+    pub async fn remove_accounts<S: ToString>(&mut self, accounts: impl IntoIterator<Item = S>) {
+        self.subscription = self.subscription.clone().remove_accounts(accounts);
+        self.resubscribe().await
+    }
+
+    /// Never ending stream, for `stream_events`
+    pub fn into_stream(self) -> impl Stream<Item = Result<SubscribeUpdate, Status>> {
+        futures::stream::unfold(self, |mut supervised| async move {
+            let update = supervised.next().await;
+            Some((Ok(update), supervised))
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use yellowstone_grpc_proto::geyser::{
+        SubscribeUpdateTransaction, SubscribeUpdateTransactionInfo,
+    };
+
+    use super::*;
+
+    #[test]
+    fn request_carries_every_filter() {
+        let request = GeyserSubscription::new()
+            .filter(
+                "swaps",
+                TransactionFilter::new()
+                    .include(["program"])
+                    .exclude(["spam"])
+                    .require(["pool"])
+                    .failed(None),
+            )
+            .account_filter(
+                "pools",
+                AccountFilter::new()
+                    .owners(["program"])
+                    .discriminator([1, 2, 3, 4, 5, 6, 7, 8]),
+            )
+            .status_filter("statuses", TransactionFilter::new().include(["program"]))
+            .slots(true)
+            .blocks_meta(true)
+            .commitment(CommitmentLevel::Confirmed)
+            .from_slot(Some(42))
+            .request();
+
+        let swaps = &request.transactions["swaps"];
+        assert_eq!(swaps.account_include, ["program"]);
+        assert_eq!(swaps.account_exclude, ["spam"]);
+        assert_eq!(swaps.account_required, ["pool"]);
+        assert_eq!((swaps.vote, swaps.failed), (Some(false), None));
+        let pools = &request.accounts["pools"];
+        assert_eq!(pools.owner, ["program"]);
+        assert!(pools.account.is_empty());
+        let Some(Filter::Memcmp(memcmp)) = &pools.filters[0].filter else {
+            panic!("{pools:?}");
+        };
+        assert_eq!(memcmp.offset, 0);
+        assert_eq!(memcmp.data, Some(Data::Bytes(vec![1, 2, 3, 4, 5, 6, 7, 8])));
+        assert_eq!(
+            request.transactions_status["statuses"].account_include,
+            ["program"]
+        );
+        assert_eq!(request.slots.len(), 1);
+        assert_eq!(request.blocks_meta.len(), 1);
+        assert!(request.blocks.is_empty() && request.entry.is_empty());
+        assert_eq!(request.commitment, Some(CommitmentLevel::Confirmed as i32));
+        assert_eq!(request.from_slot, Some(42));
+    }
+
+    #[test]
+    fn default_request_streams_the_decoded_programs() {
+        let request = GeyserSubscription::default().request();
+        let client = &request.transactions["client"];
+        assert_eq!(client.account_include, DECODED_PROGRAMS);
+        assert_eq!((client.vote, client.failed), (Some(false), Some(false)));
+        assert_eq!(request.commitment, Some(CommitmentLevel::Processed as i32));
+        assert!(request.accounts.is_empty() && request.from_slot.is_none());
+    }
+
+    #[test]
+    fn removing_accounts_never_widens_a_filter() {
+        let request = GeyserSubscription::new()
+            .filter("two", TransactionFilter::new().include(["a", "b"]))
+            .filter("one", TransactionFilter::new().include(["a"]))
+            .filter("required", TransactionFilter::new().require(["a"]))
+            .filter(
+                "pair",
+                TransactionFilter::new().include(["a"]).require(["pool"]),
+            )
+            .add_programs(["p"])
+            .filter("everything", TransactionFilter::new().exclude(["a", "c"]))
+            .remove_accounts(["a", "p"])
+            .request();
+
+        let mut names: Vec<&str> = request.transactions.keys().map(|x| x.as_str()).collect();
+        names.sort();
+        // "one" and "required" would match the whole chain
+        assert_eq!(names, ["everything", "pair", "two"]);
+        assert_eq!(request.transactions["two"].account_include, ["b"]);
+        assert!(request.transactions["pair"].account_include.is_empty());
+        assert_eq!(request.transactions["pair"].account_required, ["pool"]);
+        // unconstrained by choice, it stays so
+        assert_eq!(request.transactions["everything"].account_exclude, ["c"]);
+    }
+
+    fn supervised() -> (SupervisedStream, Arc<ArrayQueue<SlotGap>>) {
+        let gaps = Arc::new(ArrayQueue::new(4));
+        let stream = SupervisedStream::new(
+            GeyserSubscription::default(),
+            ReconnectPolicy::default(),
+            gaps.clone(),
+        );
+        (stream, gaps)
+    }
+
+    fn tx(slot: u64, signature: u8) -> SubscribeUpdate {
+        SubscribeUpdate {
+            update_oneof: Some(UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                transaction: Some(SubscribeUpdateTransactionInfo {
+                    signature: vec![signature; 64],
+                    ..Default::default()
+                }),
+                slot,
+            })),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn drops_the_transactions_replayed_within_the_dedupe_window() {
+        let (mut stream, gaps) = supervised();
+        assert!(stream.accept(&tx(100, 1)));
+        assert!(stream.accept(&tx(100, 2)));
+        // replayed after a reconnect
+        assert!(!stream.accept(&tx(100, 1)));
+        assert!(stream.accept(&tx(101, 3)));
+        assert!(!stream.accept(&tx(101, 3)));
+        assert_eq!(stream.last_slot, Some(101));
+
+        // slot 100 falls out of the window, a replay never goes back past `last_slot`
+        let last = 101 + DEDUPE_SLOTS;
+        assert!(stream.accept(&tx(last, 4)));
+        assert_eq!(stream.seen.keys().copied().collect::<Vec<_>>(), [101, last]);
+        assert!(!stream.accept(&tx(101, 3)));
+        // an older transaction leaves the last slot as is
+        assert!(stream.accept(&tx(last - 1, 5)));
+        assert_eq!(stream.last_slot, Some(last));
+
+        // other updates pass through
+        assert!(stream.accept(&SubscribeUpdate::default()));
+        assert!(gaps.is_empty());
+    }
+
+    #[test]
+    fn stops_replaying_only_when_the_endpoint_rejects_the_slot() {
+        let (mut stream, gaps) = supervised();
+        assert_eq!(stream.resume_slot(), None);
+        assert!(stream.accept(&tx(200, 1)));
+
+        assert_eq!(stream.resume_slot(), Some(200));
+        // transport errors and an unavailable endpoint are retried with the replay
+        stream.fail(None);
+        assert_eq!(stream.resume_slot(), Some(200));
+        stream.fail(Some(&Status::unavailable("connection reset")));
+        assert_eq!(stream.resume_slot(), Some(200));
+        assert!(gaps.is_empty());
 
-        Ok(stream)
+        for rejection in [
+            Status::out_of_range("slot 200 is not available"),
+            Status::invalid_argument("from_slot is too old"),
+        ] {
+            let (mut stream, gaps) = supervised();
+            assert!(stream.accept(&tx(200, 1)));
+            assert_eq!(stream.resume_slot(), Some(200));
+            stream.fail(Some(&rejection));
+            // resumes live, the first transaction reports the gap
+            assert_eq!(stream.resume_slot(), None);
+            assert!(stream.accept(&tx(230, 2)));
+            let gap = gaps.pop().unwrap();
+            assert_eq!((gap.last_processed_slot, gap.resumed_slot), (200, 230));
+            assert!(stream.accept(&tx(231, 3)));
+            assert!(gaps.is_empty());
+        }
     }
 }
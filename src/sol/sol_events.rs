@@ -1,155 +1,329 @@
-use std::collections::HashMap;
-use std::str::FromStr;
 use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::thread;
 use std::time::Duration;
-use std::time::Instant;
-use std::u8;
 
 use base64::Engine;
-use base64::alphabet::STANDARD;
 use base64::prelude::BASE64_STANDARD;
 use borsh::BorshDeserialize;
 use crossbeam_queue::ArrayQueue;
-use db::redis::Commands;
-use futures::SinkExt;
 use futures::Stream;
 use futures::StreamExt;
 use futures::pin_mut;
 use serde::Deserialize;
 use serde::Serialize;
+use serde_with::DisplayFromStr;
+use serde_with::serde_as;
+use sol_platforms::meteora_damm::v1 as damm_v1;
+use sol_platforms::meteora_damm::v1::DammV1Instruction;
+use sol_platforms::meteora_damm::v1::DammV1InstructionEvent;
+use sol_platforms::meteora_damm::v1::DammV1SwapEvent;
+use sol_platforms::meteora_damm::v1::METEORA_DAMM_V1_ADDRESS;
+use sol_platforms::meteora_damm::v2 as damm_v2;
+use sol_platforms::meteora_damm::v2::DammV2Instruction;
+use sol_platforms::meteora_damm::v2::DammV2InstructionEvent;
+use sol_platforms::meteora_damm::v2::DammV2Swap2Event;
+use sol_platforms::meteora_damm::v2::DammV2SwapEvent;
+use sol_platforms::meteora_damm::v2::DammV2TradeEvent;
+use sol_platforms::meteora_damm::v2::METEORA_DAMM_V2_ADDRESS;
+use sol_platforms::meteora_dlmm;
+use sol_platforms::meteora_dlmm::DlmmInstruction;
+use sol_platforms::meteora_dlmm::DlmmInstructionEvent;
+use sol_platforms::meteora_dlmm::DlmmSwapEvent;
+use sol_platforms::meteora_dlmm::METEORA_DLMM_ADDRESS;
 use sol_platforms::orca;
 use sol_platforms::orca::ORCA_ADDRESS;
+use sol_platforms::orca::WhirlpoolInstruction;
+use sol_platforms::orca::WhirlpoolInstructionEvent;
 use sol_platforms::pump_fun;
 use sol_platforms::pump_fun::PUMPFUN_ADDRESS;
-use sol_platforms::pump_fun::PumpFun;
-use sol_platforms::pump_fun::PumpFunEvent;
+use sol_platforms::pump_fun::PumpFunInstruction;
+use sol_platforms::pump_fun::PumpFunInstructionEvent;
+use sol_platforms::pump_swap;
+use sol_platforms::pump_swap::PUMPSWAP_ADDRESS;
+use sol_platforms::pump_swap::PumpSwapBuyEvent;
+use sol_platforms::pump_swap::PumpSwapCreatePoolEvent;
+use sol_platforms::pump_swap::PumpSwapDepositEvent;
+use sol_platforms::pump_swap::PumpSwapInstruction;
+use sol_platforms::pump_swap::PumpSwapInstructionEvent;
+use sol_platforms::pump_swap::PumpSwapSellEvent;
+use sol_platforms::pump_swap::PumpSwapTradeEvent;
+use sol_platforms::pump_swap::PumpSwapWithdrawEvent;
+use sol_platforms::raydium_amm_v4;
+use sol_platforms::raydium_amm_v4::AmmV4Instruction;
+use sol_platforms::raydium_amm_v4::AmmV4InstructionEvent;
+use sol_platforms::raydium_amm_v4::RAY_LOG_PREFIX;
+use sol_platforms::raydium_amm_v4::RAYDIUM_AMM_V4_ADDRESS;
+use sol_platforms::raydium_amm_v4::RaydiumAmmV4SwapEvent;
+use sol_platforms::raydium_clmm;
+use sol_platforms::raydium_clmm::ClmmInstruction;
+use sol_platforms::raydium_clmm::ClmmInstructionEvent;
 use sol_platforms::raydium_clmm::RAYDIUM_CLMM_ADDRESS;
 use sol_platforms::raydium_clmm::RaydiumClmmSwapEvent;
 use solana_pubkey::Pubkey;
 use solana_sdk::signature::Signature;
 use solana_transaction_status::EncodedTransactionWithStatusMeta;
-use solana_transaction_status::UiParsedInstruction;
+use solana_transaction_status::option_serializer::OptionSerializer;
 use yellowstone_grpc_proto::geyser::SubscribeUpdate;
+use yellowstone_grpc_proto::geyser::SubscribeUpdateAccount;
+use yellowstone_grpc_proto::geyser::SubscribeUpdateBlock;
+use yellowstone_grpc_proto::geyser::SubscribeUpdateBlockMeta;
+use yellowstone_grpc_proto::geyser::SubscribeUpdateEntry;
+use yellowstone_grpc_proto::geyser::SubscribeUpdateSlot;
 use yellowstone_grpc_proto::geyser::SubscribeUpdateTransaction;
+use yellowstone_grpc_proto::geyser::SubscribeUpdateTransactionStatus;
 use yellowstone_grpc_proto::{geyser::subscribe_update::UpdateOneof, tonic::Status};
 
+use crate::sol::sol_events::sol_errors::FailedTransaction;
 use crate::sol::sol_events::sol_platforms::orca::Traded;
 use crate::sol::sol_events::sol_platforms::pump_fun::PumpFunCreateEvent;
 use crate::sol::sol_events::sol_platforms::pump_fun::PumpFunTradeEvent;
+use crate::sol::sol_events::sol_platforms::raydium_cpmm::cpmm;
+use crate::sol::sol_events::sol_platforms::raydium_cpmm::cpmm::CpmmInstructionEvent;
 use crate::sol::sol_events::sol_platforms::raydium_cpmm::cpmm::RAYDIUM_CPMM;
 use crate::sol::sol_events::sol_platforms::raydium_cpmm::cpmm::RaydiumCpmmSwapEvent;
-use crate::sol::sol_events::sol_system_ix::SolanaIx;
+use crate::sol::sol_events::sol_platforms::raydium_cpmm::cpmm::RaydiumParsedCpmmEvent;
+use crate::sol::sol_events::sol_system_ix::TokenFlow;
+use crate::sol::sol_events::sol_system_ix::TokenFlowEvent;
+use crate::sol::sol_events::sol_tx::FlatInstruction;
+use crate::sol::sol_events::sol_tx::TxBalanceDelta;
+pub mod sol_errors;
 pub mod sol_platforms;
 pub mod sol_system_ix;
+pub mod sol_tx;
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum MutEvents {
     TradeEvent(TradeEvent),
     CreateEvent(CreateEvent),
+    LiquidityEvent(LiquidityEvent),
+    InstructionEvent(InstructionEvent),
+    TokenFlow(TokenFlowEvent),
+    /// Follows the other events of its transaction
+    BalanceDelta(TxBalanceDelta),
+    Failed(FailedTransaction),
 }
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum TradeEvent {
     PumpFunTrade(PumpFunTradeEvent),
     RaydiumClmmTrade(RaydiumClmmSwapEvent),
-    RaydiumCpmmTrade(RaydiumCpmmSwapEvent),
+    RaydiumCpmmTrade(RaydiumParsedCpmmEvent),
     OrcaTrade(Traded),
+    PumpSwapTrade(PumpSwapTradeEvent),
+    RaydiumAmmV4Trade(RaydiumAmmV4SwapEvent),
+    MeteoraDlmmTrade(DlmmSwapEvent),
+    MeteoraDammV1Trade(DammV1SwapEvent),
+    MeteoraDammV2Trade(DammV2TradeEvent),
 }
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub enum CreateEvent {
     PumpFunCreate(PumpFunCreateEvent),
+    PumpSwapPoolCreate(PumpSwapCreatePoolEvent),
+}
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum LiquidityEvent {
+    PumpSwapDeposit(PumpSwapDepositEvent),
+    PumpSwapWithdraw(PumpSwapWithdrawEvent),
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub enum InstructionEvent {
+    PumpFun(PumpFunInstructionEvent),
+    RaydiumCpmm(CpmmInstructionEvent),
+    RaydiumClmm(ClmmInstructionEvent),
+    Orca(WhirlpoolInstructionEvent),
+    PumpSwap(PumpSwapInstructionEvent),
+    RaydiumAmmV4(AmmV4InstructionEvent),
+    MeteoraDlmm(DlmmInstructionEvent),
+    MeteoraDammV1(DammV1InstructionEvent),
+    MeteoraDammV2(DammV2InstructionEvent),
+}
+
+/// Prefix of the self CPI instruction anchor's `emit_cpi!` carries an event in
+pub const EVENT_IX_TAG: [u8; 8] = [228, 69, 165, 46, 81, 203, 154, 29];
+
+/// Programs whose events are only emitted through a self CPI, the others log them
+/// as well and are decoded from the logs
+pub const EMIT_CPI_PROGRAMS: [&str; 2] = [METEORA_DLMM_ADDRESS, METEORA_DAMM_V2_ADDRESS];
+
+/// An event with its position in the chain, `(slot, tx_index, instruction_index)`
+/// orders events across transactions. The block time comes in the slot's
+/// `BlockMeta` update, after its transactions, see `UpdateQueues::blocks_meta`.
+#[serde_as]
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct EventEnvelope {
+    pub slot: u64,
+    /// Position of the transaction in its block
+    pub tx_index: u64,
+    pub signature: String,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub fee_payer: Option<Pubkey>,
+    /// Outer instruction, `None` for transaction level events
+    pub instruction_index: Option<usize>,
+    /// Only known for events decoded from instructions
+    pub inner_index: Option<usize>,
+    /// 1 for outer instructions
+    pub stack_height: Option<u32>,
+    pub event: MutEvents,
+}
+
+/// Transaction level fields shared by the envelopes of a transaction
+struct TxContext {
+    slot: u64,
+    tx_index: u64,
+    signature: String,
+    fee_payer: Option<Pubkey>,
+}
+
+impl TxContext {
+    fn wrap(
+        &self,
+        event: MutEvents,
+        instruction_index: Option<usize>,
+        inner_index: Option<usize>,
+        stack_height: Option<u32>,
+    ) -> EventEnvelope {
+        EventEnvelope {
+            slot: self.slot,
+            tx_index: self.tx_index,
+            signature: self.signature.clone(),
+            fee_payer: self.fee_payer,
+            instruction_index,
+            inner_index,
+            stack_height,
+            event,
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct ProgramData {
     pub program_id: String,
     pub data: String,
+    /// Outer instruction the emitter runs under
+    pub instruction_index: Option<usize>,
+    /// Invoke depth of the emitter
+    pub stack_height: Option<u32>,
+}
+
+/// Queues the non transaction updates are routed to, the kinds without a queue are
+/// dropped. A full queue drops its oldest update.
+#[derive(Clone, Default)]
+pub struct UpdateQueues {
+    pub accounts: Option<Arc<ArrayQueue<SubscribeUpdateAccount>>>,
+    pub slots: Option<Arc<ArrayQueue<SubscribeUpdateSlot>>>,
+    pub blocks_meta: Option<Arc<ArrayQueue<SubscribeUpdateBlockMeta>>>,
+    pub blocks: Option<Arc<ArrayQueue<SubscribeUpdateBlock>>>,
+    pub transactions_status: Option<Arc<ArrayQueue<SubscribeUpdateTransactionStatus>>>,
+    pub entries: Option<Arc<ArrayQueue<SubscribeUpdateEntry>>>,
+    /// Transactions and event batches dropped while the decoder or the consumer of
+    /// `result_queue` was behind
+    pub dropped: Arc<AtomicU64>,
 }
+
+fn force_push<T>(queue: &Option<Arc<ArrayQueue<T>>>, update: T) {
+    if let Some(queue) = queue {
+        queue.force_push(update);
+    }
+}
+
+impl UpdateQueues {
+    /// Routes an update to the queue of its kind, transactions go to `transactions`
+    fn route(&self, update: UpdateOneof, transactions: &ArrayQueue<SubscribeUpdateTransaction>) {
+        match update {
+            UpdateOneof::Account(x) => force_push(&self.accounts, x),
+            UpdateOneof::Slot(x) => force_push(&self.slots, x),
+            UpdateOneof::Transaction(x) => {
+                if transactions.push(x).is_err() {
+                    self.dropped.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+            UpdateOneof::TransactionStatus(x) => force_push(&self.transactions_status, x),
+            UpdateOneof::Block(x) => force_push(&self.blocks, x),
+            UpdateOneof::BlockMeta(x) => force_push(&self.blocks_meta, x),
+            UpdateOneof::Entry(x) => force_push(&self.entries, x),
+            // pings are answered by the subscription, pongs answer its keepalives
+            UpdateOneof::Ping(_) | UpdateOneof::Pong(_) => {}
+        }
+    }
+}
+
 pub async fn stream_events(
-    result_queue: Arc<ArrayQueue<Vec<MutEvents>>>,
+    result_queue: Arc<ArrayQueue<Vec<EventEnvelope>>>,
+    geyser: impl Stream<Item = Result<SubscribeUpdate, Status>>,
+) {
+    stream_events_with(result_queue, UpdateQueues::default(), geyser).await
+}
+
+/// Decodes the transactions into `result_queue` and routes the other updates to
+/// `queues`
+pub async fn stream_events_with(
+    result_queue: Arc<ArrayQueue<Vec<EventEnvelope>>>,
+    queues: UpdateQueues,
     geyser: impl Stream<Item = Result<SubscribeUpdate, Status>>,
 ) {
     pin_mut!(geyser);
     let queue = Arc::new(ArrayQueue::new(200));
 
-    for i in 0..1 {
-        thread::spawn({
-            let queue = queue.clone(); // or pass the original Arc here
-            let result_queue = result_queue.clone();
-            move || {
-                let res = listener(queue, result_queue);
-            }
-        });
-    }
+    thread::spawn({
+        let queue = queue.clone();
+        let dropped = queues.dropped.clone();
+        move || listener(queue, result_queue, dropped)
+    });
     while let Some(Ok(item)) = geyser.next().await {
-        match item.update_oneof {
-            Some(update) => match update {
-                UpdateOneof::Account(subscribe_update_account) => todo!(),
-                UpdateOneof::Slot(subscribe_update_slot) => todo!(),
-                UpdateOneof::Transaction(subscribe_update_transaction) => {
-                    let now = Instant::now();
-                    let res = queue.push(subscribe_update_transaction);
-                    println!("{:?}", res);
-                }
-                UpdateOneof::TransactionStatus(subscribe_update_transaction_status) => todo!(),
-                UpdateOneof::Block(subscribe_update_block) => todo!(),
-                UpdateOneof::Ping(subscribe_update_ping) => continue,
-                UpdateOneof::Pong(subscribe_update_pong) => todo!(),
-                UpdateOneof::BlockMeta(subscribe_update_block_meta) => todo!(),
-                UpdateOneof::Entry(subscribe_update_entry) => todo!(),
-            },
-            None => todo!(),
+        if let Some(update) = item.update_oneof {
+            queues.route(update, &queue);
         }
     }
 }
 
+/// Decodes the queued transactions, a batch `result_queue` has no room for is
+/// dropped and counted in `dropped`
 pub fn listener(
     queue: Arc<ArrayQueue<SubscribeUpdateTransaction>>,
-    result_queue: Arc<ArrayQueue<Vec<MutEvents>>>,
-) -> Result<(), ()> {
-    // let mut client = db::kv_store::get_kv_client().map_err(|_| ())?;
-    let id = std::thread::current().id();
+    result_queue: Arc<ArrayQueue<Vec<EventEnvelope>>>,
+    dropped: Arc<AtomicU64>,
+) {
     let mut stack: Vec<(String, u32)> = Vec::new();
     let mut program_data_list: Vec<ProgramData> = Vec::new();
     let mut carrier = Vec::new();
     loop {
-        let pop = queue.pop();
-        if pop.is_none() {
+        let Some(tx) = queue.pop() else {
             thread::sleep(Duration::from_millis(1));
             continue;
+        };
+        // a transaction that can't be decoded is dropped, the thread keeps serving the
+        // others
+        if transaction_update_matcher(tx, &mut carrier, &mut stack, &mut program_data_list).is_err()
+        {
+            carrier.clear();
         }
-        let res = transaction_update_matcher(
-            pop.ok_or(())?,
-            &mut carrier,
-            &mut stack,
-            &mut program_data_list,
-        )
-        .map_err(|_| ())?;
         stack.clear();
         program_data_list.clear();
-        if !carrier.is_empty() {
-            let as_bytes = serde_json::to_vec(&carrier).map_err(|_| ())?;
-            // println!("Publishing to redis");
-            // let i: () = client.publish("events", as_bytes).map_err(|_| ())?;
-            result_queue.push(carrier.clone());
-            carrier.clear();
-        } else {
-            println!("got empty");
+        if !carrier.is_empty() && result_queue.push(std::mem::take(&mut carrier)).is_err() {
+            dropped.fetch_add(1, Ordering::Relaxed);
         }
-        let pressure = queue.len();
     }
 }
 
 pub fn transaction_update_matcher(
     tx: SubscribeUpdateTransaction,
-    carrier: &mut Vec<MutEvents>,
+    carrier: &mut Vec<EventEnvelope>,
     stack: &mut Vec<(String, u32)>,
     program_data_list: &mut Vec<ProgramData>,
 ) -> Result<(), ()> {
     let tx_info = match tx.transaction {
         Some(x) => x,
-        None => todo!(),
+        // nothing to decode
+        None => return Err(()),
     };
     let signature_unp = Signature::try_from(tx_info.signature.as_slice()).map_err(|_| ())?;
+    let tx_index = tx_info.index;
+    // only set when the stream includes failed transactions
+    let failure = tx_info
+        .meta
+        .as_ref()
+        .and_then(|x| x.err.as_ref())
+        .map(|x| x.err.clone());
     let tx_with_meta = yellowstone_grpc_proto::convert_from::create_tx_with_meta(tx_info)
         .map_err(|_| ())?
         .encode(
@@ -158,162 +332,484 @@ pub fn transaction_update_matcher(
             false,
         )
         .map_err(|_| ())?;
-    let logs = tx_with_meta.meta.ok_or(())?.log_messages.ok_or(())?;
-    let events = parse_logs(
-        logs,
+    let context = TxContext {
+        slot: tx.slot,
+        tx_index,
+        signature: signature_unp.to_string(),
+        fee_payer: sol_tx::account_keys(&tx_with_meta).and_then(|x| x.first().copied()),
+    };
+    push_transaction_events(
+        tx_with_meta,
+        failure.as_deref(),
+        &context,
         carrier,
-        signature_unp.to_string(),
         stack,
         program_data_list,
-    );
-
-    // parse_events(tx_with_meta, signature_unp.to_string());
+    )
+}
 
+/// Decodes the events of a transaction, `failure` is its serialized error. The
+/// events of a transaction are followed by its `BalanceDelta`, failed transactions
+/// included as they still pay the fee. A transaction without any event adds nothing.
+fn push_transaction_events(
+    tx_with_meta: EncodedTransactionWithStatusMeta,
+    failure: Option<&[u8]>,
+    context: &TxContext,
+    carrier: &mut Vec<EventEnvelope>,
+    stack: &mut Vec<(String, u32)>,
+    program_data_list: &mut Vec<ProgramData>,
+) -> Result<(), ()> {
+    let instructions = sol_tx::flatten_instructions(&tx_with_meta).unwrap_or_default();
+    let balance_delta = TxBalanceDelta::from_tx(&tx_with_meta, &context.signature);
+    let emitted = carrier.len();
+    if let Some(error) = failure {
+        // the instructions still show what a failed transaction attempted
+        push_instruction_events(&instructions, carrier, context, true);
+        let account_keys = sol_tx::account_keys(&tx_with_meta).unwrap_or_default();
+        let outer_programs: Vec<Pubkey> = instructions
+            .iter()
+            .filter(|x| x.inner_index.is_none())
+            .map(|x| x.program_id)
+            .collect();
+        let logs = match tx_with_meta.meta.as_ref().map(|x| &x.log_messages) {
+            Some(OptionSerializer::Some(x)) => x.as_slice(),
+            _ => &[],
+        };
+        let failed = FailedTransaction::new(
+            context.signature.clone(),
+            error,
+            &account_keys,
+            &outer_programs,
+            logs,
+        );
+        let instruction_index = failed.instruction_index.map(usize::from);
+        carrier.push(context.wrap(MutEvents::Failed(failed), instruction_index, None, None));
+    } else {
+        let logs = tx_with_meta.meta.ok_or(())?.log_messages.ok_or(())?;
+        parse_logs(
+            logs,
+            &instructions,
+            carrier,
+            context,
+            stack,
+            program_data_list,
+        );
+        push_instruction_events(&instructions, carrier, context, false);
+    }
+    if let Some(balance_delta) = balance_delta.filter(|_| carrier.len() > emitted) {
+        carrier.push(context.wrap(MutEvents::BalanceDelta(balance_delta), None, None, None));
+    }
     Ok(())
 }
 
+/// The token flows of a `reverted` transaction never happened, only its platform
+/// instructions are kept
+fn push_instruction_events(
+    instructions: &[FlatInstruction],
+    carrier: &mut Vec<EventEnvelope>,
+    context: &TxContext,
+    reverted: bool,
+) {
+    for (position, ix) in instructions.iter().enumerate() {
+        let event = decode_instruction(instructions, position, &context.signature);
+        if let Some(event) =
+            event.filter(|x| !reverted || matches!(x, MutEvents::InstructionEvent(_)))
+        {
+            carrier.push(context.wrap(
+                event,
+                Some(ix.index),
+                ix.inner_index,
+                Some(ix.stack_height),
+            ));
+        }
+    }
+}
+
 fn parse_logs(
     logs: Vec<String>,
-    carrier: &mut Vec<MutEvents>,
-    signature: String,
+    instructions: &[FlatInstruction],
+    carrier: &mut Vec<EventEnvelope>,
+    context: &TxContext,
     stack: &mut Vec<(String, u32)>,
     program_data_list: &mut Vec<ProgramData>,
-) -> Result<(), ()> {
+) {
     let parse = parse_solana_logs(logs, stack, program_data_list);
-    for ProgramData { program_id, data } in parse.iter() {
+    // every CPMM swap logs one event, in execution order
+    let mut cpmm_swaps = instructions.iter().filter(|x| {
+        x.program_id == cpmm::RAYDIUM_CPMM_PROGRAM
+            && (x.data.starts_with(cpmm::SWAP_BASE_IN_DISCRIMINATOR)
+                || x.data.starts_with(cpmm::SWAP_BASE_OUT_DISCRIMINATOR))
+    });
+    for ProgramData {
+        program_id,
+        data,
+        instruction_index,
+        stack_height,
+    } in parse.iter()
+    {
         let decoded = match BASE64_STANDARD.decode(data) {
             Ok(x) => x,
-            Err(x) => {
+            Err(_) => {
                 continue;
             }
         };
-        match &program_id.as_str() {
-            &PUMPFUN_ADDRESS => match &decoded[..8] {
-                [27, 114, 169, 77, 222, 235, 99, 118] => {
-                    let mut res = PumpFunCreateEvent::try_from_slice(&decoded[8..])
-                        .map_err(|x| ())
-                        .ok()
-                        .ok_or(())?;
-                    res.signature = signature.clone();
-                    carrier.push(MutEvents::CreateEvent(CreateEvent::PumpFunCreate(res)))
-                }
-                [189, 219, 127, 211, 78, 230, 97, 238] => {
-                    let mut res = match PumpFunTradeEvent::try_from_slice(&decoded[8..]) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            return Err(());
-                        }
-                    };
-                    res.signature = signature.clone();
-                    carrier.push(MutEvents::TradeEvent(TradeEvent::PumpFunTrade(res)))
-                }
-                _ => (),
-            },
-            &RAYDIUM_CLMM_ADDRESS => match &decoded[..8] {
-                [64, 198, 205, 232, 38, 8, 113, 226] => {
-                    let mut res = match RaydiumClmmSwapEvent::try_from_slice(&decoded[8..]) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            return Err(());
-                        }
-                    };
-                    res.signature = signature.clone();
-                    carrier.push(MutEvents::TradeEvent(TradeEvent::RaydiumClmmTrade(res)))
-                }
-                _ => (),
-            },
-            &RAYDIUM_CPMM => match &decoded[..8] {
-                [64, 198, 205, 232, 38, 8, 113, 226] => {
-                    let mut res = match RaydiumCpmmSwapEvent::try_from_slice(&decoded[8..]) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            return Err(());
-                        }
-                    };
-                    res.signature = signature.clone();
-                    carrier.push(MutEvents::TradeEvent(TradeEvent::RaydiumCpmmTrade(res)))
-                }
-                _ => (),
-            },
+        let emitter_accounts =
+            if program_id == RAYDIUM_CPMM && decoded.starts_with(&cpmm::SWAP_EVENT_DISCRIMINATOR) {
+                cpmm_swaps.next().map(|x| x.accounts.as_slice())
+            } else {
+                None
+            };
+        // an event failing to decode is skipped like undecodable data
+        if let Ok(Some(event)) =
+            event_handler(program_id, &decoded, &context.signature, emitter_accounts)
+        {
+            carrier.push(context.wrap(event, *instruction_index, None, *stack_height))
+        }
+    }
+}
 
-            &ORCA_ADDRESS => match &decoded[..8] {
-                [225, 202, 73, 175, 147, 43, 160, 150] => {
-                    let mut res = match Traded::try_from_slice(&decoded[8..]) {
-                        Ok(x) => x,
-                        Err(e) => {
-                            return Err(());
-                        }
-                    };
-                    res.signature = signature.clone();
-                    carrier.push(MutEvents::TradeEvent(TradeEvent::OrcaTrade(res)))
+/// Decodes an anchor event, or a `ray_log` record for AMM v4, emitted by `program_id`.
+/// `emitter_accounts` are the accounts of the instruction that emitted it, when known.
+pub fn event_handler(
+    program_id: &str,
+    decoded: &[u8],
+    signature: &str,
+    emitter_accounts: Option<&[Pubkey]>,
+) -> Result<Option<MutEvents>, ()> {
+    if decoded.len() < 8 {
+        return Ok(None);
+    }
+    match program_id {
+        PUMPFUN_ADDRESS => match &decoded[..8] {
+            [27, 114, 169, 77, 222, 235, 99, 118] => {
+                let mut res = PumpFunCreateEvent::try_from_slice(&decoded[8..])
+                    .map_err(|_| ())
+                    .ok()
+                    .ok_or(())?;
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::CreateEvent(CreateEvent::PumpFunCreate(
+                    res,
+                ))));
+            }
+            [189, 219, 127, 211, 78, 230, 97, 238] => {
+                let mut res = match PumpFunTradeEvent::try_from_slice(&decoded[8..]) {
+                    Ok(x) => x,
+                    Err(_) => {
+                        return Err(());
+                    }
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::TradeEvent(TradeEvent::PumpFunTrade(res))));
+            }
+            _ => (),
+        },
+        RAYDIUM_CLMM_ADDRESS if decoded.starts_with(&[64, 198, 205, 232, 38, 8, 113, 226]) => {
+            let mut res = match RaydiumClmmSwapEvent::try_from_slice(&decoded[8..]) {
+                Ok(x) => x,
+                Err(_) => {
+                    return Err(());
                 }
-                _ => (),
-            },
+            };
+            res.signature = signature.to_string();
+            return Ok(Some(MutEvents::TradeEvent(TradeEvent::RaydiumClmmTrade(
+                res,
+            ))));
+        }
+        RAYDIUM_CPMM if decoded.starts_with(&cpmm::SWAP_EVENT_DISCRIMINATOR) => {
+            let Ok(mut res) = RaydiumCpmmSwapEvent::decode(&decoded[8..]) else {
+                return Ok(None);
+            };
+            res.signature = signature.to_string();
+            let Some(res) = RaydiumParsedCpmmEvent::new(res, emitter_accounts) else {
+                return Ok(None);
+            };
+            return Ok(Some(MutEvents::TradeEvent(TradeEvent::RaydiumCpmmTrade(
+                res,
+            ))));
+        }
+        PUMPSWAP_ADDRESS => match <[u8; 8]>::try_from(&decoded[..8]).unwrap_or_default() {
+            pump_swap::BUY_EVENT_DISCRIMINATOR => {
+                let Ok(mut res) = pump_swap::decode_event::<PumpSwapBuyEvent>(&decoded[8..]) else {
+                    return Ok(None);
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::TradeEvent(TradeEvent::PumpSwapTrade(
+                    PumpSwapTradeEvent::Buy(res),
+                ))));
+            }
+            pump_swap::SELL_EVENT_DISCRIMINATOR => {
+                let Ok(mut res) = pump_swap::decode_event::<PumpSwapSellEvent>(&decoded[8..])
+                else {
+                    return Ok(None);
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::TradeEvent(TradeEvent::PumpSwapTrade(
+                    PumpSwapTradeEvent::Sell(res),
+                ))));
+            }
+            pump_swap::CREATE_POOL_EVENT_DISCRIMINATOR => {
+                let Ok(mut res) = pump_swap::decode_event::<PumpSwapCreatePoolEvent>(&decoded[8..])
+                else {
+                    return Ok(None);
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::CreateEvent(
+                    CreateEvent::PumpSwapPoolCreate(res),
+                )));
+            }
+            pump_swap::DEPOSIT_EVENT_DISCRIMINATOR => {
+                let Ok(mut res) = pump_swap::decode_event::<PumpSwapDepositEvent>(&decoded[8..])
+                else {
+                    return Ok(None);
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::LiquidityEvent(
+                    LiquidityEvent::PumpSwapDeposit(res),
+                )));
+            }
+            pump_swap::WITHDRAW_EVENT_DISCRIMINATOR => {
+                let Ok(mut res) = pump_swap::decode_event::<PumpSwapWithdrawEvent>(&decoded[8..])
+                else {
+                    return Ok(None);
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::LiquidityEvent(
+                    LiquidityEvent::PumpSwapWithdraw(res),
+                )));
+            }
+            _ => (),
+        },
+        // AMM v4 predates anchor, its records come from `ray_log` lines
+        RAYDIUM_AMM_V4_ADDRESS => {
+            if let Some(mut res) = RaydiumAmmV4SwapEvent::from_ray_log(decoded) {
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::TradeEvent(TradeEvent::RaydiumAmmV4Trade(
+                    res,
+                ))));
+            }
+        }
+        METEORA_DLMM_ADDRESS if decoded.starts_with(&meteora_dlmm::SWAP_EVENT_DISCRIMINATOR) => {
+            let Ok(mut res) = DlmmSwapEvent::try_from_slice(&decoded[8..]) else {
+                return Ok(None);
+            };
+            res.signature = signature.to_string();
+            return Ok(Some(MutEvents::TradeEvent(TradeEvent::MeteoraDlmmTrade(
+                res,
+            ))));
+        }
+        METEORA_DAMM_V1_ADDRESS if decoded.starts_with(&damm_v1::SWAP_EVENT_DISCRIMINATOR) => {
+            let Ok(mut res) = DammV1SwapEvent::try_from_slice(&decoded[8..]) else {
+                return Ok(None);
+            };
+            res.signature = signature.to_string();
+            return Ok(Some(MutEvents::TradeEvent(TradeEvent::MeteoraDammV1Trade(
+                res,
+            ))));
+        }
+        METEORA_DAMM_V2_ADDRESS => match <[u8; 8]>::try_from(&decoded[..8]).unwrap_or_default() {
+            damm_v2::SWAP_EVENT_DISCRIMINATOR => {
+                let Ok(mut res) =
+                    <DammV2SwapEvent as BorshDeserialize>::deserialize(&mut &decoded[8..])
+                else {
+                    return Ok(None);
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::TradeEvent(TradeEvent::MeteoraDammV2Trade(
+                    DammV2TradeEvent::Swap(res),
+                ))));
+            }
+            damm_v2::SWAP2_EVENT_DISCRIMINATOR => {
+                let Ok(mut res) =
+                    <DammV2Swap2Event as BorshDeserialize>::deserialize(&mut &decoded[8..])
+                else {
+                    return Ok(None);
+                };
+                res.signature = signature.to_string();
+                return Ok(Some(MutEvents::TradeEvent(TradeEvent::MeteoraDammV2Trade(
+                    DammV2TradeEvent::Swap2(res),
+                ))));
+            }
+            _ => (),
+        },
 
-            _ => {}
+        ORCA_ADDRESS if decoded.starts_with(&[225, 202, 73, 175, 147, 43, 160, 150]) => {
+            let mut res = match Traded::try_from_slice(&decoded[8..]) {
+                Ok(x) => x,
+                Err(_) => {
+                    return Err(());
+                }
+            };
+            res.signature = signature.to_string();
+            return Ok(Some(MutEvents::TradeEvent(TradeEvent::OrcaTrade(res))));
         }
+
+        _ => {}
     }
+    Ok(None)
+}
 
-    println!("Returning with a len of {}", carrier.len());
+/// Decodes every instruction of a transaction, outer and inner, through the
+/// platform decoders
+pub fn parse_instructions(
+    tx_encoded: &EncodedTransactionWithStatusMeta,
+    signature: &str,
+) -> Result<Vec<MutEvents>, ()> {
+    let instructions = sol_tx::flatten_instructions(tx_encoded).ok_or(())?;
+    Ok(decode_instructions(&instructions, signature))
+}
 
-    // panic!();
-    Ok(())
+/// Instructions that fail to decode are skipped
+pub fn decode_instructions(instructions: &[FlatInstruction], signature: &str) -> Vec<MutEvents> {
+    (0..instructions.len())
+        .filter_map(|position| decode_instruction(instructions, position, signature))
+        .collect()
 }
 
-pub fn event_handler(decoded: Vec<u8>, signature: String) -> Result<MutEvents, ()> {
-    match &decoded[..8] {
-        _ => return Err(()),
+/// Decodes the instruction at `position`, or the event it carries when it is an
+/// `emit_cpi!` self CPI. Token flows are skipped when incidental, see
+/// `TokenFlow::is_incidental`.
+pub fn decode_instruction(
+    instructions: &[FlatInstruction],
+    position: usize,
+    signature: &str,
+) -> Option<MutEvents> {
+    let ix = instructions.get(position)?;
+    if let Some(parsed) = &ix.parsed {
+        return token_flow_event(ix, TokenFlow::from_parsed(parsed)?, signature);
     }
+    if ix.data.starts_with(&EVENT_IX_TAG) {
+        return decode_cpi_event(instructions, position, signature);
+    }
+    let event = match ix.program_id {
+        pump_fun::PUMPFUN => {
+            let instruction = PumpFunInstruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::PumpFun(PumpFunInstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        cpmm::RAYDIUM_CPMM_PROGRAM => {
+            let instruction = cpmm::parse_raydium_cpmm_ix(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::RaydiumCpmm(CpmmInstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        raydium_clmm::RAYDIUM_CLMM => {
+            let instruction = ClmmInstruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::RaydiumClmm(ClmmInstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        pump_swap::PUMPSWAP => {
+            let instruction = PumpSwapInstruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::PumpSwap(PumpSwapInstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        raydium_amm_v4::RAYDIUM_AMM_V4 => {
+            let instruction = AmmV4Instruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::RaydiumAmmV4(AmmV4InstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        meteora_dlmm::METEORA_DLMM => {
+            let instruction = DlmmInstruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::MeteoraDlmm(DlmmInstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        damm_v1::METEORA_DAMM_V1 => {
+            let instruction = DammV1Instruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::MeteoraDammV1(DammV1InstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        damm_v2::METEORA_DAMM_V2 => {
+            let instruction = DammV2Instruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::MeteoraDammV2(DammV2InstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        program_id if program_id == orca::whirlpools_program_id() => {
+            let instruction = WhirlpoolInstruction::decode(&ix.data, &ix.accounts).ok()?;
+            InstructionEvent::Orca(WhirlpoolInstructionEvent {
+                signature: signature.to_string(),
+                index: ix.index,
+                inner_index: ix.inner_index,
+                instruction,
+            })
+        }
+        // System, SPL Token, Token-2022 and ATA, the other programs decode to nothing
+        _ => {
+            let flow = TokenFlow::from_compiled(&ix.program_id, &ix.accounts, &ix.data)?;
+            return token_flow_event(ix, flow, signature);
+        }
+    };
+    Some(MutEvents::InstructionEvent(event))
 }
-pub fn parse_instructions(
-    tx_encoded: EncodedTransactionWithStatusMeta,
-    signature: String,
-) -> Result<Vec<MutEvents>, ()> {
-    let tx = tx_encoded.meta.ok_or(())?;
-    let ixs = tx.inner_instructions.ok_or(())?;
-
-    for inner_ixns in ixs {
-        let main_index = inner_ixns.index;
-        for (i, ix) in inner_ixns.instructions.into_iter().enumerate() {
-            match ix {
-                solana_transaction_status::UiInstruction::Parsed(ui_parsed_instruction) => {
-                    match ui_parsed_instruction {
-                        UiParsedInstruction::Parsed(parsed_instruction) => {
-                            todo!()
-                        }
-                        UiParsedInstruction::PartiallyDecoded(x) => {
-                            let program = x.program_id;
-
-                            if program
-                                .eq_ignore_ascii_case("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr")
-                            {
-                                panic!("got it");
-                            }
-                        }
-                    }
-                }
-                solana_transaction_status::UiInstruction::Compiled(ui_compiled_instruction) => {
-                    let versioned_tx = tx_encoded.transaction.decode().unwrap();
-                    let mut accounts = versioned_tx.message.static_account_keys().to_vec();
-                    let loaded_addresses = tx.loaded_addresses.clone().unwrap();
-                    for loaded_address in loaded_addresses.writable {
-                        accounts.push(Pubkey::from_str(&loaded_address).unwrap());
-                    }
-                    for loaded_address in loaded_addresses.readonly {
-                        accounts.push(Pubkey::from_str(&loaded_address).unwrap());
-                    }
-                    let program_id_index = ui_compiled_instruction.program_id_index;
-                    let program_id = accounts[program_id_index as usize];
 
-                    match program_id.to_string().as_str() {
-                        RAYDIUM_CLMM_ADDRESS => {}
-                        _ => {}
-                    }
-                }
-            }
-        }
+fn token_flow_event(ix: &FlatInstruction, flow: TokenFlow, signature: &str) -> Option<MutEvents> {
+    if flow.is_incidental() {
+        return None;
     }
-    Ok(vec![])
+    Some(MutEvents::TokenFlow(TokenFlowEvent {
+        signature: signature.to_string(),
+        index: ix.index,
+        inner_index: ix.inner_index,
+        flow,
+    }))
+}
+
+/// Decodes the event carried by the self CPI at `position`, for the programs in
+/// `EMIT_CPI_PROGRAMS`. The emitter is the closest preceding instruction of the
+/// same program one level up the stack.
+fn decode_cpi_event(
+    instructions: &[FlatInstruction],
+    position: usize,
+    signature: &str,
+) -> Option<MutEvents> {
+    let ix = &instructions[position];
+    let program_id = ix.program_id.to_string();
+    if !EMIT_CPI_PROGRAMS.contains(&program_id.as_str()) {
+        return None;
+    }
+    let emitter_accounts = instructions[..position]
+        .iter()
+        .rev()
+        .take_while(|x| x.index == ix.index)
+        .find(|x| x.program_id == ix.program_id && x.stack_height + 1 == ix.stack_height)
+        .map(|x| x.accounts.as_slice());
+    event_handler(
+        &program_id,
+        &ix.data[EVENT_IX_TAG.len()..],
+        signature,
+        emitter_accounts,
+    )
+    .ok()
+    .flatten()
 }
 
 fn parse_solana_logs<'a>(
@@ -321,9 +817,14 @@ fn parse_solana_logs<'a>(
     stack: &'a mut Vec<(String, u32)>,
     program_data_list: &'a mut Vec<ProgramData>,
 ) -> &'a mut Vec<ProgramData> {
+    // every outer instruction starts with an invoke at depth 1
+    let mut instruction_index: Option<usize> = None;
     for log_line in logs {
         let trimmed = log_line.trim();
         if let Some((program_id, depth)) = parse_invoke(trimmed) {
+            if depth == 1 {
+                instruction_index = Some(instruction_index.map_or(0, |x| x + 1));
+            }
             // Push program with its depth to the stack (needed for tracking context)
             stack.push((program_id, depth));
         } else if let Some((program_id, _)) = parse_success(trimmed) {
@@ -331,19 +832,32 @@ fn parse_solana_logs<'a>(
             if let Some(pos) = stack.iter().rposition(|(pid, _)| *pid == program_id) {
                 stack.remove(pos);
             }
-        } else if trimmed.starts_with("Program data: ") {
+        } else if let Some(data_content) = trimmed.strip_prefix("Program data: ") {
             // Only capture Program data logs - attribute to the top program on stack
-            let data_content = &trimmed[14..]; // Remove "Program data: " prefix
 
-            if let Some((emitter, _)) = stack.last() {
+            if let Some((emitter, depth)) = stack.last() {
                 program_data_list.push(ProgramData {
                     program_id: emitter.clone(),
                     data: data_content.to_string(),
+                    instruction_index,
+                    stack_height: Some(*depth),
                 });
             } else {
                 program_data_list.push(ProgramData {
                     program_id: "UNKNOWN".to_string(),
                     data: data_content.to_string(),
+                    instruction_index,
+                    stack_height: None,
+                });
+            }
+        } else if let Some(data_content) = trimmed.strip_prefix(RAY_LOG_PREFIX) {
+            // Raydium AMM v4 logs its records instead of emitting anchor events
+            if let Some((emitter, depth)) = stack.last() {
+                program_data_list.push(ProgramData {
+                    program_id: emitter.clone(),
+                    data: data_content.to_string(),
+                    instruction_index,
+                    stack_height: Some(*depth),
                 });
             }
         }
@@ -360,12 +874,11 @@ fn parse_invoke(line: &str) -> Option<(String, u32)> {
         let program_id = line[prefix.len()..].split_whitespace().next()?.to_string();
 
         // Extract depth level from [x]
-        if let Some(start) = line.find('[') {
-            if let Some(end) = line.find(']') {
-                if let Ok(depth) = line[start + 1..end].parse::<u32>() {
-                    return Some((program_id, depth));
-                }
-            }
+        if let Some(start) = line.find('[')
+            && let Some(end) = line.find(']')
+            && let Ok(depth) = line[start + 1..end].parse::<u32>()
+        {
+            return Some((program_id, depth));
         }
         return Some((program_id, 1));
     }
@@ -382,3 +895,856 @@ fn parse_success(line: &str) -> Option<(String, u32)> {
     }
     None
 }
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::address_lookup_table::AddressLookupTableAccount;
+    use solana_sdk::hash::Hash;
+    use solana_sdk::instruction::{AccountMeta, Instruction, InstructionError};
+    use solana_sdk::message::{Message, VersionedMessage, v0};
+    use solana_sdk::transaction::{TransactionError, VersionedTransaction};
+    use solana_transaction_status::EncodedTransactionWithStatusMeta;
+
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::{DECODED_PROGRAMS, test_utils::accounts};
+
+    fn swap_event_data(
+        pool: Pubkey,
+        input_amount: u64,
+        output_amount: u64,
+        mints: Option<(Pubkey, Pubkey)>,
+    ) -> String {
+        let mut data = cpmm::SWAP_EVENT_DISCRIMINATOR.to_vec();
+        data.extend(pool.to_bytes());
+        for x in [1_000_000, 2_000_000, input_amount, output_amount, 0, 0] {
+            data.extend(u64::to_le_bytes(x));
+        }
+        data.push(1);
+        // later program versions append the mints and fees
+        if let Some((input_mint, output_mint)) = mints {
+            data.extend(input_mint.to_bytes());
+            data.extend(output_mint.to_bytes());
+            data.extend(u64::to_le_bytes(2_500));
+            data.extend(u64::to_le_bytes(0));
+            data.push(0);
+        }
+        BASE64_STANDARD.encode(data)
+    }
+
+    fn cpmm_ix(
+        index: usize,
+        inner_index: Option<usize>,
+        discriminator: &[u8],
+        accounts: Vec<Pubkey>,
+    ) -> FlatInstruction {
+        FlatInstruction {
+            index,
+            inner_index,
+            stack_height: if inner_index.is_some() { 2 } else { 1 },
+            program_id: cpmm::RAYDIUM_CPMM_PROGRAM,
+            accounts,
+            data: [discriminator, &[0; 16]].concat(),
+            parsed: None,
+        }
+    }
+
+    fn swap_accounts(
+        user: Pubkey,
+        pool: Pubkey,
+        input_mint: Pubkey,
+        output_mint: Pubkey,
+    ) -> Vec<Pubkey> {
+        let mut accounts = accounts(13);
+        accounts[0] = user;
+        accounts[3] = pool;
+        accounts[10] = input_mint;
+        accounts[11] = output_mint;
+        accounts
+    }
+
+    fn context() -> TxContext {
+        TxContext {
+            slot: 1,
+            tx_index: 0,
+            signature: "sig".to_string(),
+            fee_payer: None,
+        }
+    }
+
+    fn parse(logs: &[String], instructions: &[FlatInstruction]) -> Vec<EventEnvelope> {
+        let mut carrier = Vec::new();
+        parse_logs(
+            logs.to_vec(),
+            instructions,
+            &mut carrier,
+            &context(),
+            &mut Vec::new(),
+            &mut Vec::new(),
+        );
+        carrier
+    }
+
+    fn cpmm_trade(envelope: &EventEnvelope) -> &RaydiumParsedCpmmEvent {
+        match &envelope.event {
+            MutEvents::TradeEvent(TradeEvent::RaydiumCpmmTrade(x)) => x,
+            x => panic!("not a CPMM trade: {x:?}"),
+        }
+    }
+
+    fn pump_swap_buy() -> PumpSwapBuyEvent {
+        PumpSwapBuyEvent {
+            signature: String::new(),
+            timestamp: 1_750_000_000,
+            base_amount_out: 2_000_000_000,
+            max_quote_amount_in: 60_000_000,
+            user_base_token_reserves: 0,
+            user_quote_token_reserves: 1_000_000_000,
+            pool_base_token_reserves: 180_000_000_000_000,
+            pool_quote_token_reserves: 5_000_000_000_000,
+            quote_amount_in: 55_556_173,
+            lp_fee_basis_points: 20,
+            lp_fee: 111_113,
+            protocol_fee_basis_points: 5,
+            protocol_fee: 27_779,
+            quote_amount_in_with_lp_fee: 55_667_286,
+            user_quote_amount_in: 55_695_065,
+            pool: Pubkey::new_unique(),
+            user: Pubkey::new_unique(),
+            user_base_token_account: Pubkey::new_unique(),
+            user_quote_token_account: Pubkey::new_unique(),
+            protocol_fee_recipient: Pubkey::new_unique(),
+            protocol_fee_recipient_token_account: Pubkey::new_unique(),
+            coin_creator: Pubkey::new_unique(),
+            coin_creator_fee_basis_points: 5,
+            coin_creator_fee: 27_779,
+        }
+    }
+
+    #[test]
+    fn decodes_pump_swap_trades_from_their_logs() {
+        for (name, discriminator) in [
+            ("BuyEvent", pump_swap::BUY_EVENT_DISCRIMINATOR),
+            ("SellEvent", pump_swap::SELL_EVENT_DISCRIMINATOR),
+        ] {
+            let hash = solana_sdk::hash::hash(format!("event:{name}").as_bytes());
+            assert_eq!(hash.to_bytes()[..8], discriminator);
+        }
+
+        let buy = pump_swap_buy();
+        let mut data = pump_swap::BUY_EVENT_DISCRIMINATOR.to_vec();
+        data.extend(borsh::to_vec(&buy).unwrap());
+        // fields the program appended later are ignored
+        data.extend([1; 40]);
+        let logs = [
+            format!("Program {PUMPSWAP_ADDRESS} invoke [1]"),
+            format!("Program data: {}", BASE64_STANDARD.encode(&data)),
+            format!("Program {PUMPSWAP_ADDRESS} success"),
+        ];
+        let events = parse(&logs, &[]);
+        assert_eq!(events.len(), 1);
+        let MutEvents::TradeEvent(TradeEvent::PumpSwapTrade(PumpSwapTradeEvent::Buy(decoded))) =
+            &events[0].event
+        else {
+            panic!("not a PumpSwap buy: {:?}", events[0].event);
+        };
+        assert_eq!(decoded.signature, "sig");
+        assert_eq!(decoded.pool, buy.pool);
+        assert_eq!(decoded.user, buy.user);
+        assert_eq!(decoded.base_amount_out, 2_000_000_000);
+        assert_eq!(decoded.quote_amount_in, 55_556_173);
+        assert_eq!(decoded.user_quote_amount_in, 55_695_065);
+        assert_eq!(decoded.coin_creator_fee, 27_779);
+
+        // a sell shares the buy's layout, with the amounts going the other way
+        let mut data = pump_swap::SELL_EVENT_DISCRIMINATOR.to_vec();
+        data.extend(borsh::to_vec(&buy).unwrap());
+        let Some(MutEvents::TradeEvent(TradeEvent::PumpSwapTrade(PumpSwapTradeEvent::Sell(sell)))) =
+            event_handler(PUMPSWAP_ADDRESS, &data, "sig", None).unwrap()
+        else {
+            panic!("not a PumpSwap sell");
+        };
+        assert_eq!(sell.base_amount_in, 2_000_000_000);
+        assert_eq!(sell.min_quote_amount_out, 60_000_000);
+        assert_eq!(sell.quote_amount_out, 55_556_173);
+        assert_eq!(sell.user_quote_amount_out, 55_695_065);
+        assert_eq!(sell.user, buy.user);
+
+        // a truncated event is skipped, not an error
+        data.truncate(100);
+        assert!(
+            event_handler(PUMPSWAP_ADDRESS, &data, "sig", None)
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    fn ray_log(fields: [u64; 7], log_type: raydium_amm_v4::LogType) -> String {
+        let mut data = vec![log_type as u8];
+        for x in fields {
+            data.extend(u64::to_le_bytes(x));
+        }
+        format!("{RAY_LOG_PREFIX}{}", BASE64_STANDARD.encode(data))
+    }
+
+    #[test]
+    fn decodes_ray_log_swaps_at_any_depth() {
+        let router = Pubkey::new_unique();
+        let logs = [
+            // a direct swap_base_in selling 1 coin, the amounts follow the program's
+            // constant product with its 0.25% trade fee
+            format!("Program {RAYDIUM_AMM_V4_ADDRESS} invoke [1]"),
+            ray_log(
+                [
+                    1_000_000_000,
+                    148_000_000,
+                    2,
+                    3_000_000_000,
+                    90_000_000_000_000,
+                    13_500_000_000_000,
+                    149_623_341,
+                ],
+                raydium_amm_v4::LogType::SwapBaseIn,
+            ),
+            format!("Program {RAYDIUM_AMM_V4_ADDRESS} consumed 31200 of 200000 compute units"),
+            format!("Program {RAYDIUM_AMM_V4_ADDRESS} success"),
+            // a swap_base_out routed through another program, 1 SOL of coin bought
+            format!("Program {router} invoke [1]"),
+            "Program log: Instruction: Route".to_string(),
+            format!("Program {RAYDIUM_AMM_V4_ADDRESS} invoke [2]"),
+            ray_log(
+                [
+                    152_000_000,
+                    1_000_000_000,
+                    1,
+                    500_000_000,
+                    90_000_000_000_000,
+                    13_500_000_000_000,
+                    150_377_612,
+                ],
+                raydium_amm_v4::LogType::SwapBaseOut,
+            ),
+            format!("Program {RAYDIUM_AMM_V4_ADDRESS} success"),
+            format!("Program {router} success"),
+        ];
+        let (mut stack, mut data) = (Vec::new(), Vec::new());
+        let records = parse_solana_logs(logs.to_vec(), &mut stack, &mut data);
+        assert_eq!(records.len(), 2);
+        assert!(
+            records
+                .iter()
+                .all(|x| x.program_id == RAYDIUM_AMM_V4_ADDRESS)
+        );
+        assert_eq!(
+            records
+                .iter()
+                .map(|x| (x.instruction_index, x.stack_height))
+                .collect::<Vec<_>>(),
+            [(Some(0), Some(1)), (Some(1), Some(2))]
+        );
+
+        let events = parse(&logs, &[]);
+        assert_eq!(events.len(), 2);
+        let swaps: Vec<&RaydiumAmmV4SwapEvent> = events
+            .iter()
+            .map(|x| match &x.event {
+                MutEvents::TradeEvent(TradeEvent::RaydiumAmmV4Trade(x)) => x,
+                x => panic!("not an AMM v4 trade: {x:?}"),
+            })
+            .collect();
+        assert!(swaps[0].base_input && swaps[0].coin_to_pc);
+        assert_eq!(
+            (swaps[0].amount_in, swaps[0].amount_out),
+            (1_000_000_000, 149_623_341)
+        );
+        assert_eq!(swaps[0].limit, 148_000_000);
+        assert_eq!(
+            (swaps[0].pool_coin, swaps[0].pool_pc),
+            (90_000_000_000_000, 13_500_000_000_000)
+        );
+        assert_eq!(swaps[0].signature, "sig");
+        // base out logs what it took from the user as `deduct_in`
+        assert!(!swaps[1].base_input && !swaps[1].coin_to_pc);
+        assert_eq!(
+            (swaps[1].amount_in, swaps[1].amount_out),
+            (150_377_612, 1_000_000_000)
+        );
+        assert_eq!(swaps[1].limit, 152_000_000);
+        assert_eq!(swaps[1].user_source, 500_000_000);
+    }
+
+    #[test]
+    fn attributes_the_legacy_event_from_its_instruction() {
+        let (user, pool, input_mint, output_mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let instructions = [cpmm_ix(
+            0,
+            None,
+            cpmm::SWAP_BASE_IN_DISCRIMINATOR,
+            swap_accounts(user, pool, input_mint, output_mint),
+        )];
+        let logs = [
+            format!("Program {RAYDIUM_CPMM} invoke [1]"),
+            format!("Program data: {}", swap_event_data(pool, 500, 400, None)),
+            format!("Program {RAYDIUM_CPMM} success"),
+        ];
+        let events = parse(&logs, &instructions);
+        assert_eq!(events.len(), 1);
+        let trade = cpmm_trade(&events[0]);
+        assert_eq!(trade.user, user);
+        assert_eq!(trade.pool_state, pool);
+        assert_eq!(trade.input_mint, input_mint);
+        assert_eq!(trade.output_mint, output_mint);
+        assert_eq!((trade.input_amount, trade.output_amount), (500, 400));
+        assert_eq!(trade.signature, "sig");
+        assert!(trade.event.extension.is_none());
+
+        // without its instruction the legacy layout carries no mints
+        assert!(parse(&logs, &[]).is_empty());
+    }
+
+    /// A confirmed transaction as an RPC node returns it, base64 encoded
+    fn confirmed_tx(
+        message: VersionedMessage,
+        loaded: (&[Pubkey], &[Pubkey]),
+        logs: &[String],
+    ) -> EncodedTransactionWithStatusMeta {
+        let tx = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message,
+        };
+        let to_strings = |keys: &[Pubkey]| keys.iter().map(|x| x.to_string()).collect::<Vec<_>>();
+        serde_json::from_value(serde_json::json!({
+            "transaction": [BASE64_STANDARD.encode(bincode::serialize(&tx).unwrap()), "base64"],
+            "meta": {
+                "err": null,
+                "status": { "Ok": null },
+                "fee": 5000,
+                "preBalances": [],
+                "postBalances": [],
+                "innerInstructions": [],
+                "logMessages": logs,
+                "preTokenBalances": [],
+                "postTokenBalances": [],
+                "rewards": [],
+                "loadedAddresses": { "writable": to_strings(loaded.0), "readonly": to_strings(loaded.1) },
+            },
+        }))
+        .unwrap()
+    }
+
+    /// A CPMM `swap_base_input` of 1 SOL for at least 149 USDC
+    fn swap_ix(keys: &[Pubkey]) -> Instruction {
+        let metas = keys
+            .iter()
+            .enumerate()
+            .map(|(i, x)| match i {
+                0 => AccountMeta::new(*x, true),
+                3..=7 | 12 => AccountMeta::new(*x, false),
+                _ => AccountMeta::new_readonly(*x, false),
+            })
+            .collect();
+        Instruction::new_with_bytes(
+            cpmm::RAYDIUM_CPMM_PROGRAM,
+            &cpmm::RaydiumBuyBaseIn {
+                amount_in: 1_000_000_000,
+                minimum_amount_out: 149_000_000,
+            }
+            .data(),
+            metas,
+        )
+    }
+
+    fn swap_logs(pool: Pubkey, mints: Option<(Pubkey, Pubkey)>) -> [String; 3] {
+        [
+            format!("Program {RAYDIUM_CPMM} invoke [1]"),
+            format!(
+                "Program data: {}",
+                swap_event_data(pool, 1_000_000_000, 149_625_000, mints)
+            ),
+            format!("Program {RAYDIUM_CPMM} success"),
+        ]
+    }
+
+    #[test]
+    fn decodes_cpmm_swaps_out_of_legacy_and_v0_transactions() {
+        let (user, pool, sol, usdc) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let keys = swap_accounts(user, pool, sol, usdc);
+        let swap = swap_ix(&keys);
+        let logs = |mints| swap_logs(pool, mints);
+
+        // a legacy transaction logging the event without the mints
+        let tx = confirmed_tx(
+            VersionedMessage::Legacy(Message::new(std::slice::from_ref(&swap), Some(&user))),
+            (&[], &[]),
+            &logs(None),
+        );
+        let instructions = sol_tx::flatten_instructions(&tx).unwrap();
+        let events = parse(&logs(None), &instructions);
+        assert_eq!(events.len(), 1);
+        let trade = cpmm_trade(&events[0]);
+        assert_eq!((trade.user, trade.pool_state), (user, pool));
+        assert_eq!((trade.input_mint, trade.output_mint), (sol, usdc));
+        assert_eq!(
+            (trade.input_amount, trade.output_amount),
+            (1_000_000_000, 149_625_000)
+        );
+        assert!(trade.event.base_input);
+        assert!(trade.event.extension.is_none());
+
+        // a v0 transaction loading the vaults, programs and mints from a lookup
+        // table, logging the extended event
+        let table = AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: keys[6..13].to_vec(),
+        };
+        let message = v0::Message::try_compile(
+            &user,
+            &[swap],
+            std::slice::from_ref(&table),
+            Hash::default(),
+        )
+        .unwrap();
+        let lookup = &message.address_table_lookups[0];
+        let loaded = |indexes: &[u8]| -> Vec<Pubkey> {
+            indexes
+                .iter()
+                .map(|x| table.addresses[*x as usize])
+                .collect()
+        };
+        let (writable, readonly) = (
+            loaded(&lookup.writable_indexes),
+            loaded(&lookup.readonly_indexes),
+        );
+        assert!(!writable.is_empty() && !readonly.is_empty());
+        let tx = confirmed_tx(
+            VersionedMessage::V0(message),
+            (&writable, &readonly),
+            &logs(Some((sol, usdc))),
+        );
+        let instructions = sol_tx::flatten_instructions(&tx).unwrap();
+        assert_eq!(instructions[0].accounts, keys);
+        let events = parse(&logs(Some((sol, usdc))), &instructions);
+        assert_eq!(events.len(), 1);
+        let trade = cpmm_trade(&events[0]);
+        assert_eq!((trade.user, trade.pool_state), (user, pool));
+        assert_eq!((trade.input_mint, trade.output_mint), (sol, usdc));
+        assert_eq!(
+            (trade.input_amount, trade.output_amount),
+            (1_000_000_000, 149_625_000)
+        );
+        let extension = trade.event.extension.as_ref().unwrap();
+        assert_eq!((extension.input_mint, extension.output_mint), (sol, usdc));
+    }
+
+    #[test]
+    fn every_batch_ends_with_the_balance_delta() {
+        let (user, pool, sol, usdc) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let keys = swap_accounts(user, pool, sol, usdc);
+        let message = Message::new(&[swap_ix(&keys)], Some(&user));
+        let mut tx = confirmed_tx(
+            VersionedMessage::Legacy(message.clone()),
+            (&[], &[]),
+            &swap_logs(pool, None),
+        );
+        let meta = tx.meta.as_mut().unwrap();
+        // the user pays the fee, every other account is unchanged
+        meta.pre_balances = vec![1_000_000_000; message.account_keys.len()];
+        meta.post_balances = meta.pre_balances.clone();
+        meta.post_balances[0] -= 5_000;
+        let events = |failure: Option<&[u8]>| {
+            let mut carrier = Vec::new();
+            push_transaction_events(
+                tx.clone(),
+                failure,
+                &context(),
+                &mut carrier,
+                &mut Vec::new(),
+                &mut Vec::new(),
+            )
+            .unwrap();
+            carrier
+        };
+        let is_fee = |envelope: &EventEnvelope| match &envelope.event {
+            MutEvents::BalanceDelta(delta) => {
+                delta.sol.len() == 1
+                    && (delta.sol[0].owner, delta.sol[0].lamports) == (user, -5_000)
+            }
+            _ => false,
+        };
+
+        let succeeded = events(None);
+        assert!(matches!(
+            succeeded[0].event,
+            MutEvents::TradeEvent(TradeEvent::RaydiumCpmmTrade(_))
+        ));
+        assert!(is_fee(succeeded.last().unwrap()));
+        assert_eq!(
+            succeeded
+                .iter()
+                .filter(|x| matches!(x.event, MutEvents::BalanceDelta(_)))
+                .count(),
+            1
+        );
+
+        // a failed transaction still pays its fee
+        let error = bincode::serialize(&TransactionError::InstructionError(
+            0,
+            InstructionError::Custom(6005),
+        ))
+        .unwrap();
+        let failed = events(Some(&error));
+        let [.., failure, delta] = failed.as_slice() else {
+            panic!("{failed:?}");
+        };
+        assert!(matches!(failure.event, MutEvents::Failed(_)));
+        assert!(is_fee(delta));
+
+        // a transaction without any event emits no batch, not even its delta
+        let other = Instruction::new_with_bytes(Pubkey::new_unique(), &[1], vec![]);
+        let mut tx = confirmed_tx(
+            VersionedMessage::Legacy(Message::new(&[other], Some(&user))),
+            (&[], &[]),
+            &[],
+        );
+        let meta = tx.meta.as_mut().unwrap();
+        meta.pre_balances = vec![1_000_000_000, 1];
+        meta.post_balances = vec![999_995_000, 1];
+        let mut carrier = Vec::new();
+        push_transaction_events(
+            tx,
+            None,
+            &context(),
+            &mut carrier,
+            &mut Vec::new(),
+            &mut Vec::new(),
+        )
+        .unwrap();
+        assert!(carrier.is_empty());
+    }
+
+    #[test]
+    fn falls_back_to_the_extended_event() {
+        let (pool, input_mint, output_mint) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let logs = [
+            format!("Program {RAYDIUM_CPMM} invoke [1]"),
+            format!(
+                "Program data: {}",
+                swap_event_data(pool, 500, 400, Some((input_mint, output_mint)))
+            ),
+            format!("Program {RAYDIUM_CPMM} success"),
+        ];
+        let events = parse(&logs, &[]);
+        assert_eq!(events.len(), 1);
+        let trade = cpmm_trade(&events[0]);
+        assert_eq!(trade.user, Pubkey::default());
+        assert_eq!(trade.pool_state, pool);
+        assert_eq!(trade.input_mint, input_mint);
+        assert_eq!(trade.output_mint, output_mint);
+        let extension = trade.event.extension.as_ref().unwrap();
+        assert_eq!(extension.trade_fee, 2_500);
+    }
+
+    #[test]
+    fn pairs_each_event_with_the_swap_emitting_it() {
+        let router = Pubkey::new_unique();
+        let (user, pool_a, pool_b) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let (sol, usdc, bonk) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let instructions = [
+            // a direct swap, then a router going through a deposit and a base out swap
+            cpmm_ix(
+                0,
+                None,
+                cpmm::SWAP_BASE_IN_DISCRIMINATOR,
+                swap_accounts(user, pool_a, sol, usdc),
+            ),
+            FlatInstruction {
+                index: 1,
+                inner_index: None,
+                stack_height: 1,
+                program_id: router,
+                accounts: vec![user],
+                data: vec![1],
+                parsed: None,
+            },
+            cpmm_ix(1, Some(0), cpmm::DEPOSIT_DISCRIMINATOR, accounts(13)),
+            cpmm_ix(
+                1,
+                Some(1),
+                cpmm::SWAP_BASE_OUT_DISCRIMINATOR,
+                swap_accounts(router, pool_b, usdc, bonk),
+            ),
+        ];
+        let logs = [
+            format!("Program {RAYDIUM_CPMM} invoke [1]"),
+            format!(
+                "Program data: {}",
+                swap_event_data(pool_a, 1_000, 150, None)
+            ),
+            format!("Program {RAYDIUM_CPMM} success"),
+            format!("Program {router} invoke [1]"),
+            format!("Program {RAYDIUM_CPMM} invoke [2]"),
+            format!("Program {RAYDIUM_CPMM} success"),
+            format!("Program {RAYDIUM_CPMM} invoke [2]"),
+            format!(
+                "Program data: {}",
+                swap_event_data(pool_b, 150, 9_000, Some((usdc, bonk)))
+            ),
+            format!("Program {RAYDIUM_CPMM} success"),
+            format!("Program {router} success"),
+        ];
+        let events = parse(&logs, &instructions);
+        assert_eq!(events.len(), 2);
+
+        let first = cpmm_trade(&events[0]);
+        assert_eq!(events[0].instruction_index, Some(0));
+        assert_eq!(events[0].stack_height, Some(1));
+        assert_eq!(first.user, user);
+        assert_eq!(first.pool_state, pool_a);
+        assert_eq!((first.input_mint, first.output_mint), (sol, usdc));
+        assert_eq!((first.input_amount, first.output_amount), (1_000, 150));
+
+        // the deposit logs no event and takes no turn in the pairing
+        let second = cpmm_trade(&events[1]);
+        assert_eq!(events[1].instruction_index, Some(1));
+        assert_eq!(events[1].stack_height, Some(2));
+        assert_eq!(second.user, router);
+        assert_eq!(second.pool_state, pool_b);
+        assert_eq!((second.input_mint, second.output_mint), (usdc, bonk));
+        assert_eq!((second.input_amount, second.output_amount), (150, 9_000));
+    }
+
+    #[test]
+    fn routes_each_update_kind_to_its_queue() {
+        let queues = UpdateQueues {
+            accounts: Some(Arc::new(ArrayQueue::new(1))),
+            slots: Some(Arc::new(ArrayQueue::new(1))),
+            blocks_meta: Some(Arc::new(ArrayQueue::new(1))),
+            blocks: Some(Arc::new(ArrayQueue::new(1))),
+            transactions_status: Some(Arc::new(ArrayQueue::new(1))),
+            entries: Some(Arc::new(ArrayQueue::new(1))),
+            ..Default::default()
+        };
+        let transactions = ArrayQueue::new(1);
+        let account = |slot| {
+            UpdateOneof::Account(SubscribeUpdateAccount {
+                slot,
+                ..Default::default()
+            })
+        };
+        let transaction = |slot| {
+            UpdateOneof::Transaction(SubscribeUpdateTransaction {
+                slot,
+                ..Default::default()
+            })
+        };
+        for update in [
+            account(1),
+            UpdateOneof::Slot(SubscribeUpdateSlot {
+                slot: 1,
+                ..Default::default()
+            }),
+            UpdateOneof::BlockMeta(SubscribeUpdateBlockMeta {
+                slot: 1,
+                ..Default::default()
+            }),
+            UpdateOneof::Block(SubscribeUpdateBlock {
+                slot: 1,
+                ..Default::default()
+            }),
+            UpdateOneof::TransactionStatus(SubscribeUpdateTransactionStatus {
+                slot: 1,
+                ..Default::default()
+            }),
+            UpdateOneof::Entry(SubscribeUpdateEntry {
+                slot: 1,
+                ..Default::default()
+            }),
+            transaction(1),
+            UpdateOneof::Ping(Default::default()),
+            UpdateOneof::Pong(Default::default()),
+        ] {
+            queues.route(update, &transactions);
+        }
+        fn pop_one<T>(queue: &Option<Arc<ArrayQueue<T>>>) -> T {
+            let queue = queue.as_ref().unwrap();
+            assert_eq!(queue.len(), 1);
+            queue.pop().unwrap()
+        }
+        assert_eq!(pop_one(&queues.accounts).slot, 1);
+        assert_eq!(pop_one(&queues.slots).slot, 1);
+        assert_eq!(pop_one(&queues.blocks_meta).slot, 1);
+        assert_eq!(pop_one(&queues.blocks).slot, 1);
+        assert_eq!(pop_one(&queues.transactions_status).slot, 1);
+        assert_eq!(pop_one(&queues.entries).slot, 1);
+        assert_eq!(transactions.pop().map(|x| x.slot), Some(1));
+
+        // a full queue drops its oldest update, a full transaction queue drops and
+        // counts the new transaction
+        queues.route(account(2), &transactions);
+        queues.route(account(3), &transactions);
+        assert_eq!(pop_one(&queues.accounts).slot, 3);
+        queues.route(transaction(2), &transactions);
+        queues.route(transaction(3), &transactions);
+        assert_eq!(transactions.pop().map(|x| x.slot), Some(2));
+        assert_eq!(queues.dropped.load(Ordering::Relaxed), 1);
+
+        // kinds without a queue are dropped
+        UpdateQueues::default().route(account(4), &transactions);
+        assert!(transactions.is_empty());
+    }
+
+    #[test]
+    fn subscribes_to_every_dispatched_program() {
+        // the programs `event_handler` and `decode_instruction` match on, a new
+        // platform adds its id to both lists
+        let logged = [
+            PUMPFUN_ADDRESS,
+            RAYDIUM_CLMM_ADDRESS,
+            RAYDIUM_CPMM,
+            PUMPSWAP_ADDRESS,
+            METEORA_DLMM_ADDRESS,
+            ORCA_ADDRESS,
+        ];
+        let instructions = [
+            pump_fun::PUMPFUN,
+            cpmm::RAYDIUM_CPMM_PROGRAM,
+            raydium_clmm::RAYDIUM_CLMM,
+            pump_swap::PUMPSWAP,
+            raydium_amm_v4::RAYDIUM_AMM_V4,
+            meteora_dlmm::METEORA_DLMM,
+            damm_v1::METEORA_DAMM_V1,
+            damm_v2::METEORA_DAMM_V2,
+            orca::whirlpools_program_id(),
+        ];
+        let subscribed: Vec<Pubkey> = DECODED_PROGRAMS
+            .iter()
+            .map(|x| x.parse().unwrap())
+            .collect();
+        for program in logged.iter().chain(&EMIT_CPI_PROGRAMS) {
+            assert!(DECODED_PROGRAMS.contains(program), "{program}");
+        }
+        for program in &instructions {
+            assert!(subscribed.contains(program), "{program}");
+        }
+        // nothing subscribed to without being decoded
+        assert_eq!(subscribed.len(), instructions.len());
+    }
+
+    #[test]
+    fn decodes_damm_v2_swap_events_from_their_self_cpi() {
+        let pool = Pubkey::new_unique();
+        let self_cpi = |data: Vec<u8>| FlatInstruction {
+            index: 0,
+            inner_index: Some(0),
+            stack_height: 2,
+            program_id: damm_v2::METEORA_DAMM_V2,
+            accounts: accounts(1),
+            data,
+            parsed: None,
+        };
+        let swap = DammV2SwapEvent {
+            signature: String::new(),
+            pool,
+            trade_direction: 1,
+            has_referral: false,
+            params: damm_v2::SwapParameters {
+                amount_in: 5_000_000,
+                minimum_amount_out: 1,
+            },
+            swap_result: damm_v2::SwapResult {
+                output_amount: 123_456,
+                next_sqrt_price: 1 << 64,
+                lp_fee: 10_000,
+                protocol_fee: 2_500,
+                partner_fee: 0,
+                referral_fee: 0,
+            },
+            actual_amount_in: 5_000_000,
+            current_timestamp: 1_750_000_000,
+        };
+        let mut data = [EVENT_IX_TAG, damm_v2::SWAP_EVENT_DISCRIMINATOR].concat();
+        data.extend(borsh::to_vec(&swap).unwrap());
+        let Some(MutEvents::TradeEvent(TradeEvent::MeteoraDammV2Trade(DammV2TradeEvent::Swap(
+            decoded,
+        )))) = decode_instruction(&[self_cpi(data.clone())], 0, "sig")
+        else {
+            panic!("EvtSwap not decoded");
+        };
+        assert_eq!(decoded.signature, "sig");
+        assert_eq!(decoded.pool, pool);
+        assert_eq!(decoded.trade_direction, 1);
+        assert_eq!(decoded.swap_result.output_amount, 123_456);
+        assert_eq!(decoded.actual_amount_in, 5_000_000);
+        data.truncate(data.len() - 1);
+        assert!(decode_instruction(&[self_cpi(data)], 0, "sig").is_none());
+
+        let swap2 = DammV2Swap2Event {
+            signature: String::new(),
+            pool,
+            trade_direction: 0,
+            collect_fee_mode: damm_v2::COLLECT_FEE_MODE_ONLY_B,
+            has_referral: true,
+            params: damm_v2::SwapParameters2 {
+                amount_0: 1_000_000,
+                amount_1: 3_000_000,
+                swap_mode: 2,
+            },
+            swap_result: damm_v2::SwapResult2 {
+                included_fee_input_amount: 2_900_000,
+                excluded_fee_input_amount: 2_891_300,
+                amount_left: 0,
+                output_amount: 1_000_000,
+                next_sqrt_price: 2 << 64,
+                trading_fee: 8_700,
+                protocol_fee: 1_740,
+                partner_fee: 0,
+                referral_fee: 348,
+            },
+            included_transfer_fee_amount_in: 2_900_000,
+            included_transfer_fee_amount_out: 1_000_000,
+            excluded_transfer_fee_amount_out: 1_000_000,
+            current_timestamp: 1_750_000_000,
+            reserve_a_amount: 40_000_000,
+            reserve_b_amount: 120_000_000,
+        };
+        let mut data = [EVENT_IX_TAG, damm_v2::SWAP2_EVENT_DISCRIMINATOR].concat();
+        data.extend(borsh::to_vec(&swap2).unwrap());
+        let Some(MutEvents::TradeEvent(TradeEvent::MeteoraDammV2Trade(DammV2TradeEvent::Swap2(
+            decoded,
+        )))) = decode_instruction(&[self_cpi(data)], 0, "sig")
+        else {
+            panic!("EvtSwap2 not decoded");
+        };
+        assert_eq!(decoded.pool, pool);
+        assert_eq!(decoded.params.swap_mode, 2);
+        assert_eq!(decoded.swap_result.included_fee_input_amount, 2_900_000);
+        assert_eq!(decoded.swap_result.referral_fee, 348);
+        assert_eq!(decoded.reserve_b_amount, 120_000_000);
+    }
+}
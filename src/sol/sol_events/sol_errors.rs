@@ -0,0 +1,138 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_pubkey::Pubkey;
+use solana_sdk::{instruction::InstructionError, transaction::TransactionError};
+
+use super::sol_platforms::{
+    orca::{self, ORCA_ADDRESS},
+    pump_fun::{self, PUMPFUN_ADDRESS},
+    raydium_clmm::{self, RAYDIUM_CLMM_ADDRESS},
+    raydium_cpmm::cpmm::{self, RAYDIUM_CPMM},
+};
+
+/// Anchor numbers the errors of a program from 6000
+pub const ANCHOR_ERROR_OFFSET: u32 = 6000;
+
+/// A transaction that landed with an error
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct FailedTransaction {
+    pub signature: String,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub fee_payer: Option<Pubkey>,
+    /// Outer instruction that failed
+    pub instruction_index: Option<u8>,
+    /// Program raising the error, the innermost one when it failed in a CPI
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub program_id: Option<Pubkey>,
+    /// `InstructionError::Custom` code
+    pub code: Option<u32>,
+    /// Error name, for the tracked programs
+    pub name: Option<String>,
+    pub error: String,
+    /// The bincode encoded `TransactionError`, kept when it doesn't decode, e.g. a
+    /// variant newer than our solana-sdk
+    pub raw_error: Option<Vec<u8>>,
+}
+
+/// Name of a custom error of pump.fun, Raydium CPMM/CLMM or Whirlpool
+pub fn program_error_name(program_id: &str, code: u32) -> Option<&'static str> {
+    let names = match program_id {
+        PUMPFUN_ADDRESS => pump_fun::ERROR_NAMES,
+        RAYDIUM_CPMM => cpmm::ERROR_NAMES,
+        RAYDIUM_CLMM_ADDRESS => raydium_clmm::ERROR_NAMES,
+        ORCA_ADDRESS => orca::ERROR_NAMES,
+        _ => return None,
+    };
+    names
+        .get(code.checked_sub(ANCHOR_ERROR_OFFSET)? as usize)
+        .copied()
+}
+
+// Parse: Program <program_id> failed: <reason>
+fn parse_failure(line: &str) -> Option<&str> {
+    let (program_id, _) = line.strip_prefix("Program ")?.split_once(" failed: ")?;
+    Some(program_id)
+}
+
+impl FailedTransaction {
+    /// `error` is the bincode encoded `TransactionError` of the meta. The innermost
+    /// failing program is read from the logs, the outer instruction's program is used
+    /// when they are missing.
+    pub fn new(
+        signature: String,
+        error: &[u8],
+        account_keys: &[Pubkey],
+        outer_programs: &[Pubkey],
+        logs: &[String],
+    ) -> Self {
+        let decoded = bincode::deserialize::<TransactionError>(error).ok();
+        let (instruction_index, code) = match &decoded {
+            Some(TransactionError::InstructionError(index, InstructionError::Custom(code))) => {
+                (Some(*index), Some(*code))
+            }
+            Some(TransactionError::InstructionError(index, _)) => (Some(*index), None),
+            _ => (None, None),
+        };
+        // the failing program logs first, its callers follow
+        let program_id = logs
+            .iter()
+            .find_map(|x| parse_failure(x.trim()))
+            .and_then(|x| Pubkey::from_str(x).ok())
+            .or_else(|| outer_programs.get(instruction_index? as usize).copied());
+        let name = match (program_id, code) {
+            (Some(program_id), Some(code)) => {
+                program_error_name(&program_id.to_string(), code).map(|x| x.to_string())
+            }
+            _ => None,
+        };
+        FailedTransaction {
+            signature,
+            fee_payer: account_keys.first().copied(),
+            instruction_index,
+            program_id,
+            code,
+            name,
+            error: match &decoded {
+                Some(x) => x.to_string(),
+                None => "undecodable transaction error".to_string(),
+            },
+            raw_error: decoded.is_none().then(|| error.to_vec()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn names_custom_errors_and_keeps_undecodable_ones() {
+        let payer = Pubkey::new_unique();
+        let pump_fun = Pubkey::from_str(PUMPFUN_ADDRESS).unwrap();
+        let logs = vec![format!(
+            "Program {PUMPFUN_ADDRESS} failed: custom program error: 0x1772"
+        )];
+        let error = bincode::serialize(&TransactionError::InstructionError(
+            2,
+            InstructionError::Custom(6002),
+        ))
+        .unwrap();
+        let failed = FailedTransaction::new("sig".to_string(), &error, &[payer], &[], &logs);
+        assert_eq!(failed.fee_payer, Some(payer));
+        assert_eq!(failed.instruction_index, Some(2));
+        assert_eq!(failed.program_id, Some(pump_fun));
+        assert_eq!(failed.code, Some(6002));
+        assert_eq!(failed.name.as_deref(), Some(pump_fun::ERROR_NAMES[2]));
+        assert!(failed.raw_error.is_none());
+
+        // a variant index past the ones solana-sdk knows
+        let error = [200, 0, 0, 0];
+        let failed = FailedTransaction::new("sig".to_string(), &error, &[payer], &[], &logs);
+        assert_eq!(failed.program_id, Some(pump_fun));
+        assert_eq!((failed.code, failed.name), (None, None));
+        assert_eq!(failed.raw_error, Some(error.to_vec()));
+    }
+}
@@ -1,5 +1,139 @@
+pub mod meteora_damm;
+pub mod meteora_dlmm;
 pub mod orca;
 pub mod pump_fun;
+pub mod pump_swap;
+pub mod raydium_amm_v4;
 pub mod raydium_cpmm;
 
 pub mod raydium_clmm;
+
+/// Programs `sol_events` decodes events or instructions of, subscribed to by default
+pub const DECODED_PROGRAMS: [&str; 9] = [
+    pump_fun::PUMPFUN_ADDRESS,
+    pump_swap::PUMPSWAP_ADDRESS,
+    orca::ORCA_ADDRESS,
+    raydium_clmm::RAYDIUM_CLMM_ADDRESS,
+    raydium_cpmm::cpmm::RAYDIUM_CPMM,
+    raydium_amm_v4::RAYDIUM_AMM_V4_ADDRESS,
+    meteora_dlmm::METEORA_DLMM_ADDRESS,
+    meteora_damm::v1::METEORA_DAMM_V1_ADDRESS,
+    meteora_damm::v2::METEORA_DAMM_V2_ADDRESS,
+];
+
+/// Helpers shared by the decoder tests
+#[cfg(test)]
+pub(crate) mod test_utils {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::orca::{Whirlpool, WhirlpoolRewardInfo};
+    use super::raydium_clmm::{PoolState, RewardInfo, TICK_ARRAY_BITMAP_SIZE};
+    use super::raydium_cpmm::cpmm;
+
+    /// `count` distinct accounts, to check which position each decoded field reads
+    pub fn accounts(count: usize) -> Vec<Pubkey> {
+        (0..count).map(|_| Pubkey::new_unique()).collect()
+    }
+
+    /// A 9 and 6 decimals pool holding uncollected protocol and fund fees
+    pub fn cpmm_pool() -> cpmm::Pool {
+        cpmm::Pool {
+            amm_config: Pubkey::new_unique(),
+            pool_creator: Pubkey::new_unique(),
+            token0_vault: Pubkey::new_unique(),
+            token1_vault: Pubkey::new_unique(),
+            lp_mint: Pubkey::new_unique(),
+            token0_mint: Pubkey::new_unique(),
+            token1_mint: Pubkey::new_unique(),
+            token0_program: spl_token::ID,
+            token1_program: spl_token::ID,
+            observation_key: Pubkey::new_unique(),
+            auth_bump: 254,
+            status: 0,
+            lp_mint_decimals: 9,
+            mint0_decimals: 9,
+            mint1_decimals: 6,
+            lp_supply: 0,
+            // uncollected fees sit in the vaults, out of the reserves
+            protocol_fees_token0: 3_000_000,
+            protocol_fees_token1: 450_000,
+            fund_fees_token0: 1_000_000,
+            fund_fees_token1: 150_000,
+            open_time: 0,
+            recent_epoch: 0,
+            padding: [0; 31],
+        }
+    }
+
+    /// A pool with 600 ticks per array and the given tick arrays initialized
+    pub fn clmm_pool(tick_current: i32, initialized: &[i32]) -> PoolState {
+        let mut pool = PoolState {
+            bump: [255],
+            amm_config: Pubkey::new_unique(),
+            owner: Pubkey::default(),
+            token_mint_0: Pubkey::new_unique(),
+            token_mint_1: Pubkey::new_unique(),
+            token_vault_0: Pubkey::new_unique(),
+            token_vault_1: Pubkey::new_unique(),
+            observation_key: Pubkey::new_unique(),
+            mint_decimals_0: 9,
+            mint_decimals_1: 6,
+            tick_spacing: 10,
+            liquidity: 0,
+            sqrt_price_x64: 0,
+            tick_current,
+            padding3: 0,
+            padding4: 0,
+            fee_growth_global_0_x64: 0,
+            fee_growth_global_1_x64: 0,
+            protocol_fees_token_0: 0,
+            protocol_fees_token_1: 0,
+            swap_in_amount_token_0: 0,
+            swap_out_amount_token_1: 0,
+            swap_in_amount_token_1: 0,
+            swap_out_amount_token_0: 0,
+            status: 0,
+            padding: [0; 7],
+            reward_infos: [RewardInfo::default(); 3],
+            tick_array_bitmap: [0; 16],
+            total_fees_token_0: 0,
+            total_fees_claimed_token_0: 0,
+            total_fees_token_1: 0,
+            total_fees_claimed_token_1: 0,
+            fund_fees_token_0: 0,
+            fund_fees_token_1: 0,
+            open_time: 0,
+            recent_epoch: 0,
+        };
+        for start_index in initialized {
+            let offset = (start_index / 600 + TICK_ARRAY_BITMAP_SIZE) as usize;
+            pool.tick_array_bitmap[offset / 64] |= 1 << (offset % 64);
+        }
+        pool
+    }
+
+    /// Tick spacing 64, 5632 ticks per array
+    pub fn whirlpool(tick_current_index: i32) -> Whirlpool {
+        Whirlpool {
+            whirlpools_config: Pubkey::new_unique(),
+            whirlpool_bump: [255],
+            tick_spacing: 64,
+            fee_tier_index_seed: [64, 0],
+            fee_rate: 3000,
+            protocol_fee_rate: 1300,
+            liquidity: 3_000_000_000,
+            sqrt_price: 1 << 64,
+            tick_current_index,
+            protocol_fee_owed_a: 0,
+            protocol_fee_owed_b: 0,
+            token_mint_a: Pubkey::new_unique(),
+            token_vault_a: Pubkey::new_unique(),
+            fee_growth_global_a: 0,
+            token_mint_b: Pubkey::new_unique(),
+            token_vault_b: Pubkey::new_unique(),
+            fee_growth_global_b: 0,
+            reward_last_updated_timestamp: 0,
+            reward_infos: [WhirlpoolRewardInfo::default(); 3],
+        }
+    }
+}
@@ -0,0 +1,1471 @@
+/// Meteora dynamic AMM (DAMM v1), pool reserves live in Meteora vaults the pool
+/// holds lp tokens of
+pub mod v1 {
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use serde::{Deserialize, Serialize};
+    use serde_with::{DisplayFromStr, serde_as};
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_pubkey::pubkey;
+    use solana_sdk::{
+        account::Account,
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+    };
+
+    pub const METEORA_DAMM_V1_ADDRESS: &str = "Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB";
+    pub const METEORA_DAMM_V1: Pubkey = pubkey!("Eo7WjKq67rjJQSZxS6z3YkapzY3eMj6Xy8X5EQVn5UaB");
+    /// Program owning the token vaults of the pools
+    pub const METEORA_VAULT: Pubkey = pubkey!("24Uqj9JCLxUeoC3hGfh5W3s9FM9uCHDS2SG3LYwBpyTi");
+
+    pub const SWAP_EVENT_DISCRIMINATOR: [u8; 8] = [81, 108, 227, 190, 205, 208, 10, 196];
+    pub const POOL_ACCOUNT_DISCRIMINATOR: [u8; 8] = [241, 154, 109, 4, 17, 177, 109, 188];
+    pub const VAULT_ACCOUNT_DISCRIMINATOR: [u8; 8] = [211, 8, 232, 43, 2, 152, 117, 119];
+
+    /// Vault profits unlock linearly, the degradation rate is expressed over 1e12
+    pub const LOCKED_PROFIT_DEGRADATION_DENOMINATOR: u128 = 1_000_000_000_000;
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+    pub struct DammV1SwapEvent {
+        #[borsh(skip)]
+        pub signature: String,
+        pub in_amount: u64,
+        pub out_amount: u64,
+        pub trade_fee: u64,
+        pub protocol_fee: u64,
+        pub host_fee: u64,
+    }
+
+    /// Fees are static, the protocol takes its share out of the trade fee
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct PoolFees {
+        pub trade_fee_numerator: u64,
+        pub trade_fee_denominator: u64,
+        pub protocol_trade_fee_numerator: u64,
+        pub protocol_trade_fee_denominator: u64,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum PoolType {
+        Permissioned,
+        Permissionless,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct Bootstrapping {
+        /// Slot or timestamp depending on `activation_type`
+        pub activation_point: u64,
+        pub whitelisted_vault: Pubkey,
+        pub pool_creator_authority: Pubkey,
+        pub activation_type: u8,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct PartnerInfo {
+        pub fee_numerator: u64,
+        pub partner_authority: Pubkey,
+        pub pending_fee_a: u64,
+        pub pending_fee_b: u64,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug)]
+    pub struct Padding {
+        pub padding_0: [u8; 6],
+        pub padding_1: [u64; 21],
+        pub padding_2: [u64; 21],
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct TokenMultiplier {
+        pub token_a_multiplier: u64,
+        pub token_b_multiplier: u64,
+        pub precision_factor: u8,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq)]
+    pub enum DepegType {
+        None,
+        Marinade,
+        Lido,
+        SplStake,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug)]
+    pub struct Depeg {
+        pub base_virtual_price: u64,
+        pub base_cache_updated: u64,
+        pub depeg_type: DepegType,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug)]
+    pub enum CurveType {
+        ConstantProduct,
+        Stable {
+            amp: u64,
+            token_multiplier: TokenMultiplier,
+            depeg: Depeg,
+            last_amp_updated_timestamp: u64,
+        },
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+    pub struct Pool {
+        pub lp_mint: Pubkey,
+        pub token_a_mint: Pubkey,
+        pub token_b_mint: Pubkey,
+        pub a_vault: Pubkey,
+        pub b_vault: Pubkey,
+        /// Lp token account of `a_vault` held by the pool
+        pub a_vault_lp: Pubkey,
+        /// Lp token account of `b_vault` held by the pool
+        pub b_vault_lp: Pubkey,
+        pub a_vault_lp_bump: u8,
+        pub enabled: bool,
+        pub protocol_token_a_fee: Pubkey,
+        pub protocol_token_b_fee: Pubkey,
+        pub fee_last_updated_at: u64,
+        pub padding_0: [u8; 24],
+        pub fees: PoolFees,
+        pub pool_type: PoolType,
+        pub stake: Pubkey,
+        pub total_locked_lp: u64,
+        pub bootstrapping: Bootstrapping,
+        pub partner_info: PartnerInfo,
+        pub padding: Padding,
+        pub curve_type: CurveType,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct VaultBumps {
+        pub vault_bump: u8,
+        pub token_vault_bump: u8,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct LockedProfitTracker {
+        pub last_updated_locked_profit: u64,
+        /// Timestamp of the last strategy report
+        pub last_report: u64,
+        pub locked_profit_degradation: u64,
+    }
+
+    /// A Meteora vault, lent out to strategies so only part of `total_amount` sits
+    /// in `token_vault`
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+    pub struct Vault {
+        pub enabled: u8,
+        pub bumps: VaultBumps,
+        pub total_amount: u64,
+        pub token_vault: Pubkey,
+        pub fee_vault: Pubkey,
+        pub token_mint: Pubkey,
+        pub lp_mint: Pubkey,
+        pub strategies: [Pubkey; 30],
+        pub base: Pubkey,
+        pub admin: Pubkey,
+        pub operator: Pubkey,
+        pub locked_profit_tracker: LockedProfitTracker,
+    }
+
+    #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+    pub struct DammV1Quote {
+        pub amount_in: u64,
+        pub amount_out: u64,
+        /// Trade fee left to the liquidity providers, in the input token
+        pub trade_fee: u64,
+        /// In the input token
+        pub protocol_fee: u64,
+    }
+
+    /// `amount * numerator / denominator`, at least 1 when the fee is not zero
+    fn calculate_fee(amount: u64, numerator: u64, denominator: u64) -> Option<u64> {
+        if numerator == 0 || amount == 0 {
+            return Some(0);
+        }
+        let fee = (amount as u128 * numerator as u128).checked_div(denominator as u128)?;
+        u64::try_from(fee.max(1)).ok()
+    }
+
+    impl PoolFees {
+        pub fn trading_fee(&self, amount: u64) -> Option<u64> {
+            calculate_fee(amount, self.trade_fee_numerator, self.trade_fee_denominator)
+        }
+        /// Protocol share of `trade_fee`
+        pub fn protocol_trading_fee(&self, trade_fee: u64) -> Option<u64> {
+            calculate_fee(
+                trade_fee,
+                self.protocol_trade_fee_numerator,
+                self.protocol_trade_fee_denominator,
+            )
+        }
+    }
+
+    impl Vault {
+        pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+            if data.len() < 8 || data[..8] != VAULT_ACCOUNT_DISCRIMINATOR {
+                return Err(());
+            }
+            Self::deserialize(&mut &data[8..]).map_err(|_| ())
+        }
+        /// Profit of the last report still locked at `current_time`
+        pub fn locked_profit(&self, current_time: u64) -> Option<u64> {
+            let tracker = &self.locked_profit_tracker;
+            let duration = current_time.checked_sub(tracker.last_report)? as u128;
+            let locked_fund_ratio = duration * tracker.locked_profit_degradation as u128;
+            if locked_fund_ratio > LOCKED_PROFIT_DEGRADATION_DENOMINATOR {
+                return Some(0);
+            }
+            let locked_profit = tracker.last_updated_locked_profit as u128
+                * (LOCKED_PROFIT_DEGRADATION_DENOMINATOR - locked_fund_ratio)
+                / LOCKED_PROFIT_DEGRADATION_DENOMINATOR;
+            u64::try_from(locked_profit).ok()
+        }
+        pub fn unlocked_amount(&self, current_time: u64) -> Option<u64> {
+            self.total_amount
+                .checked_sub(self.locked_profit(current_time)?)
+        }
+        /// Tokens backing `share` lp tokens out of `total_supply`, rounded down
+        pub fn amount_by_share(
+            &self,
+            current_time: u64,
+            share: u64,
+            total_supply: u64,
+        ) -> Option<u64> {
+            if total_supply == 0 {
+                return Some(0);
+            }
+            let amount =
+                self.unlocked_amount(current_time)? as u128 * share as u128 / total_supply as u128;
+            u64::try_from(amount).ok()
+        }
+        /// Lp tokens worth `amount`, rounded down
+        pub fn unmint_amount(
+            &self,
+            current_time: u64,
+            amount: u64,
+            total_supply: u64,
+        ) -> Option<u64> {
+            let unlocked_amount = self.unlocked_amount(current_time)? as u128;
+            if unlocked_amount == 0 {
+                return None;
+            }
+            u64::try_from(amount as u128 * total_supply as u128 / unlocked_amount).ok()
+        }
+    }
+
+    /// Vaults of a pool and the pool's share of them
+    #[derive(Clone, Debug)]
+    pub struct PoolVaults {
+        pub a_vault: Vault,
+        pub b_vault: Vault,
+        /// Balance of the pool's `a_vault_lp` account
+        pub a_vault_lp_amount: u64,
+        pub b_vault_lp_amount: u64,
+        /// Supply of the lp mint of `a_vault`
+        pub a_vault_lp_supply: u64,
+        pub b_vault_lp_supply: u64,
+    }
+
+    /// Amount of a spl token account
+    fn token_account_amount(account: &Account) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            account.data.get(64..72)?.try_into().ok()?,
+        ))
+    }
+
+    /// Supply of a spl token mint
+    fn mint_supply(account: &Account) -> Option<u64> {
+        Some(u64::from_le_bytes(
+            account.data.get(36..44)?.try_into().ok()?,
+        ))
+    }
+
+    impl PoolVaults {
+        /// Reads both vaults and the pool's lp accounts, then the vault lp mints
+        pub async fn fetch(rpc: &RpcClient, pool: &Pool) -> Result<Self, ()> {
+            let accounts = rpc
+                .get_multiple_accounts(&[
+                    pool.a_vault,
+                    pool.b_vault,
+                    pool.a_vault_lp,
+                    pool.b_vault_lp,
+                ])
+                .await
+                .map_err(|_| ())?;
+            let [
+                Some(a_vault),
+                Some(b_vault),
+                Some(a_vault_lp),
+                Some(b_vault_lp),
+            ] = accounts.as_slice()
+            else {
+                return Err(());
+            };
+            if a_vault.owner != METEORA_VAULT || b_vault.owner != METEORA_VAULT {
+                return Err(());
+            }
+            let a_vault = Vault::from_bytes(&a_vault.data)?;
+            let b_vault = Vault::from_bytes(&b_vault.data)?;
+            let mints = rpc
+                .get_multiple_accounts(&[a_vault.lp_mint, b_vault.lp_mint])
+                .await
+                .map_err(|_| ())?;
+            let [Some(a_lp_mint), Some(b_lp_mint)] = mints.as_slice() else {
+                return Err(());
+            };
+            Ok(PoolVaults {
+                a_vault_lp_amount: token_account_amount(a_vault_lp).ok_or(())?,
+                b_vault_lp_amount: token_account_amount(b_vault_lp).ok_or(())?,
+                a_vault_lp_supply: mint_supply(a_lp_mint).ok_or(())?,
+                b_vault_lp_supply: mint_supply(b_lp_mint).ok_or(())?,
+                a_vault,
+                b_vault,
+            })
+        }
+        /// Token a and token b owned by the pool at `current_time`
+        pub fn reserves(&self, current_time: u64) -> Option<(u64, u64)> {
+            Some((
+                self.a_vault.amount_by_share(
+                    current_time,
+                    self.a_vault_lp_amount,
+                    self.a_vault_lp_supply,
+                )?,
+                self.b_vault.amount_by_share(
+                    current_time,
+                    self.b_vault_lp_amount,
+                    self.b_vault_lp_supply,
+                )?,
+            ))
+        }
+    }
+
+    /// Output of a constant product swap, the pool keeps the rounding
+    pub fn constant_product_swap(
+        amount_in: u64,
+        source_amount: u64,
+        destination_amount: u64,
+    ) -> Option<u64> {
+        let invariant = source_amount as u128 * destination_amount as u128;
+        let new_source_amount = source_amount as u128 + amount_in as u128;
+        if new_source_amount == 0 {
+            return None;
+        }
+        let new_destination_amount = invariant.div_ceil(new_source_amount);
+        u64::try_from((destination_amount as u128).checked_sub(new_destination_amount)?).ok()
+    }
+
+    impl Pool {
+        pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+            if data.len() < 8 || data[..8] != POOL_ACCOUNT_DISCRIMINATOR {
+                return Err(());
+            }
+            Self::deserialize(&mut &data[8..]).map_err(|_| ())
+        }
+        pub async fn fetch(rpc: &RpcClient, pool_address: &Pubkey) -> Result<Self, ()> {
+            let account = rpc.get_account(pool_address).await.map_err(|_| ())?;
+
+            if account.owner != METEORA_DAMM_V1 {
+                return Err(());
+            }
+            Self::from_bytes(&account.data)
+        }
+
+        /// Quotes swapping exactly `amount_in`, fees included. Only constant product
+        /// pools are supported.
+        ///
+        /// # Arguments
+        /// * `current_time` - Unix timestamp, unlocks the vault profits
+        pub fn quote_exact_in(
+            &self,
+            vaults: &PoolVaults,
+            amount_in: u64,
+            a_to_b: bool,
+            current_time: u64,
+        ) -> Result<DammV1Quote, ()> {
+            if !matches!(self.curve_type, CurveType::ConstantProduct) {
+                return Err(());
+            }
+            let (token_a_amount, token_b_amount) = vaults.reserves(current_time).ok_or(())?;
+            let trade_fee = self.fees.trading_fee(amount_in).ok_or(())?;
+            let protocol_fee = self.fees.protocol_trading_fee(trade_fee).ok_or(())?;
+            let trade_fee = trade_fee - protocol_fee;
+            let amount_in_less_protocol_fee = amount_in.checked_sub(protocol_fee).ok_or(())?;
+
+            let (in_vault, in_lp_amount, in_lp_supply, out_vault, out_lp_supply) = if a_to_b {
+                (
+                    &vaults.a_vault,
+                    vaults.a_vault_lp_amount,
+                    vaults.a_vault_lp_supply,
+                    &vaults.b_vault,
+                    vaults.b_vault_lp_supply,
+                )
+            } else {
+                (
+                    &vaults.b_vault,
+                    vaults.b_vault_lp_amount,
+                    vaults.b_vault_lp_supply,
+                    &vaults.a_vault,
+                    vaults.a_vault_lp_supply,
+                )
+            };
+            // the deposit into the input vault rounds against the pool
+            let before_in = in_vault
+                .amount_by_share(current_time, in_lp_amount, in_lp_supply)
+                .ok_or(())?;
+            let in_lp = in_vault
+                .unmint_amount(current_time, amount_in_less_protocol_fee, in_lp_supply)
+                .ok_or(())?;
+            let mut in_vault = in_vault.clone();
+            in_vault.total_amount = in_vault
+                .total_amount
+                .checked_add(amount_in_less_protocol_fee)
+                .ok_or(())?;
+            let after_in = in_vault
+                .amount_by_share(current_time, in_lp_amount + in_lp, in_lp_supply + in_lp)
+                .ok_or(())?;
+            let actual_amount_in = after_in
+                .checked_sub(before_in)
+                .and_then(|x| x.checked_sub(trade_fee))
+                .ok_or(())?;
+
+            let (source_amount, destination_amount) = if a_to_b {
+                (token_a_amount, token_b_amount)
+            } else {
+                (token_b_amount, token_a_amount)
+            };
+            let swapped =
+                constant_product_swap(actual_amount_in, source_amount, destination_amount)
+                    .ok_or(())?;
+            // so is the withdrawal from the output vault
+            let out_lp = out_vault
+                .unmint_amount(current_time, swapped, out_lp_supply)
+                .ok_or(())?;
+            let amount_out = out_vault
+                .amount_by_share(current_time, out_lp, out_lp_supply)
+                .ok_or(())?;
+
+            Ok(DammV1Quote {
+                amount_in,
+                amount_out,
+                trade_fee,
+                protocol_fee,
+            })
+        }
+    }
+
+    #[derive(
+        BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, Serialize, Deserialize,
+    )]
+    pub struct SwapArgs {
+        pub in_amount: u64,
+        pub minimum_out_amount: u64,
+    }
+
+    impl SwapArgs {
+        pub const DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+        pub fn data(&self) -> Result<Vec<u8>, ()> {
+            let mut data = Vec::with_capacity(24);
+            data.extend_from_slice(&Self::DISCRIMINATOR);
+            BorshSerialize::serialize(self, &mut data).map_err(|_| ())?;
+            Ok(data)
+        }
+    }
+
+    /// Builds a swap, the protocol fee is taken in the input token
+    #[allow(clippy::too_many_arguments)]
+    pub fn swap(
+        payer: &Pubkey,
+        pool_address: &Pubkey,
+        pool: &Pool,
+        vaults: &PoolVaults,
+        user_source_token: &Pubkey,
+        user_destination_token: &Pubkey,
+        a_to_b: bool,
+        args: SwapArgs,
+    ) -> Result<Instruction, ()> {
+        let protocol_token_fee = if a_to_b {
+            pool.protocol_token_a_fee
+        } else {
+            pool.protocol_token_b_fee
+        };
+        Ok(Instruction::new_with_bytes(
+            METEORA_DAMM_V1,
+            &args.data()?,
+            vec![
+                AccountMeta::new(*pool_address, false),
+                AccountMeta::new(*user_source_token, false),
+                AccountMeta::new(*user_destination_token, false),
+                AccountMeta::new(pool.a_vault, false),
+                AccountMeta::new(pool.b_vault, false),
+                AccountMeta::new(vaults.a_vault.token_vault, false),
+                AccountMeta::new(vaults.b_vault.token_vault, false),
+                AccountMeta::new(vaults.a_vault.lp_mint, false),
+                AccountMeta::new(vaults.b_vault.lp_mint, false),
+                AccountMeta::new(pool.a_vault_lp, false),
+                AccountMeta::new(pool.b_vault_lp, false),
+                AccountMeta::new(protocol_token_fee, false),
+                AccountMeta::new_readonly(*payer, true),
+                AccountMeta::new_readonly(METEORA_VAULT, false),
+                AccountMeta::new_readonly(spl_token::ID, false),
+            ],
+        ))
+    }
+    /// Accounts of a swap, the direction follows the user token accounts
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct DammV1SwapAccounts {
+        #[serde_as(as = "DisplayFromStr")]
+        pub pool: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub user_source_token: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub user_destination_token: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub a_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub b_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub protocol_token_fee: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub user: Pubkey,
+    }
+
+    impl DammV1SwapAccounts {
+        pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+            if accounts.len() < 13 {
+                return Err(());
+            }
+            Ok(Self {
+                pool: accounts[0],
+                user_source_token: accounts[1],
+                user_destination_token: accounts[2],
+                a_vault: accounts[3],
+                b_vault: accounts[4],
+                protocol_token_fee: accounts[11],
+                user: accounts[12],
+            })
+        }
+    }
+
+    /// A DAMM v1 swap decoded from a transaction
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum DammV1Instruction {
+        Swap {
+            args: SwapArgs,
+            accounts: DammV1SwapAccounts,
+        },
+    }
+
+    impl DammV1Instruction {
+        /// Decodes the instruction data and its resolved accounts
+        ///
+        /// # Returns
+        /// * `Err(())` - Not a swap, or truncated data or accounts
+        pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+            let mut args = data.strip_prefix(&SwapArgs::DISCRIMINATOR).ok_or(())?;
+            Ok(Self::Swap {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: DammV1SwapAccounts::from_accounts(accounts)?,
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct DammV1InstructionEvent {
+        pub signature: String,
+        /// Index of the outer instruction
+        pub index: usize,
+        /// Position among the inner instructions, `None` for an outer instruction
+        pub inner_index: Option<usize>,
+        pub instruction: DammV1Instruction,
+    }
+}
+
+/// Meteora DAMM v2 (cp-amm), a single range concentrated pool with a fee scheduler
+/// and a volatility based dynamic fee
+pub mod v2 {
+    use borsh::{BorshDeserialize, BorshSerialize};
+    use serde::{Deserialize, Serialize};
+    use serde_with::{DisplayFromStr, serde_as};
+    use solana_client::nonblocking::rpc_client::RpcClient;
+    use solana_pubkey::pubkey;
+    use solana_sdk::{
+        instruction::{AccountMeta, Instruction},
+        pubkey::Pubkey,
+    };
+
+    use crate::sol::sol_math::{ONE_Q64, U256, pow_q64};
+
+    pub const METEORA_DAMM_V2_ADDRESS: &str = "cpamdpZCGKUy5JxQXB4dcpGPiikHswMfz7SWN7AU9a2";
+    pub const METEORA_DAMM_V2: Pubkey = pubkey!("cpamdpZCGKUy5JxQXB4dcpGPiikHswMfz7SWN7AU9a2");
+
+    pub const SWAP_EVENT_DISCRIMINATOR: [u8; 8] = [27, 60, 21, 213, 138, 170, 187, 147];
+    pub const SWAP2_EVENT_DISCRIMINATOR: [u8; 8] = [189, 66, 51, 168, 38, 80, 117, 153];
+    pub const POOL_ACCOUNT_DISCRIMINATOR: [u8; 8] = [241, 154, 109, 4, 17, 177, 109, 188];
+
+    /// Seed for the pool authority PDA
+    pub const POOL_AUTHORITY_SEED: &[u8] = b"pool_authority";
+    /// Seed for the anchor event authority PDA
+    pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+    /// Fee numerators are expressed over 1e9
+    pub const FEE_DENOMINATOR: u128 = 1_000_000_000;
+    /// Fees are capped at 50%
+    pub const MAX_FEE_NUMERATOR: u128 = 500_000_000;
+    pub const BASIS_POINT_MAX: u128 = 10_000;
+
+    pub const TRADE_DIRECTION_A_TO_B: u8 = 0;
+    pub const COLLECT_FEE_MODE_ONLY_B: u8 = 1;
+    pub const FEE_SCHEDULER_MODE_EXPONENTIAL: u8 = 1;
+
+    #[derive(
+        BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, Serialize, Deserialize,
+    )]
+    pub struct SwapParameters {
+        pub amount_in: u64,
+        pub minimum_amount_out: u64,
+    }
+
+    #[derive(
+        BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, Serialize, Deserialize,
+    )]
+    pub struct SwapResult {
+        pub output_amount: u64,
+        pub next_sqrt_price: u128,
+        pub lp_fee: u64,
+        pub protocol_fee: u64,
+        pub partner_fee: u64,
+        pub referral_fee: u64,
+    }
+
+    /// The `EvtSwap` anchor event, emitted through a self CPI (`emit_cpi!`)
+    #[serde_as]
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+    pub struct DammV2SwapEvent {
+        #[borsh(skip)]
+        pub signature: String,
+        #[serde_as(as = "DisplayFromStr")]
+        pub pool: Pubkey,
+        /// 0 for token a to token b, 1 for token b to token a
+        pub trade_direction: u8,
+        pub has_referral: bool,
+        pub params: SwapParameters,
+        pub swap_result: SwapResult,
+        pub actual_amount_in: u64,
+        pub current_timestamp: u64,
+    }
+
+    #[derive(
+        BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, Serialize, Deserialize,
+    )]
+    pub struct SwapParameters2 {
+        /// Amount in for exact in and partial fill swaps, amount out for exact out
+        pub amount_0: u64,
+        /// Minimum amount out, or maximum amount in for exact out
+        pub amount_1: u64,
+        /// 0 for exact in, 1 for partial fill, 2 for exact out
+        pub swap_mode: u8,
+    }
+
+    #[derive(
+        BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, Serialize, Deserialize,
+    )]
+    pub struct SwapResult2 {
+        pub included_fee_input_amount: u64,
+        pub excluded_fee_input_amount: u64,
+        /// Input left unswapped by a partial fill
+        pub amount_left: u64,
+        pub output_amount: u64,
+        pub next_sqrt_price: u128,
+        pub trading_fee: u64,
+        pub protocol_fee: u64,
+        pub partner_fee: u64,
+        pub referral_fee: u64,
+    }
+
+    /// The `EvtSwap2` anchor event, emitted through a self CPI by `swap` and `swap2`
+    /// on newer program versions
+    #[serde_as]
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+    pub struct DammV2Swap2Event {
+        #[borsh(skip)]
+        pub signature: String,
+        #[serde_as(as = "DisplayFromStr")]
+        pub pool: Pubkey,
+        /// 0 for token a to token b, 1 for token b to token a
+        pub trade_direction: u8,
+        pub collect_fee_mode: u8,
+        pub has_referral: bool,
+        pub params: SwapParameters2,
+        pub swap_result: SwapResult2,
+        pub included_transfer_fee_amount_in: u64,
+        pub included_transfer_fee_amount_out: u64,
+        pub excluded_transfer_fee_amount_out: u64,
+        pub current_timestamp: u64,
+        pub reserve_a_amount: u64,
+        pub reserve_b_amount: u64,
+    }
+
+    /// A DAMM v2 swap, older program versions emit `EvtSwap` and newer `EvtSwap2`
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum DammV2TradeEvent {
+        Swap(DammV2SwapEvent),
+        Swap2(DammV2Swap2Event),
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct BaseFeeStruct {
+        pub cliff_fee_numerator: u64,
+        pub fee_scheduler_mode: u8,
+        pub padding_0: [u8; 5],
+        pub number_of_period: u16,
+        pub period_frequency: u64,
+        pub reduction_factor: u64,
+        pub padding_1: u64,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct DynamicFeeStruct {
+        pub initialized: u8,
+        pub padding: [u8; 7],
+        pub max_volatility_accumulator: u32,
+        pub variable_fee_control: u32,
+        pub bin_step: u16,
+        pub filter_period: u16,
+        pub decay_period: u16,
+        pub reduction_factor: u16,
+        pub last_update_timestamp: u64,
+        pub bin_step_u128: u128,
+        pub sqrt_price_reference: u128,
+        pub volatility_accumulator: u128,
+        pub volatility_reference: u128,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct PoolFeesStruct {
+        pub base_fee: BaseFeeStruct,
+        pub protocol_fee_percent: u8,
+        pub partner_fee_percent: u8,
+        pub referral_fee_percent: u8,
+        pub padding_0: [u8; 5],
+        pub dynamic_fee: DynamicFeeStruct,
+        pub padding_1: [u64; 2],
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+    pub struct PoolMetrics {
+        pub total_lp_a_fee: u128,
+        pub total_lp_b_fee: u128,
+        pub total_protocol_a_fee: u64,
+        pub total_protocol_b_fee: u64,
+        pub total_partner_a_fee: u64,
+        pub total_partner_b_fee: u64,
+        pub total_position: u64,
+        pub padding: u64,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+    pub struct Pool {
+        pub pool_fees: PoolFeesStruct,
+        pub token_a_mint: Pubkey,
+        pub token_b_mint: Pubkey,
+        pub token_a_vault: Pubkey,
+        pub token_b_vault: Pubkey,
+        pub whitelisted_vault: Pubkey,
+        pub partner: Pubkey,
+        pub liquidity: u128,
+        pub padding: u128,
+        pub protocol_a_fee: u64,
+        pub protocol_b_fee: u64,
+        pub partner_a_fee: u64,
+        pub partner_b_fee: u64,
+        pub sqrt_min_price: u128,
+        pub sqrt_max_price: u128,
+        /// Q64.64 square root of the price of token a in token b
+        pub sqrt_price: u128,
+        /// Slot or timestamp depending on `activation_type`
+        pub activation_point: u64,
+        pub activation_type: u8,
+        pub pool_status: u8,
+        /// 0 for spl token, 1 for token-2022
+        pub token_a_flag: u8,
+        pub token_b_flag: u8,
+        pub collect_fee_mode: u8,
+        pub pool_type: u8,
+        pub padding_0: [u8; 2],
+        pub fee_a_per_liquidity: [u8; 32],
+        pub fee_b_per_liquidity: [u8; 32],
+        pub permanent_lock_liquidity: u128,
+        pub metrics: PoolMetrics,
+        pub creator: Pubkey,
+    }
+
+    #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+    pub struct DammQuote {
+        pub amount_in: u64,
+        pub amount_out: u64,
+        /// Total trading fee, in the input token when charged on input, else in the output token
+        pub trading_fee: u64,
+        pub protocol_fee: u64,
+        pub fee_on_input: bool,
+        pub next_sqrt_price: u128,
+    }
+
+    impl Pool {
+        pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+            if data.len() < 8 || data[..8] != POOL_ACCOUNT_DISCRIMINATOR {
+                return Err(());
+            }
+            // account padding and reward infos follow `creator`
+            Self::deserialize(&mut &data[8..]).map_err(|_| ())
+        }
+        pub async fn fetch(rpc: &RpcClient, pool_address: &Pubkey) -> Result<Self, ()> {
+            let account = rpc.get_account(pool_address).await.map_err(|_| ())?;
+
+            if account.owner != METEORA_DAMM_V2 {
+                return Err(());
+            }
+            Self::from_bytes(&account.data)
+        }
+        pub fn token_a_program(&self) -> Pubkey {
+            token_program_from_flag(self.token_a_flag)
+        }
+        pub fn token_b_program(&self) -> Pubkey {
+            token_program_from_flag(self.token_b_flag)
+        }
+
+        /// Base fee numerator once the fee scheduler is applied.
+        ///
+        /// # Arguments
+        /// * `current_point` - Current slot or timestamp, matching `activation_type`
+        pub fn get_base_fee_numerator(&self, current_point: u64) -> Option<u128> {
+            let base_fee = &self.pool_fees.base_fee;
+            let cliff = base_fee.cliff_fee_numerator as u128;
+            if base_fee.period_frequency == 0 {
+                return Some(cliff);
+            }
+            // before activation the scheduler is considered fully elapsed
+            let period = if current_point < self.activation_point {
+                base_fee.number_of_period as u64
+            } else {
+                ((current_point - self.activation_point) / base_fee.period_frequency)
+                    .min(base_fee.number_of_period as u64)
+            };
+            if base_fee.fee_scheduler_mode == FEE_SCHEDULER_MODE_EXPONENTIAL {
+                get_fee_in_period(cliff, base_fee.reduction_factor, period as i32)
+            } else {
+                Some(cliff.saturating_sub(period as u128 * base_fee.reduction_factor as u128))
+            }
+        }
+        /// Volatility driven fee numerator, using the pool's last volatility accumulator
+        pub fn get_variable_fee_numerator(&self) -> Option<u128> {
+            let dynamic_fee = &self.pool_fees.dynamic_fee;
+            if dynamic_fee.initialized == 0 {
+                return Some(0);
+            }
+            let square_vfa_bin = dynamic_fee
+                .volatility_accumulator
+                .checked_mul(dynamic_fee.bin_step as u128)?
+                .checked_pow(2)?;
+            let v_fee = square_vfa_bin.checked_mul(dynamic_fee.variable_fee_control as u128)?;
+            Some(v_fee.checked_add(99_999_999_999)? / 100_000_000_000)
+        }
+        pub fn get_total_fee_numerator(&self, current_point: u64) -> Option<u128> {
+            Some(
+                (self.get_base_fee_numerator(current_point)?
+                    + self.get_variable_fee_numerator()?)
+                .min(MAX_FEE_NUMERATOR),
+            )
+        }
+
+        /// Quotes swapping exactly `amount_in`, fees included.
+        ///
+        /// # Arguments
+        /// * `current_point` - Current slot or timestamp, matching `activation_type`
+        pub fn quote_exact_in(
+            &self,
+            amount_in: u64,
+            a_to_b: bool,
+            current_point: u64,
+        ) -> Result<DammQuote, ()> {
+            let fee_numerator = self.get_total_fee_numerator(current_point).ok_or(())?;
+            let fee_on_input = !a_to_b && self.collect_fee_mode == COLLECT_FEE_MODE_ONLY_B;
+            let trading_fee_of = |amount: u64| -> u64 {
+                (amount as u128 * fee_numerator).div_ceil(FEE_DENOMINATOR) as u64
+            };
+
+            let (swap_amount, mut trading_fee) = if fee_on_input {
+                let fee = trading_fee_of(amount_in);
+                (amount_in - fee, fee)
+            } else {
+                (amount_in, 0)
+            };
+            let (next_sqrt_price, mut amount_out) = if a_to_b {
+                self.swap_a_to_b(swap_amount).ok_or(())?
+            } else {
+                self.swap_b_to_a(swap_amount).ok_or(())?
+            };
+            if !fee_on_input {
+                trading_fee = trading_fee_of(amount_out);
+                amount_out -= trading_fee;
+            }
+
+            Ok(DammQuote {
+                amount_in,
+                amount_out,
+                trading_fee,
+                protocol_fee: trading_fee * self.pool_fees.protocol_fee_percent as u64 / 100,
+                fee_on_input,
+                next_sqrt_price,
+            })
+        }
+
+        fn swap_a_to_b(&self, amount_in: u64) -> Option<(u128, u64)> {
+            // √P' = √P * L / (L + Δa * √P), rounded up
+            let liquidity = U256::from_u128(self.liquidity);
+            let sqrt_price = U256::from_u128(self.sqrt_price);
+            let denominator = liquidity
+                .checked_add(U256::from_u128(amount_in as u128).checked_mul(sqrt_price)?)?;
+            let next_sqrt_price = liquidity
+                .checked_mul(sqrt_price)?
+                .div_rounding(denominator, true)?
+                .to_u128()?;
+            if next_sqrt_price < self.sqrt_min_price {
+                return None;
+            }
+            // Δb = L * (√P - √P') >> 128, rounded down
+            let amount_out = liquidity
+                .checked_mul(U256::from_u128(self.sqrt_price - next_sqrt_price))?
+                .shr(128)
+                .to_u128()?;
+            Some((next_sqrt_price, u64::try_from(amount_out).ok()?))
+        }
+
+        fn swap_b_to_a(&self, amount_in: u64) -> Option<(u128, u64)> {
+            // √P' = √P + (Δb << 128) / L, rounded down
+            let delta = U256::from_u128(amount_in as u128)
+                .shl(128)
+                .div_rounding(U256::from_u128(self.liquidity), false)?
+                .to_u128()?;
+            let next_sqrt_price = self.sqrt_price.checked_add(delta)?;
+            if next_sqrt_price > self.sqrt_max_price {
+                return None;
+            }
+            // Δa = L * (√P' - √P) / (√P * √P'), rounded down
+            let amount_out = U256::from_u128(self.liquidity)
+                .checked_mul(U256::from_u128(next_sqrt_price - self.sqrt_price))?
+                .div_rounding(
+                    U256::from_u128(self.sqrt_price)
+                        .checked_mul(U256::from_u128(next_sqrt_price))?,
+                    false,
+                )?
+                .to_u128()?;
+            Some((next_sqrt_price, u64::try_from(amount_out).ok()?))
+        }
+    }
+
+    /// Cliff fee reduced by `reduction_factor` bps, compounded over `period` periods
+    /// in Q64.64
+    pub fn get_fee_in_period(cliff: u128, reduction_factor: u64, period: i32) -> Option<u128> {
+        if reduction_factor == 0 {
+            return Some(cliff);
+        }
+        let base = ONE_Q64.checked_sub(((reduction_factor as u128) << 64) / BASIS_POINT_MAX)?;
+        Some(pow_q64(base, period)?.checked_mul(cliff)? >> 64)
+    }
+
+    fn token_program_from_flag(flag: u8) -> Pubkey {
+        match flag {
+            1 => spl_token_2022::ID,
+            _ => spl_token::ID,
+        }
+    }
+
+    pub fn get_pool_authority_pda() -> Pubkey {
+        Pubkey::find_program_address(&[POOL_AUTHORITY_SEED], &METEORA_DAMM_V2).0
+    }
+    pub fn get_event_authority_pda() -> Pubkey {
+        Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], &METEORA_DAMM_V2).0
+    }
+
+    impl SwapParameters {
+        pub const DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+        pub fn data(&self) -> Result<Vec<u8>, ()> {
+            let mut data = Vec::with_capacity(24);
+            data.extend_from_slice(&Self::DISCRIMINATOR);
+            BorshSerialize::serialize(self, &mut data).map_err(|_| ())?;
+            Ok(data)
+        }
+    }
+
+    /// Builds a swap, the direction follows the input and output token accounts
+    pub fn swap(
+        payer: &Pubkey,
+        pool_address: &Pubkey,
+        pool: &Pool,
+        input_token_account: &Pubkey,
+        output_token_account: &Pubkey,
+        referral_token_account: Option<&Pubkey>,
+        args: SwapParameters,
+    ) -> Result<Instruction, ()> {
+        Ok(Instruction::new_with_bytes(
+            METEORA_DAMM_V2,
+            &args.data()?,
+            vec![
+                AccountMeta::new_readonly(get_pool_authority_pda(), false),
+                AccountMeta::new(*pool_address, false),
+                AccountMeta::new(*input_token_account, false),
+                AccountMeta::new(*output_token_account, false),
+                AccountMeta::new(pool.token_a_vault, false),
+                AccountMeta::new(pool.token_b_vault, false),
+                AccountMeta::new_readonly(pool.token_a_mint, false),
+                AccountMeta::new_readonly(pool.token_b_mint, false),
+                AccountMeta::new_readonly(*payer, true),
+                AccountMeta::new_readonly(pool.token_a_program(), false),
+                AccountMeta::new_readonly(pool.token_b_program(), false),
+                // anchor reads the program id as `None` for optional accounts
+                match referral_token_account {
+                    Some(x) => AccountMeta::new(*x, false),
+                    None => AccountMeta::new_readonly(METEORA_DAMM_V2, false),
+                },
+                AccountMeta::new_readonly(get_event_authority_pda(), false),
+                AccountMeta::new_readonly(METEORA_DAMM_V2, false),
+            ],
+        ))
+    }
+    /// Accounts of a swap, the direction follows the input and output token accounts
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct DammV2SwapAccounts {
+        #[serde_as(as = "DisplayFromStr")]
+        pub pool: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub input_token_account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub output_token_account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_a_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_b_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_a_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_b_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub payer: Pubkey,
+    }
+
+    impl DammV2SwapAccounts {
+        pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+            if accounts.len() < 9 {
+                return Err(());
+            }
+            Ok(Self {
+                pool: accounts[1],
+                input_token_account: accounts[2],
+                output_token_account: accounts[3],
+                token_a_vault: accounts[4],
+                token_b_vault: accounts[5],
+                token_a_mint: accounts[6],
+                token_b_mint: accounts[7],
+                payer: accounts[8],
+            })
+        }
+    }
+
+    /// A DAMM v2 swap decoded from a transaction
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum DammV2Instruction {
+        Swap {
+            args: SwapParameters,
+            accounts: DammV2SwapAccounts,
+        },
+    }
+
+    impl DammV2Instruction {
+        /// Decodes the instruction data and its resolved accounts
+        ///
+        /// # Returns
+        /// * `Err(())` - Not a swap, or truncated data or accounts
+        pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+            let mut args = data
+                .strip_prefix(&SwapParameters::DISCRIMINATOR)
+                .ok_or(())?;
+            Ok(Self::Swap {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: DammV2SwapAccounts::from_accounts(accounts)?,
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct DammV2InstructionEvent {
+        pub signature: String,
+        /// Index of the outer instruction
+        pub index: usize,
+        /// Position among the inner instructions, `None` for an outer instruction
+        pub inner_index: Option<usize>,
+        pub instruction: DammV2Instruction,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::v1::*;
+    use super::v2::{self, SwapParameters};
+    use crate::sol::sol_events::sol_platforms::test_utils::accounts;
+
+    fn vault(total_amount: u64) -> Vault {
+        Vault {
+            enabled: 1,
+            bumps: VaultBumps::default(),
+            total_amount,
+            token_vault: Pubkey::new_unique(),
+            fee_vault: Pubkey::new_unique(),
+            token_mint: Pubkey::new_unique(),
+            lp_mint: Pubkey::new_unique(),
+            strategies: [Pubkey::default(); 30],
+            base: Pubkey::default(),
+            admin: Pubkey::default(),
+            operator: Pubkey::default(),
+            locked_profit_tracker: LockedProfitTracker::default(),
+        }
+    }
+
+    fn v1_pool(curve_type: CurveType) -> Pool {
+        Pool {
+            lp_mint: Pubkey::new_unique(),
+            token_a_mint: Pubkey::new_unique(),
+            token_b_mint: Pubkey::new_unique(),
+            a_vault: Pubkey::new_unique(),
+            b_vault: Pubkey::new_unique(),
+            a_vault_lp: Pubkey::new_unique(),
+            b_vault_lp: Pubkey::new_unique(),
+            a_vault_lp_bump: 255,
+            enabled: true,
+            protocol_token_a_fee: Pubkey::new_unique(),
+            protocol_token_b_fee: Pubkey::new_unique(),
+            fee_last_updated_at: 0,
+            padding_0: [0; 24],
+            fees: PoolFees {
+                trade_fee_numerator: 25,
+                trade_fee_denominator: 10_000,
+                protocol_trade_fee_numerator: 20,
+                protocol_trade_fee_denominator: 100,
+            },
+            pool_type: PoolType::Permissionless,
+            stake: Pubkey::default(),
+            total_locked_lp: 0,
+            bootstrapping: Bootstrapping::default(),
+            partner_info: PartnerInfo::default(),
+            padding: Padding {
+                padding_0: [0; 6],
+                padding_1: [0; 21],
+                padding_2: [0; 21],
+            },
+            curve_type,
+        }
+    }
+
+    /// The pool owns half of a 1e12 vault a and a tenth of a 2e12 vault b
+    fn pool_vaults() -> PoolVaults {
+        PoolVaults {
+            a_vault: vault(1_000_000_000_000),
+            b_vault: vault(2_000_000_000_000),
+            a_vault_lp_amount: 500_000_000_000,
+            b_vault_lp_amount: 100_000_000_000,
+            a_vault_lp_supply: 1_000_000_000_000,
+            b_vault_lp_supply: 1_000_000_000_000,
+        }
+    }
+
+    #[test]
+    fn v1_pool_round_trips() {
+        let pool = v1_pool(CurveType::ConstantProduct);
+        let mut data = POOL_ACCOUNT_DISCRIMINATOR.to_vec();
+        data.extend(borsh::to_vec(&pool).unwrap());
+        // accounts are allocated with trailing space
+        data.extend([0; 64]);
+        let decoded = Pool::from_bytes(&data).unwrap();
+        assert_eq!(decoded.a_vault_lp, pool.a_vault_lp);
+        assert_eq!(decoded.fees.trade_fee_numerator, 25);
+        assert!(matches!(decoded.curve_type, CurveType::ConstantProduct));
+        assert!(Pool::from_bytes(&data[8..]).is_err());
+    }
+
+    #[test]
+    fn v1_vault_unlocks_profit_linearly() {
+        let mut vault = vault(10_000);
+        vault.locked_profit_tracker = LockedProfitTracker {
+            last_updated_locked_profit: 1_000,
+            last_report: 100,
+            // fully unlocked 1000 seconds after the report
+            locked_profit_degradation: 1_000_000_000,
+        };
+        assert_eq!(vault.unlocked_amount(100), Some(9_000));
+        assert_eq!(vault.unlocked_amount(600), Some(9_500));
+        assert_eq!(vault.unlocked_amount(1_100), Some(10_000));
+        assert_eq!(vault.unlocked_amount(5_000), Some(10_000));
+        assert_eq!(vault.unlocked_amount(99), None);
+    }
+
+    #[test]
+    fn v1_quote_exact_in() {
+        let pool = v1_pool(CurveType::ConstantProduct);
+        let vaults = pool_vaults();
+        assert_eq!(vaults.reserves(0), Some((500_000_000_000, 200_000_000_000)));
+
+        let quote = pool.quote_exact_in(&vaults, 1_000_000, true, 0).unwrap();
+        // 25 bps of trade fee, a fifth of it to the protocol
+        assert_eq!(quote.protocol_fee, 500);
+        assert_eq!(quote.trade_fee, 2_000);
+        // 997_500 in: 398_999 out of the curve, less 1 from the vault lp rounding
+        assert_eq!(quote.amount_out, 398_998);
+
+        let quote = pool.quote_exact_in(&vaults, 1_000, false, 0).unwrap();
+        // the fee rounds down to 2 and the protocol share up to 1
+        assert_eq!((quote.trade_fee, quote.protocol_fee), (1, 1));
+        // 999 deposited in vault b mint 499 lp worth 998, 997 once the fee is kept
+        assert_eq!(
+            constant_product_swap(997, 200_000_000_000, 500_000_000_000),
+            Some(2_492)
+        );
+        assert_eq!(quote.amount_out, 2_492);
+    }
+
+    #[test]
+    fn v1_quote_rejects_stable_pools() {
+        let pool = v1_pool(CurveType::Stable {
+            amp: 100,
+            token_multiplier: TokenMultiplier::default(),
+            depeg: Depeg {
+                base_virtual_price: 0,
+                base_cache_updated: 0,
+                depeg_type: DepegType::None,
+            },
+            last_amp_updated_timestamp: 0,
+        });
+        assert!(
+            pool.quote_exact_in(&pool_vaults(), 1_000_000, true, 0)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn v1_swap_accounts() {
+        let pool = v1_pool(CurveType::ConstantProduct);
+        let vaults = pool_vaults();
+        let (payer, pool_address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let (source, destination) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let args = SwapArgs {
+            in_amount: 1_000_000,
+            minimum_out_amount: 398_000,
+        };
+        let ix = swap(
+            &payer,
+            &pool_address,
+            &pool,
+            &vaults,
+            &source,
+            &destination,
+            false,
+            args,
+        )
+        .unwrap();
+        assert_eq!(ix.program_id, METEORA_DAMM_V1);
+        assert_eq!(ix.accounts.len(), 15);
+        assert_eq!(ix.accounts[11].pubkey, pool.protocol_token_b_fee);
+        assert!(ix.accounts[12].is_signer);
+        assert_eq!(&ix.data[..8], &SwapArgs::DISCRIMINATOR);
+        assert_eq!(ix.data[8..16], 1_000_000u64.to_le_bytes());
+    }
+
+    fn v2_pool(base_fee: v2::BaseFeeStruct, collect_fee_mode: u8) -> v2::Pool {
+        v2::Pool {
+            pool_fees: v2::PoolFeesStruct {
+                base_fee,
+                protocol_fee_percent: 20,
+                partner_fee_percent: 0,
+                referral_fee_percent: 20,
+                padding_0: [0; 5],
+                dynamic_fee: v2::DynamicFeeStruct::default(),
+                padding_1: [0; 2],
+            },
+            token_a_mint: Pubkey::new_unique(),
+            token_b_mint: Pubkey::new_unique(),
+            token_a_vault: Pubkey::new_unique(),
+            token_b_vault: Pubkey::new_unique(),
+            whitelisted_vault: Pubkey::default(),
+            partner: Pubkey::default(),
+            // 1e12 of token a at a price of 4
+            liquidity: 2_000_000_000_000 << 64,
+            padding: 0,
+            protocol_a_fee: 0,
+            protocol_b_fee: 0,
+            partner_a_fee: 0,
+            partner_b_fee: 0,
+            sqrt_min_price: 4_295_048_016,
+            sqrt_max_price: 4 << 64,
+            sqrt_price: 2 << 64,
+            activation_point: 1_000,
+            activation_type: 1,
+            pool_status: 0,
+            token_a_flag: 0,
+            token_b_flag: 0,
+            collect_fee_mode,
+            pool_type: 0,
+            padding_0: [0; 2],
+            fee_a_per_liquidity: [0; 32],
+            fee_b_per_liquidity: [0; 32],
+            permanent_lock_liquidity: 0,
+            metrics: v2::PoolMetrics::default(),
+            creator: Pubkey::default(),
+        }
+    }
+
+    /// A 50% cliff fee decaying every 60 seconds over 120 periods
+    fn fee_scheduler(fee_scheduler_mode: u8, reduction_factor: u64) -> v2::BaseFeeStruct {
+        v2::BaseFeeStruct {
+            cliff_fee_numerator: 500_000_000,
+            fee_scheduler_mode,
+            padding_0: [0; 5],
+            number_of_period: 120,
+            period_frequency: 60,
+            reduction_factor,
+            padding_1: 0,
+        }
+    }
+
+    /// A flat 0.25% base fee with a volatility accumulator worth 0.04%
+    fn dynamic_fee_pool(collect_fee_mode: u8) -> v2::Pool {
+        let mut pool = v2_pool(
+            v2::BaseFeeStruct {
+                cliff_fee_numerator: 2_500_000,
+                ..Default::default()
+            },
+            collect_fee_mode,
+        );
+        pool.pool_fees.dynamic_fee = v2::DynamicFeeStruct {
+            initialized: 1,
+            variable_fee_control: 40_000,
+            bin_step: 100,
+            volatility_accumulator: 10_000,
+            ..Default::default()
+        };
+        pool
+    }
+
+    #[test]
+    fn v2_exponential_fee_scheduler_compounds_in_q64() {
+        let pool = v2_pool(fee_scheduler(v2::FEE_SCHEDULER_MODE_EXPONENTIAL, 100), 0);
+        assert_eq!(pool.get_base_fee_numerator(1_000), Some(500_000_000));
+        assert_eq!(pool.get_base_fee_numerator(1_119), Some(495_000_000));
+        // 10 periods of 1%, rounding each period would give 452_191_034
+        assert_eq!(pool.get_base_fee_numerator(1_600), Some(452_191_037));
+        assert_eq!(pool.get_base_fee_numerator(4_000), Some(302_503_033));
+        // the scheduler stops at the last period and counts as elapsed before activation
+        assert_eq!(pool.get_base_fee_numerator(1_000_000), Some(149_690_195));
+        assert_eq!(pool.get_base_fee_numerator(999), Some(149_690_195));
+        assert_eq!(v2::get_fee_in_period(500_000_000, 0, 10), Some(500_000_000));
+    }
+
+    #[test]
+    fn v2_linear_fee_scheduler() {
+        let pool = v2_pool(fee_scheduler(0, 1_000_000), 0);
+        assert_eq!(pool.get_base_fee_numerator(1_000), Some(500_000_000));
+        assert_eq!(pool.get_base_fee_numerator(1_600), Some(490_000_000));
+        assert_eq!(pool.get_base_fee_numerator(999), Some(380_000_000));
+    }
+
+    #[test]
+    fn v2_dynamic_fee() {
+        let mut pool = dynamic_fee_pool(0);
+        // ceil((10_000 * 100)^2 * 40_000 / 1e11)
+        assert_eq!(pool.get_variable_fee_numerator(), Some(400_000));
+        assert_eq!(pool.get_total_fee_numerator(0), Some(2_900_000));
+
+        pool.pool_fees.dynamic_fee.volatility_accumulator = 123_456;
+        pool.pool_fees.dynamic_fee.bin_step = 80;
+        pool.pool_fees.dynamic_fee.variable_fee_control = 100_000;
+        assert_eq!(pool.get_variable_fee_numerator(), Some(97_544_858));
+
+        // the total is capped at 50%
+        pool.pool_fees.base_fee.cliff_fee_numerator = 500_000_000;
+        assert_eq!(pool.get_total_fee_numerator(0), Some(500_000_000));
+
+        pool.pool_fees.dynamic_fee.initialized = 0;
+        assert_eq!(pool.get_variable_fee_numerator(), Some(0));
+    }
+
+    #[test]
+    fn v2_quote_exact_in() {
+        let pool = dynamic_fee_pool(0);
+        let quote = pool.quote_exact_in(1_000_000_000, true, 0).unwrap();
+        assert!(!quote.fee_on_input);
+        assert_eq!(quote.amount_out, 3_984_415_584);
+        assert_eq!(
+            (quote.trading_fee, quote.protocol_fee),
+            (11_588_412, 2_317_682)
+        );
+        assert_eq!(quote.next_sqrt_price, 36_856_631_515_903_200_032);
+
+        let quote = pool.quote_exact_in(1_000_000_000, false, 0).unwrap();
+        assert_eq!(quote.amount_out, 249_212_696);
+        assert_eq!((quote.trading_fee, quote.protocol_fee), (724_819, 144_963));
+        assert_eq!(quote.next_sqrt_price, 36_902_711_519_455_958_007);
+
+        // fees only in token b are taken from the input when selling b
+        let pool = dynamic_fee_pool(v2::COLLECT_FEE_MODE_ONLY_B);
+        let quote = pool.quote_exact_in(1_000_000_000, false, 0).unwrap();
+        assert!(quote.fee_on_input);
+        assert_eq!(quote.amount_out, 249_212_877);
+        assert_eq!(
+            (quote.trading_fee, quote.protocol_fee),
+            (2_900_000, 580_000)
+        );
+        assert_eq!(quote.next_sqrt_price, 36_902_684_771_677_051_128);
+        let quote = pool.quote_exact_in(1_000_000_000, true, 0).unwrap();
+        assert!(!quote.fee_on_input);
+        assert_eq!(quote.amount_out, 3_984_415_584);
+
+        // past the maximum price of 16
+        assert!(pool.quote_exact_in(u64::MAX, false, 0).is_err());
+    }
+
+    #[test]
+    fn decodes_v1_and_v2_swaps() {
+        let keys = accounts(15);
+        let data = SwapArgs {
+            in_amount: 1_000_000,
+            minimum_out_amount: 990_000,
+        }
+        .data()
+        .unwrap();
+        let DammV1Instruction::Swap { args, accounts } =
+            DammV1Instruction::decode(&data, &keys).unwrap();
+        assert_eq!(args.in_amount, 1_000_000);
+        assert_eq!(accounts.pool, keys[0]);
+        assert_eq!(accounts.user_source_token, keys[1]);
+        assert_eq!(accounts.b_vault, keys[4]);
+        assert_eq!(accounts.protocol_token_fee, keys[11]);
+        assert_eq!(accounts.user, keys[12]);
+        assert!(DammV1Instruction::decode(&data, &keys[..12]).is_err());
+
+        let data = SwapParameters {
+            amount_in: 1_000_000,
+            minimum_amount_out: 990_000,
+        }
+        .data()
+        .unwrap();
+        let v2::DammV2Instruction::Swap { args, accounts } =
+            v2::DammV2Instruction::decode(&data, &keys[..14]).unwrap();
+        assert_eq!(args.minimum_amount_out, 990_000);
+        assert_eq!(accounts.pool, keys[1]);
+        assert_eq!(accounts.input_token_account, keys[2]);
+        assert_eq!(accounts.token_b_mint, keys[7]);
+        assert_eq!(accounts.payer, keys[8]);
+        assert!(v2::DammV2Instruction::decode(&data[..16], &keys).is_err());
+        assert!(v2::DammV2Instruction::decode(&[0; 24], &keys).is_err());
+    }
+}
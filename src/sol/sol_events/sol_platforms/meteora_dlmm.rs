@@ -0,0 +1,840 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_pubkey::pubkey;
+use solana_sdk::pubkey::Pubkey;
+
+use crate::sol::sol_events::EVENT_IX_TAG;
+use crate::sol::sol_math::{mul_shr, shl_div};
+
+pub const METEORA_DLMM_ADDRESS: &str = "LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo";
+pub const METEORA_DLMM: Pubkey = pubkey!("LBUZKhRxPF3XUpBCjp4YzTKgLccjZhTSDM9YuVaPwxo");
+
+pub const SWAP_EVENT_DISCRIMINATOR: [u8; 8] = [81, 108, 227, 190, 205, 208, 10, 196];
+pub const LB_PAIR_ACCOUNT_DISCRIMINATOR: [u8; 8] = [33, 11, 49, 98, 181, 101, 177, 13];
+pub const BIN_ARRAY_ACCOUNT_DISCRIMINATOR: [u8; 8] = [92, 142, 92, 220, 5, 148, 70, 181];
+
+/// Seed for bin array PDAs
+pub const BIN_ARRAY_SEED: &[u8] = b"bin_array";
+
+pub const MAX_BIN_PER_ARRAY: i32 = 70;
+pub const BASIS_POINT_MAX: u128 = 10_000;
+/// Fee rates are expressed over 1e9
+pub const FEE_PRECISION: u128 = 1_000_000_000;
+/// Fee rates are capped at 10%
+pub const MAX_FEE_RATE: u128 = 100_000_000;
+/// Bin prices are Q64.64
+pub const SCALE_OFFSET: u32 = 64;
+
+/// The `Swap` anchor event, recent program versions emit it through a self CPI
+/// (`emit_cpi!`) instead of a `Program data:` log
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct DlmmSwapEvent {
+    #[borsh(skip)]
+    pub signature: String,
+    #[serde_as(as = "DisplayFromStr")]
+    pub lb_pair: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub from: Pubkey,
+    pub start_bin_id: i32,
+    pub end_bin_id: i32,
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// True when token x is sold for token y
+    pub swap_for_y: bool,
+    pub fee: u64,
+    pub protocol_fee: u64,
+    pub fee_bps: u128,
+    pub host_fee: u64,
+}
+
+impl DlmmSwapEvent {
+    /// Decodes the event out of the data of its self CPI: the event instruction
+    /// tag, the event discriminator then the fields
+    pub fn from_cpi_data(data: &[u8]) -> Option<Self> {
+        let data = data
+            .strip_prefix(&EVENT_IX_TAG)?
+            .strip_prefix(&SWAP_EVENT_DISCRIMINATOR)?;
+        Self::try_from_slice(data).ok()
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct StaticParameters {
+    pub base_factor: u16,
+    pub filter_period: u16,
+    pub decay_period: u16,
+    pub reduction_factor: u16,
+    pub variable_fee_control: u32,
+    pub max_volatility_accumulator: u32,
+    pub min_bin_id: i32,
+    pub max_bin_id: i32,
+    pub protocol_share: u16,
+    pub base_fee_power_factor: u8,
+    pub padding: [u8; 5],
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct VariableParameters {
+    pub volatility_accumulator: u32,
+    pub volatility_reference: u32,
+    pub index_reference: i32,
+    pub padding: [u8; 4],
+    pub last_update_timestamp: i64,
+    pub padding1: [u8; 8],
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct ProtocolFee {
+    pub amount_x: u64,
+    pub amount_y: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct RewardInfo {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub funder: Pubkey,
+    pub reward_duration: u64,
+    pub reward_duration_end: u64,
+    pub reward_rate: u128,
+    pub last_update_time: u64,
+    pub cumulative_seconds_with_empty_liquidity_reward: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct LbPair {
+    pub parameters: StaticParameters,
+    pub v_parameters: VariableParameters,
+    pub bump_seed: [u8; 1],
+    pub bin_step_seed: [u8; 2],
+    pub pair_type: u8,
+    /// Bin holding the current price
+    pub active_id: i32,
+    /// Price increment between two bins, in basis points
+    pub bin_step: u16,
+    pub status: u8,
+    pub require_base_factor_seed: u8,
+    pub base_factor_seed: [u8; 2],
+    pub activation_type: u8,
+    pub creator_pool_on_off_control: u8,
+    pub token_x_mint: Pubkey,
+    pub token_y_mint: Pubkey,
+    pub reserve_x: Pubkey,
+    pub reserve_y: Pubkey,
+    pub protocol_fee: ProtocolFee,
+    pub padding1: [u8; 32],
+    pub reward_infos: [RewardInfo; 2],
+    pub oracle: Pubkey,
+    pub bin_array_bitmap: [u64; 16],
+    pub last_updated_at: i64,
+    pub padding2: [u8; 32],
+    pub pre_activation_swap_address: Pubkey,
+    pub base_key: Pubkey,
+    pub activation_point: u64,
+    pub pre_activation_duration: u64,
+    pub padding3: [u8; 8],
+    pub padding4: u64,
+    pub creator: Pubkey,
+    pub token_mint_x_program_flag: u8,
+    pub token_mint_y_program_flag: u8,
+    pub reserved: [u8; 22],
+}
+
+impl LbPair {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        if data.len() < 8 || data[..8] != LB_PAIR_ACCOUNT_DISCRIMINATOR {
+            return Err(());
+        }
+        Self::deserialize(&mut &data[8..]).map_err(|_| ())
+    }
+    pub async fn fetch(rpc: &RpcClient, lb_pair: &Pubkey) -> Result<Self, ()> {
+        let account = rpc.get_account(lb_pair).await.map_err(|_| ())?;
+
+        if account.owner != METEORA_DLMM {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+
+    /// Base fee rate over `FEE_PRECISION`
+    pub fn get_base_fee(&self) -> u128 {
+        self.parameters.base_factor as u128
+            * self.bin_step as u128
+            * 10
+            * 10u128.pow(self.parameters.base_fee_power_factor as u32)
+    }
+    /// Volatility driven fee rate over `FEE_PRECISION`
+    pub fn get_variable_fee(&self, v_parameters: &VariableParameters) -> u128 {
+        if self.parameters.variable_fee_control == 0 {
+            return 0;
+        }
+        let square_vfa_bin =
+            (v_parameters.volatility_accumulator as u128 * self.bin_step as u128).pow(2);
+        let v_fee = self.parameters.variable_fee_control as u128 * square_vfa_bin;
+        v_fee.div_ceil(100_000_000_000)
+    }
+    pub fn get_total_fee(&self, v_parameters: &VariableParameters) -> u128 {
+        (self.get_base_fee() + self.get_variable_fee(v_parameters)).min(MAX_FEE_RATE)
+    }
+    /// Protocol share of a fee, rounded down for each bin like the program does
+    fn compute_protocol_fee(&self, fee: u128) -> u128 {
+        fee * self.parameters.protocol_share as u128 / BASIS_POINT_MAX
+    }
+
+    /// Decays the volatility reference the same way the program does at the start of a swap
+    fn update_references(&self, v_parameters: &mut VariableParameters, current_timestamp: i64) {
+        let elapsed = current_timestamp - v_parameters.last_update_timestamp;
+        if elapsed >= self.parameters.filter_period as i64 {
+            v_parameters.index_reference = self.active_id;
+            v_parameters.volatility_reference = if elapsed < self.parameters.decay_period as i64 {
+                (v_parameters.volatility_accumulator as u128
+                    * self.parameters.reduction_factor as u128
+                    / BASIS_POINT_MAX) as u32
+            } else {
+                0
+            };
+        }
+    }
+    fn update_volatility_accumulator(&self, v_parameters: &mut VariableParameters, active_id: i32) {
+        let delta_id = (v_parameters.index_reference as i64 - active_id as i64).unsigned_abs();
+        let accumulator =
+            v_parameters.volatility_reference as u128 + delta_id as u128 * BASIS_POINT_MAX;
+        v_parameters.volatility_accumulator =
+            accumulator.min(self.parameters.max_volatility_accumulator as u128) as u32;
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct Bin {
+    pub amount_x: u64,
+    pub amount_y: u64,
+    /// Q64.64 price of token x in token y
+    pub price: u128,
+    pub liquidity_supply: u128,
+    pub reward_per_token_stored: [u128; 2],
+    pub fee_amount_x_per_token_stored: u128,
+    pub fee_amount_y_per_token_stored: u128,
+    pub amount_x_in: u128,
+    pub amount_y_in: u128,
+}
+
+impl Bin {
+    /// Amount of token x (or y) this bin can fill, excluding fees
+    fn get_max_amount_in(&self, swap_for_y: bool) -> Option<u128> {
+        if swap_for_y {
+            shl_div(self.amount_y as u128, self.price, SCALE_OFFSET, true)
+        } else {
+            mul_shr(self.amount_x as u128, self.price, SCALE_OFFSET, true)
+        }
+    }
+    fn get_amount_out(&self, amount_in: u128, swap_for_y: bool) -> Option<u128> {
+        if swap_for_y {
+            mul_shr(amount_in, self.price, SCALE_OFFSET, false)
+        } else {
+            shl_div(amount_in, self.price, SCALE_OFFSET, false)
+        }
+    }
+    fn get_amount_in(&self, amount_out: u128, swap_for_y: bool) -> Option<u128> {
+        if swap_for_y {
+            shl_div(amount_out, self.price, SCALE_OFFSET, true)
+        } else {
+            mul_shr(amount_out, self.price, SCALE_OFFSET, true)
+        }
+    }
+    fn get_max_amount_out(&self, swap_for_y: bool) -> u128 {
+        if swap_for_y {
+            self.amount_y as u128
+        } else {
+            self.amount_x as u128
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct BinArray {
+    pub index: i64,
+    pub version: u8,
+    pub padding: [u8; 7],
+    pub lb_pair: Pubkey,
+    pub bins: [Bin; 70],
+}
+
+impl BinArray {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        if data.len() < 8 || data[..8] != BIN_ARRAY_ACCOUNT_DISCRIMINATOR {
+            return Err(());
+        }
+        Self::deserialize(&mut &data[8..]).map_err(|_| ())
+    }
+    /// Index of the bin array holding `bin_id`
+    pub fn bin_id_to_bin_array_index(bin_id: i32) -> i64 {
+        bin_id.div_euclid(MAX_BIN_PER_ARRAY) as i64
+    }
+    pub fn get_bin_array_pda(lb_pair: &Pubkey, index: i64) -> Pubkey {
+        Pubkey::find_program_address(
+            &[BIN_ARRAY_SEED, lb_pair.as_ref(), &index.to_le_bytes()],
+            &METEORA_DLMM,
+        )
+        .0
+    }
+    fn get_bin(&self, bin_id: i32) -> Option<&Bin> {
+        let offset = bin_id as i64 - self.index * MAX_BIN_PER_ARRAY as i64;
+        self.bins.get(usize::try_from(offset).ok()?)
+    }
+    /// Fetches the `count` bin arrays a swap walks through starting at the active bin,
+    /// arrays that were never initialized are skipped
+    pub async fn fetch_for_swap(
+        rpc: &RpcClient,
+        lb_pair_address: &Pubkey,
+        lb_pair: &LbPair,
+        swap_for_y: bool,
+        count: i64,
+    ) -> Result<Vec<Self>, ()> {
+        let start = Self::bin_id_to_bin_array_index(lb_pair.active_id);
+        let keys = (0..count)
+            .map(|i| {
+                let index = if swap_for_y { start - i } else { start + i };
+                Self::get_bin_array_pda(lb_pair_address, index)
+            })
+            .collect::<Vec<_>>();
+        let accounts = rpc.get_multiple_accounts(&keys).await.map_err(|_| ())?;
+        Ok(accounts
+            .into_iter()
+            .flatten()
+            .filter_map(|account| Self::from_bytes(&account.data).ok())
+            .collect())
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+pub struct DlmmQuote {
+    /// Amount of input token including fees
+    pub amount_in: u64,
+    pub amount_out: u64,
+    /// Total fee paid in the input token
+    pub fee: u64,
+    /// Share of `fee` going to the protocol
+    pub protocol_fee: u64,
+    /// Active bin once the swap is done
+    pub end_bin_id: i32,
+}
+
+fn find_bin(bin_arrays: &[BinArray], bin_id: i32) -> Option<&Bin> {
+    let index = BinArray::bin_id_to_bin_array_index(bin_id);
+    bin_arrays
+        .iter()
+        .find(|x| x.index == index)
+        .and_then(|x| x.get_bin(bin_id))
+}
+
+/// Fee to add on top of an amount that excludes fees
+fn compute_fee(amount: u128, fee_rate: u128) -> Option<u128> {
+    let denominator = FEE_PRECISION.checked_sub(fee_rate)?;
+    Some((amount * fee_rate).div_ceil(denominator))
+}
+
+/// Fee included in an amount that includes fees
+fn compute_fee_from_amount(amount_with_fees: u128, fee_rate: u128) -> u128 {
+    (amount_with_fees * fee_rate).div_ceil(FEE_PRECISION)
+}
+
+/// Quotes swapping exactly `amount_in`, walking bins from the active one like the program.
+///
+/// # Arguments
+/// * `bin_arrays` - Bin arrays from the active bin in the swap direction, see `BinArray::fetch_for_swap`
+/// * `current_timestamp` - Unix timestamp used to decay the volatility, as the program does
+///
+/// # Returns
+/// * `Err(())` - The provided bin arrays do not hold enough liquidity
+pub fn quote_exact_in(
+    lb_pair: &LbPair,
+    bin_arrays: &[BinArray],
+    amount_in: u64,
+    swap_for_y: bool,
+    current_timestamp: i64,
+) -> Result<DlmmQuote, ()> {
+    let mut v_parameters = lb_pair.v_parameters;
+    lb_pair.update_references(&mut v_parameters, current_timestamp);
+
+    let mut active_id = lb_pair.active_id;
+    let mut amount_left = amount_in as u128;
+    let mut amount_out: u128 = 0;
+    let mut total_fee: u128 = 0;
+    let mut protocol_fee: u128 = 0;
+
+    while amount_left > 0 {
+        if active_id < lb_pair.parameters.min_bin_id || active_id > lb_pair.parameters.max_bin_id {
+            return Err(());
+        }
+        let bin = find_bin(bin_arrays, active_id).ok_or(())?;
+        if bin.get_max_amount_out(swap_for_y) > 0 {
+            lb_pair.update_volatility_accumulator(&mut v_parameters, active_id);
+            let fee_rate = lb_pair.get_total_fee(&v_parameters);
+
+            let max_amount_in = bin.get_max_amount_in(swap_for_y).ok_or(())?;
+            let max_fee = compute_fee(max_amount_in, fee_rate).ok_or(())?;
+            let max_amount_in = max_amount_in + max_fee;
+
+            let (amount_in_with_fees, out, fee) = if amount_left >= max_amount_in {
+                (max_amount_in, bin.get_max_amount_out(swap_for_y), max_fee)
+            } else {
+                let fee = compute_fee_from_amount(amount_left, fee_rate);
+                let out = bin
+                    .get_amount_out(amount_left - fee, swap_for_y)
+                    .ok_or(())?;
+                (
+                    amount_left,
+                    out.min(bin.get_max_amount_out(swap_for_y)),
+                    fee,
+                )
+            };
+            amount_left -= amount_in_with_fees;
+            amount_out += out;
+            total_fee += fee;
+            protocol_fee += lb_pair.compute_protocol_fee(fee);
+        }
+        if amount_left > 0 {
+            active_id = if swap_for_y {
+                active_id - 1
+            } else {
+                active_id + 1
+            };
+        }
+    }
+
+    Ok(DlmmQuote {
+        amount_in,
+        amount_out: u64::try_from(amount_out).map_err(|_| ())?,
+        fee: u64::try_from(total_fee).map_err(|_| ())?,
+        protocol_fee: u64::try_from(protocol_fee).map_err(|_| ())?,
+        end_bin_id: active_id,
+    })
+}
+
+/// Quotes the input needed to receive exactly `amount_out`, fees included.
+///
+/// # Returns
+/// * `Err(())` - The provided bin arrays do not hold enough liquidity
+pub fn quote_exact_out(
+    lb_pair: &LbPair,
+    bin_arrays: &[BinArray],
+    amount_out: u64,
+    swap_for_y: bool,
+    current_timestamp: i64,
+) -> Result<DlmmQuote, ()> {
+    let mut v_parameters = lb_pair.v_parameters;
+    lb_pair.update_references(&mut v_parameters, current_timestamp);
+
+    let mut active_id = lb_pair.active_id;
+    let mut amount_out_left = amount_out as u128;
+    let mut amount_in: u128 = 0;
+    let mut total_fee: u128 = 0;
+    let mut protocol_fee: u128 = 0;
+
+    while amount_out_left > 0 {
+        if active_id < lb_pair.parameters.min_bin_id || active_id > lb_pair.parameters.max_bin_id {
+            return Err(());
+        }
+        let bin = find_bin(bin_arrays, active_id).ok_or(())?;
+        let max_amount_out = bin.get_max_amount_out(swap_for_y);
+        if max_amount_out > 0 {
+            lb_pair.update_volatility_accumulator(&mut v_parameters, active_id);
+            let fee_rate = lb_pair.get_total_fee(&v_parameters);
+
+            let (amount_in_bin, out) = if amount_out_left >= max_amount_out {
+                (bin.get_max_amount_in(swap_for_y).ok_or(())?, max_amount_out)
+            } else {
+                (
+                    bin.get_amount_in(amount_out_left, swap_for_y).ok_or(())?,
+                    amount_out_left,
+                )
+            };
+            let fee = compute_fee(amount_in_bin, fee_rate).ok_or(())?;
+            amount_in += amount_in_bin + fee;
+            total_fee += fee;
+            protocol_fee += lb_pair.compute_protocol_fee(fee);
+            amount_out_left -= out;
+        }
+        if amount_out_left > 0 {
+            active_id = if swap_for_y {
+                active_id - 1
+            } else {
+                active_id + 1
+            };
+        }
+    }
+
+    Ok(DlmmQuote {
+        amount_in: u64::try_from(amount_in).map_err(|_| ())?,
+        amount_out,
+        fee: u64::try_from(total_fee).map_err(|_| ())?,
+        protocol_fee: u64::try_from(protocol_fee).map_err(|_| ())?,
+        end_bin_id: active_id,
+    })
+}
+
+/// Args of swap and swap2, swap2 appends the remaining accounts info
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SwapArgs {
+    pub amount_in: u64,
+    pub min_amount_out: u64,
+}
+
+/// Args of swap_exact_out and swap_exact_out2
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SwapExactOutArgs {
+    pub max_in_amount: u64,
+    pub out_amount: u64,
+}
+
+/// Args of swap_with_price_impact and swap_with_price_impact2
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct SwapWithPriceImpactArgs {
+    pub amount_in: u64,
+    pub active_id: Option<i32>,
+    pub max_price_impact_bps: u16,
+}
+
+pub const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+pub const SWAP2_DISCRIMINATOR: [u8; 8] = [65, 75, 63, 76, 235, 91, 91, 136];
+pub const SWAP_EXACT_OUT_DISCRIMINATOR: [u8; 8] = [250, 73, 101, 33, 38, 207, 75, 184];
+pub const SWAP_EXACT_OUT2_DISCRIMINATOR: [u8; 8] = [43, 215, 247, 132, 137, 60, 243, 81];
+pub const SWAP_WITH_PRICE_IMPACT_DISCRIMINATOR: [u8; 8] = [56, 173, 230, 208, 173, 228, 156, 205];
+pub const SWAP_WITH_PRICE_IMPACT2_DISCRIMINATOR: [u8; 8] = [74, 98, 192, 214, 177, 51, 75, 51];
+
+/// Accounts shared by every swap instruction, the `2` versions only append the
+/// memo program
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DlmmSwapAccounts {
+    #[serde_as(as = "DisplayFromStr")]
+    pub lb_pair: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub reserve_x: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub reserve_y: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_token_in: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_token_out: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub token_x_mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub token_y_mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+}
+
+impl DlmmSwapAccounts {
+    pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+        if accounts.len() < 11 {
+            return Err(());
+        }
+        Ok(Self {
+            lb_pair: accounts[0],
+            reserve_x: accounts[2],
+            reserve_y: accounts[3],
+            user_token_in: accounts[4],
+            user_token_out: accounts[5],
+            token_x_mint: accounts[6],
+            token_y_mint: accounts[7],
+            user: accounts[10],
+        })
+    }
+}
+
+/// A DLMM swap decoded from a transaction, the `2` versions of the instructions
+/// decode to the same variants
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum DlmmInstruction {
+    Swap {
+        args: SwapArgs,
+        accounts: DlmmSwapAccounts,
+    },
+    SwapExactOut {
+        args: SwapExactOutArgs,
+        accounts: DlmmSwapAccounts,
+    },
+    SwapWithPriceImpact {
+        args: SwapWithPriceImpactArgs,
+        accounts: DlmmSwapAccounts,
+    },
+}
+
+impl DlmmInstruction {
+    /// Decodes the instruction data and its resolved accounts
+    ///
+    /// # Returns
+    /// * `Err(())` - Not a swap, or truncated data or accounts
+    pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+        if data.len() < 8 {
+            return Err(());
+        }
+        let (discriminator, mut args) = data.split_at(8);
+        Ok(match <[u8; 8]>::try_from(discriminator).map_err(|_| ())? {
+            SWAP_DISCRIMINATOR | SWAP2_DISCRIMINATOR => Self::Swap {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: DlmmSwapAccounts::from_accounts(accounts)?,
+            },
+            SWAP_EXACT_OUT_DISCRIMINATOR | SWAP_EXACT_OUT2_DISCRIMINATOR => Self::SwapExactOut {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: DlmmSwapAccounts::from_accounts(accounts)?,
+            },
+            SWAP_WITH_PRICE_IMPACT_DISCRIMINATOR | SWAP_WITH_PRICE_IMPACT2_DISCRIMINATOR => {
+                Self::SwapWithPriceImpact {
+                    args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                    accounts: DlmmSwapAccounts::from_accounts(accounts)?,
+                }
+            }
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct DlmmInstructionEvent {
+    pub signature: String,
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions, `None` for an outer instruction
+    pub inner_index: Option<usize>,
+    pub instruction: DlmmInstruction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::accounts;
+
+    fn swap_event() -> DlmmSwapEvent {
+        DlmmSwapEvent {
+            signature: String::new(),
+            lb_pair: Pubkey::new_from_array([1; 32]),
+            from: Pubkey::new_from_array([2; 32]),
+            start_bin_id: -4410,
+            end_bin_id: -4408,
+            amount_in: 1_000_000_000,
+            amount_out: 151_234_567,
+            swap_for_y: true,
+            fee: 2_500_000,
+            protocol_fee: 125_000,
+            fee_bps: 25,
+            host_fee: 0,
+        }
+    }
+
+    #[test]
+    fn decodes_the_swap_event_of_the_self_cpi() {
+        let event = swap_event();
+        let mut data = [EVENT_IX_TAG, SWAP_EVENT_DISCRIMINATOR].concat();
+        data.extend(borsh::to_vec(&event).unwrap());
+        // tag + discriminator + 2 keys + 2 i32 + 2 u64 + bool + 2 u64 + u128 + u64
+        assert_eq!(data.len(), 8 + 8 + 64 + 8 + 16 + 1 + 16 + 16 + 8);
+
+        let decoded = DlmmSwapEvent::from_cpi_data(&data).unwrap();
+        assert_eq!(decoded.lb_pair, event.lb_pair);
+        assert_eq!(decoded.from, event.from);
+        assert_eq!(decoded.start_bin_id, -4410);
+        assert_eq!(decoded.end_bin_id, -4408);
+        assert_eq!(decoded.amount_in, 1_000_000_000);
+        assert_eq!(decoded.amount_out, 151_234_567);
+        assert!(decoded.swap_for_y);
+        assert_eq!(decoded.fee, 2_500_000);
+        assert_eq!(decoded.protocol_fee, 125_000);
+        assert_eq!(decoded.fee_bps, 25);
+        assert_eq!(decoded.host_fee, 0);
+    }
+
+    #[test]
+    fn rejects_other_instructions() {
+        let event = borsh::to_vec(&swap_event()).unwrap();
+        // the `Program data:` form carries no event instruction tag
+        let log_data = [&SWAP_EVENT_DISCRIMINATOR[..], &event[..]].concat();
+        assert!(DlmmSwapEvent::from_cpi_data(&log_data).is_none());
+        let other_event = [&EVENT_IX_TAG[..], &[0; 8], &event[..]].concat();
+        assert!(DlmmSwapEvent::from_cpi_data(&other_event).is_none());
+        let truncated = [&EVENT_IX_TAG[..], &SWAP_EVENT_DISCRIMINATOR, &event[..40]].concat();
+        assert!(DlmmSwapEvent::from_cpi_data(&truncated).is_none());
+    }
+
+    // A SOL/USDC pair around 146 USDC, bin step 25, 0.25% base fee and 5%
+    // protocol share.
+    const ACTIVE_ID: i32 = -771;
+    const NOW: i64 = 1_700_000_100;
+
+    fn lb_pair() -> LbPair {
+        let mut lb_pair = LbPair::deserialize(&mut &[0; 1024][..]).unwrap();
+        lb_pair.parameters.base_factor = 10_000;
+        lb_pair.parameters.filter_period = 30;
+        lb_pair.parameters.decay_period = 600;
+        lb_pair.parameters.reduction_factor = 5_000;
+        lb_pair.parameters.variable_fee_control = 7_500;
+        lb_pair.parameters.max_volatility_accumulator = 150_000;
+        lb_pair.parameters.min_bin_id = -443_636;
+        lb_pair.parameters.max_bin_id = 443_636;
+        lb_pair.parameters.protocol_share = 500;
+        lb_pair.v_parameters.volatility_accumulator = 20_000;
+        lb_pair.v_parameters.volatility_reference = 10_000;
+        lb_pair.v_parameters.index_reference = ACTIVE_ID;
+        lb_pair.v_parameters.last_update_timestamp = NOW - 100;
+        lb_pair.active_id = ACTIVE_ID;
+        lb_pair.bin_step = 25;
+        lb_pair
+    }
+
+    /// The active bin is the last of array -12, the bins above it start array -11
+    fn bin_arrays() -> Vec<BinArray> {
+        let bins = [
+            (-773, 0, 400_000_000, 2677275005998362860),
+            (-772, 0, 400_000_000, 2683968193513358767),
+            (-771, 2_000_000_000, 300_000_000, 2690678113997142164),
+            (-770, 3_000_000_000, 0, 2697404809282135019),
+            (-769, 3_000_000_000, 0, 2704148321305340357),
+        ];
+        [-12, -11]
+            .into_iter()
+            .map(|index| {
+                let mut bin_array = BinArray {
+                    index,
+                    version: 1,
+                    padding: [0; 7],
+                    lb_pair: Pubkey::new_from_array([1; 32]),
+                    bins: [Bin::default(); 70],
+                };
+                for (bin_id, amount_x, amount_y, price) in bins {
+                    if BinArray::bin_id_to_bin_array_index(bin_id) == index {
+                        let bin = &mut bin_array.bins[(bin_id as i64 - index * 70) as usize];
+                        bin.amount_x = amount_x;
+                        bin.amount_y = amount_y;
+                        bin.price = price;
+                    }
+                }
+                bin_array
+            })
+            .collect()
+    }
+
+    fn assert_matches_event(quote: &DlmmQuote, event: &DlmmSwapEvent) {
+        assert_eq!(quote.amount_in, event.amount_in);
+        assert_eq!(quote.amount_out, event.amount_out);
+        assert_eq!(quote.fee, event.fee);
+        assert_eq!(quote.protocol_fee, event.protocol_fee);
+        assert_eq!(quote.end_bin_id, event.end_bin_id);
+    }
+
+    fn expected_event(
+        amount_in: u64,
+        amount_out: u64,
+        swap_for_y: bool,
+        fee: u64,
+        protocol_fee: u64,
+        end_bin_id: i32,
+    ) -> DlmmSwapEvent {
+        DlmmSwapEvent {
+            start_bin_id: ACTIVE_ID,
+            end_bin_id,
+            amount_in,
+            amount_out,
+            swap_for_y,
+            fee,
+            protocol_fee,
+            ..swap_event()
+        }
+    }
+
+    #[test]
+    fn quotes_exact_in_across_bins() {
+        let (lb_pair, bin_arrays) = (lb_pair(), bin_arrays());
+        // sells 5 SOL through the active bin and two bins below it
+        let quote = quote_exact_in(&lb_pair, &bin_arrays, 5_000_000_000, true, NOW).unwrap();
+        // the protocol share is rounded down per bin, 628_451 on the total fee
+        let event = expected_event(5_000_000_000, 726_344_534, true, 12_569_023, 628_450, -773);
+        assert_matches_event(&quote, &event);
+
+        // buys SOL with 600 USDC, crossing into the next bin array
+        let quote = quote_exact_in(&lb_pair, &bin_arrays, 600_000_000, false, NOW).unwrap();
+        let event = expected_event(600_000_000, 4_097_901_057, false, 1_507_139, 75_356, -770);
+        assert_matches_event(&quote, &event);
+    }
+
+    #[test]
+    fn quotes_exact_out_across_bins() {
+        let (lb_pair, bin_arrays) = (lb_pair(), bin_arrays());
+        let quote = quote_exact_out(&lb_pair, &bin_arrays, 650_000_000, true, NOW).unwrap();
+        let event = expected_event(4_473_505_796, 650_000_000, true, 11_238_649, 561_932, -772);
+        assert_matches_event(&quote, &event);
+    }
+
+    #[test]
+    fn exact_in_and_exact_out_round_trip() {
+        let (lb_pair, bin_arrays) = (lb_pair(), bin_arrays());
+        for (amount_in, swap_for_y) in [
+            (5_000_000_000, true),
+            (1_000_000, true),
+            (600_000_000, false),
+            (250_000, false),
+        ] {
+            let exact_in =
+                quote_exact_in(&lb_pair, &bin_arrays, amount_in, swap_for_y, NOW).unwrap();
+            let exact_out =
+                quote_exact_out(&lb_pair, &bin_arrays, exact_in.amount_out, swap_for_y, NOW)
+                    .unwrap();
+            // never asks for more than the input producing that output
+            assert!(exact_out.amount_in <= amount_in);
+            let back = quote_exact_in(&lb_pair, &bin_arrays, exact_out.amount_in, swap_for_y, NOW)
+                .unwrap();
+            assert!(back.amount_out >= exact_in.amount_out);
+        }
+    }
+
+    #[test]
+    fn fails_beyond_the_provided_bins() {
+        let (lb_pair, bin_arrays) = (lb_pair(), bin_arrays());
+        // the five bins hold about 8 SOL and 1_100 USDC
+        assert!(quote_exact_in(&lb_pair, &bin_arrays, 10_000_000_000, true, NOW).is_err());
+        assert!(quote_exact_out(&lb_pair, &bin_arrays, 9_000_000_000, false, NOW).is_err());
+    }
+
+    #[test]
+    fn decodes_the_swap_instructions() {
+        // 15 accounts as swap2 passes them
+        let keys = accounts(15);
+        let args = borsh::to_vec(&SwapArgs {
+            amount_in: 5_000_000_000,
+            min_amount_out: 720_000_000,
+        })
+        .unwrap();
+        // swap2 appends an empty remaining accounts info
+        let data = [&SWAP2_DISCRIMINATOR[..], &args, &[0, 0, 0, 0]].concat();
+        let DlmmInstruction::Swap { args, accounts } =
+            DlmmInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a swap");
+        };
+        assert_eq!(args.amount_in, 5_000_000_000);
+        assert_eq!(args.min_amount_out, 720_000_000);
+        assert_eq!(accounts.lb_pair, keys[0]);
+        assert_eq!(accounts.reserve_y, keys[3]);
+        assert_eq!(accounts.user_token_out, keys[5]);
+        assert_eq!(accounts.token_x_mint, keys[6]);
+        assert_eq!(accounts.user, keys[10]);
+
+        let args = borsh::to_vec(&SwapExactOutArgs {
+            max_in_amount: 4_500_000_000,
+            out_amount: 650_000_000,
+        })
+        .unwrap();
+        let data = [&SWAP_EXACT_OUT_DISCRIMINATOR[..], &args].concat();
+        let DlmmInstruction::SwapExactOut { args, .. } =
+            DlmmInstruction::decode(&data, &keys[..14]).unwrap()
+        else {
+            panic!("not a swap_exact_out");
+        };
+        assert_eq!(args.out_amount, 650_000_000);
+        assert!(DlmmInstruction::decode(&data, &keys[..10]).is_err());
+        assert!(DlmmInstruction::decode(&data[..12], &keys).is_err());
+    }
+}
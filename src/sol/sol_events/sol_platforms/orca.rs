@@ -2,11 +2,90 @@ use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use serde_with::DisplayFromStr;
 use serde_with::serde_as;
+use solana_client::nonblocking::rpc_client::RpcClient;
 use solana_sdk::pubkey::Pubkey;
+use spl_associated_token_account::{
+    get_associated_token_address, get_associated_token_address_with_program_id,
+};
+
+use super::raydium_clmm::MEMO_PROGRAM;
+use crate::sol::sol_math::clmm::{self, ClmmPool, ClmmSwapResult, ClmmTick, TickMath};
+use crate::sol::{SolError, sol_token_type::SolTokenType};
 
 pub(crate) const ORCA_ADDRESS: &str = "whirLbMiicVdio4qvUfM5KAg6Ct8VwpYzGff3uctyCc";
 
-#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub const WHIRLPOOL_DISCRIMINATOR: [u8; 8] = [63, 149, 209, 12, 225, 128, 99, 9];
+pub const TICK_ARRAY_DISCRIMINATOR: [u8; 8] = [69, 97, 189, 190, 110, 7, 66, 187];
+
+pub const TICK_ARRAY_SEED: &[u8] = b"tick_array";
+pub const ORACLE_SEED: &[u8] = b"oracle";
+
+pub const TICK_ARRAY_SIZE: i32 = 88;
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+pub const MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+/// Anchor error names, custom code `6000 + index`
+pub const ERROR_NAMES: &[&str] = &[
+    "InvalidEnum",
+    "InvalidStartTick",
+    "TickArrayExistInPool",
+    "TickArrayIndexOutofBounds",
+    "InvalidTickSpacing",
+    "ClosePositionNotEmpty",
+    "DivideByZero",
+    "NumberCastError",
+    "NumberDownCastError",
+    "TickNotFound",
+    "InvalidTickIndex",
+    "SqrtPriceOutOfBounds",
+    "LiquidityZero",
+    "LiquidityTooHigh",
+    "LiquidityOverflow",
+    "LiquidityUnderflow",
+    "LiquidityNetError",
+    "TokenMaxExceeded",
+    "TokenMinSubceeded",
+    "MissingOrInvalidDelegate",
+    "InvalidPositionTokenAmount",
+    "InvalidTimestampConversion",
+    "InvalidTimestamp",
+    "InvalidTickArraySequence",
+    "InvalidTokenMintOrder",
+    "RewardNotInitialized",
+    "InvalidRewardIndex",
+    "RewardVaultAmountInsufficient",
+    "FeeRateMaxExceeded",
+    "ProtocolFeeRateMaxExceeded",
+    "MultiplicationShiftRightOverflow",
+    "MulDivOverflow",
+    "MulDivInvalidInput",
+    "MultiplicationOverflow",
+    "InvalidSqrtPriceLimitDirection",
+    "ZeroTradableAmount",
+    "AmountOutBelowMinimum",
+    "AmountInAboveMaximum",
+    "TickArraySequenceInvalidIndex",
+    "AmountCalcOverflow",
+    "AmountRemainingOverflow",
+    "InvalidIntermediaryMint",
+    "DuplicateTwoHopPool",
+    "InvalidBundleIndex",
+    "BundledPositionAlreadyOpened",
+    "BundledPositionAlreadyClosed",
+    "PositionBundleNotDeletable",
+    "UnsupportedTokenMint",
+    "RemainingAccountsInvalidSlice",
+    "RemainingAccountsInsufficient",
+    "NoExtraAccountsForTransferHook",
+    "IntermediateTokenAmountMismatch",
+    "TransferFeeCalculationError",
+    "RemainingAccountsDuplicatedAccountsType",
+    "FullRangeOnlyPool",
+    "TooManySupplementalTickArrays",
+    "DifferentWhirlpoolTickArrayAccount",
+    "PartialFillError",
+];
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default, Serialize, Deserialize)]
 pub struct SwapArgs {
     pub amount: u64,
     pub other_amount_threshold: u64,
@@ -32,6 +111,7 @@ pub struct SwapAccounts {
 }
 
 impl SwapAccounts {
+    #[allow(clippy::too_many_arguments)]
     pub fn with_default_program(
         whirlpool: Pubkey,
         token_program: Pubkey,
@@ -62,6 +142,50 @@ impl SwapAccounts {
     }
 }
 
+impl SwapAccounts {
+    /// Derives the swap accounts out of an already decoded whirlpool, the user
+    /// token accounts are its associated token accounts.
+    /// The legacy swap only supports spl token mints.
+    pub fn from_whirlpool(
+        whirlpool_address: &Pubkey,
+        whirlpool: &Whirlpool,
+        user: &Pubkey,
+        a_to_b: bool,
+    ) -> Self {
+        let [tick_array_0, tick_array_1, tick_array_2] = whirlpool
+            .swap_tick_array_start_indexes(a_to_b)
+            .map(|start_index| get_tick_array_pda(whirlpool_address, start_index));
+        Self::with_default_program(
+            *whirlpool_address,
+            spl_token::ID,
+            *user,
+            get_associated_token_address(user, &whirlpool.token_mint_a),
+            whirlpool.token_vault_a,
+            get_associated_token_address(user, &whirlpool.token_mint_b),
+            whirlpool.token_vault_b,
+            tick_array_0,
+            tick_array_1,
+            tick_array_2,
+            get_oracle_pda(whirlpool_address),
+        )
+    }
+    /// Fetches the whirlpool and derives everything `build_whirlpool_swap_ix` needs
+    pub async fn resolve(
+        rpc: &RpcClient,
+        whirlpool_address: &Pubkey,
+        user: &Pubkey,
+        a_to_b: bool,
+    ) -> Result<Self, ()> {
+        let whirlpool = Whirlpool::fetch(rpc, whirlpool_address).await?;
+        Ok(Self::from_whirlpool(
+            whirlpool_address,
+            &whirlpool,
+            user,
+            a_to_b,
+        ))
+    }
+}
+
 pub fn build_whirlpool_swap_ix(
     accts: &SwapAccounts,
     args: &SwapArgs,
@@ -92,6 +216,225 @@ pub fn build_whirlpool_swap_ix(
     }
 }
 
+/// Kind of the accounts appended after the fixed swap_v2 accounts
+#[repr(u8)]
+#[derive(
+    BorshSerialize, BorshDeserialize, Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize,
+)]
+pub enum AccountsType {
+    TransferHookA,
+    TransferHookB,
+    TransferHookReward,
+    TransferHookInput,
+    TransferHookIntermediate,
+    TransferHookOutput,
+    SupplementalTickArrays,
+    SupplementalTickArraysOne,
+    SupplementalTickArraysTwo,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Serialize, Deserialize)]
+pub struct RemainingAccountsSlice {
+    pub accounts_type: AccountsType,
+    pub length: u8,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct RemainingAccountsInfo {
+    pub slices: Vec<RemainingAccountsSlice>,
+}
+
+impl RemainingAccountsInfo {
+    /// Describes transfer hook accounts appended for token a then token b,
+    /// `None` when neither mint has a hook
+    pub fn transfer_hooks(accounts_a: usize, accounts_b: usize) -> Option<Self> {
+        let slices: Vec<RemainingAccountsSlice> = [
+            (AccountsType::TransferHookA, accounts_a),
+            (AccountsType::TransferHookB, accounts_b),
+        ]
+        .into_iter()
+        .filter(|(_, length)| *length > 0)
+        .map(|(accounts_type, length)| RemainingAccountsSlice {
+            accounts_type,
+            length: length as u8,
+        })
+        .collect();
+        (!slices.is_empty()).then_some(Self { slices })
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Default, Serialize, Deserialize)]
+pub struct SwapV2Args {
+    pub amount: u64,
+    pub other_amount_threshold: u64,
+    pub sqrt_price_limit: u128,
+    pub amount_specified_is_input: bool,
+    pub a_to_b: bool,
+    pub remaining_accounts_info: Option<RemainingAccountsInfo>,
+}
+
+impl From<&SwapArgs> for SwapV2Args {
+    fn from(args: &SwapArgs) -> Self {
+        Self {
+            amount: args.amount,
+            other_amount_threshold: args.other_amount_threshold,
+            sqrt_price_limit: args.sqrt_price_limit,
+            amount_specified_is_input: args.amount_specified_is_input,
+            a_to_b: args.a_to_b,
+            remaining_accounts_info: None,
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SwapV2Accounts {
+    pub whirlpool_program: Pubkey,
+    pub token_program_a: Pubkey,
+    pub token_program_b: Pubkey,
+    pub memo_program: Pubkey,
+    pub token_authority: Pubkey,
+    pub whirlpool: Pubkey,
+    pub token_mint_a: Pubkey,
+    pub token_mint_b: Pubkey,
+    pub token_owner_account_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub token_owner_account_b: Pubkey,
+    pub token_vault_b: Pubkey,
+    pub tick_array_0: Pubkey,
+    pub tick_array_1: Pubkey,
+    pub tick_array_2: Pubkey,
+    pub oracle: Pubkey,
+    /// Transfer hook accounts, described by `SwapV2Args::remaining_accounts_info`
+    pub remaining_accounts: Vec<solana_sdk::instruction::AccountMeta>,
+}
+
+impl SwapV2Accounts {
+    /// Derives the swap_v2 accounts out of an already decoded whirlpool, the user
+    /// token accounts are its associated token accounts under each token program
+    pub fn from_whirlpool(
+        whirlpool_address: &Pubkey,
+        whirlpool: &Whirlpool,
+        user: &Pubkey,
+        token_program_a: Pubkey,
+        token_program_b: Pubkey,
+        a_to_b: bool,
+    ) -> Self {
+        let [tick_array_0, tick_array_1, tick_array_2] = whirlpool
+            .swap_tick_array_start_indexes(a_to_b)
+            .map(|start_index| get_tick_array_pda(whirlpool_address, start_index));
+        Self {
+            whirlpool_program: whirlpools_program_id(),
+            token_program_a,
+            token_program_b,
+            memo_program: MEMO_PROGRAM,
+            token_authority: *user,
+            whirlpool: *whirlpool_address,
+            token_mint_a: whirlpool.token_mint_a,
+            token_mint_b: whirlpool.token_mint_b,
+            token_owner_account_a: get_associated_token_address_with_program_id(
+                user,
+                &whirlpool.token_mint_a,
+                &token_program_a,
+            ),
+            token_vault_a: whirlpool.token_vault_a,
+            token_owner_account_b: get_associated_token_address_with_program_id(
+                user,
+                &whirlpool.token_mint_b,
+                &token_program_b,
+            ),
+            token_vault_b: whirlpool.token_vault_b,
+            tick_array_0,
+            tick_array_1,
+            tick_array_2,
+            oracle: get_oracle_pda(whirlpool_address),
+            remaining_accounts: Vec::new(),
+        }
+    }
+}
+
+pub fn build_whirlpool_swap_v2_ix(
+    accts: &SwapV2Accounts,
+    args: &SwapV2Args,
+) -> solana_sdk::instruction::Instruction {
+    use solana_sdk::instruction::{AccountMeta, Instruction};
+
+    let mut data = anchor_sighash_global_swap_v2().to_vec();
+    data.extend(borsh::to_vec(args).expect("borsh serialize SwapV2Args"));
+
+    let mut metas = vec![
+        AccountMeta::new_readonly(accts.token_program_a, false),
+        AccountMeta::new_readonly(accts.token_program_b, false),
+        AccountMeta::new_readonly(accts.memo_program, false),
+        AccountMeta::new_readonly(accts.token_authority, true),
+        AccountMeta::new(accts.whirlpool, false),
+        AccountMeta::new_readonly(accts.token_mint_a, false),
+        AccountMeta::new_readonly(accts.token_mint_b, false),
+        AccountMeta::new(accts.token_owner_account_a, false),
+        AccountMeta::new(accts.token_vault_a, false),
+        AccountMeta::new(accts.token_owner_account_b, false),
+        AccountMeta::new(accts.token_vault_b, false),
+        AccountMeta::new(accts.tick_array_0, false),
+        AccountMeta::new(accts.tick_array_1, false),
+        AccountMeta::new(accts.tick_array_2, false),
+        AccountMeta::new(accts.oracle, false),
+    ];
+    metas.extend(accts.remaining_accounts.iter().cloned());
+
+    Instruction {
+        program_id: accts.whirlpool_program,
+        accounts: metas,
+        data,
+    }
+}
+
+/// Builds the legacy swap when both mints are spl tokens, swap_v2 otherwise.
+/// Mints with transfer hooks need `build_whirlpool_swap_v2_ix` with the hook accounts.
+pub async fn build_whirlpool_swap_ix_for_pool(
+    whirlpool_address: &Pubkey,
+    whirlpool: &Whirlpool,
+    user: &Pubkey,
+    args: &SwapArgs,
+) -> Result<solana_sdk::instruction::Instruction, SolError> {
+    let token_type_a = SolTokenType::detect_token_program(&whirlpool.token_mint_a).await?;
+    let token_type_b = SolTokenType::detect_token_program(&whirlpool.token_mint_b).await?;
+    Ok(build_whirlpool_swap_ix_for_programs(
+        whirlpool_address,
+        whirlpool,
+        user,
+        token_type_a.id(),
+        token_type_b.id(),
+        args,
+    ))
+}
+
+/// `build_whirlpool_swap_ix_for_pool` once the token program of each mint is known
+pub fn build_whirlpool_swap_ix_for_programs(
+    whirlpool_address: &Pubkey,
+    whirlpool: &Whirlpool,
+    user: &Pubkey,
+    token_program_a: Pubkey,
+    token_program_b: Pubkey,
+    args: &SwapArgs,
+) -> solana_sdk::instruction::Instruction {
+    if token_program_a == spl_token::ID && token_program_b == spl_token::ID {
+        return build_whirlpool_swap_ix(
+            &SwapAccounts::from_whirlpool(whirlpool_address, whirlpool, user, args.a_to_b),
+            args,
+        );
+    }
+    build_whirlpool_swap_v2_ix(
+        &SwapV2Accounts::from_whirlpool(
+            whirlpool_address,
+            whirlpool,
+            user,
+            token_program_a,
+            token_program_b,
+            args.a_to_b,
+        ),
+        &SwapV2Args::from(args),
+    )
+}
+
 pub fn whirlpools_program_id() -> Pubkey {
     ::std::str::FromStr::from_str(ORCA_ADDRESS).expect("valid ORCA program id")
 }
@@ -104,6 +447,14 @@ fn anchor_sighash_global_swap() -> [u8; 8] {
     out
 }
 
+fn anchor_sighash_global_swap_v2() -> [u8; 8] {
+    let preimage = b"global:swap_v2";
+    let digest = solana_sdk::hash::hash(preimage);
+    let mut out = [0u8; 8];
+    out.copy_from_slice(&digest.to_bytes()[0..8]);
+    out
+}
+
 #[serde_as]
 #[derive(BorshSerialize, BorshDeserialize, Clone, Serialize, Debug, Deserialize)]
 pub struct Traded {
@@ -121,3 +472,529 @@ pub struct Traded {
     pub lp_fee: u64,
     pub protocol_fee: u64,
 }
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct WhirlpoolRewardInfo {
+    pub mint: Pubkey,
+    pub vault: Pubkey,
+    pub authority: Pubkey,
+    pub emissions_per_second_x64: u128,
+    pub growth_global_x64: u128,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct Whirlpool {
+    pub whirlpools_config: Pubkey,
+    pub whirlpool_bump: [u8; 1],
+    pub tick_spacing: u16,
+    pub fee_tier_index_seed: [u8; 2],
+    /// Trade fee, in hundredths of a basis point
+    pub fee_rate: u16,
+    pub protocol_fee_rate: u16,
+    pub liquidity: u128,
+    /// Q64.64 square root of the price of token a in token b
+    pub sqrt_price: u128,
+    pub tick_current_index: i32,
+    pub protocol_fee_owed_a: u64,
+    pub protocol_fee_owed_b: u64,
+    pub token_mint_a: Pubkey,
+    pub token_vault_a: Pubkey,
+    pub fee_growth_global_a: u128,
+    pub token_mint_b: Pubkey,
+    pub token_vault_b: Pubkey,
+    pub fee_growth_global_b: u128,
+    pub reward_last_updated_timestamp: u64,
+    pub reward_infos: [WhirlpoolRewardInfo; 3],
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct Tick {
+    pub initialized: bool,
+    /// Liquidity added when crossing the tick left to right
+    pub liquidity_net: i128,
+    pub liquidity_gross: u128,
+    pub fee_growth_outside_a: u128,
+    pub fee_growth_outside_b: u128,
+    pub reward_growths_outside: [u128; 3],
+}
+
+/// Fixed size tick array, a packed zero copy account
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct TickArray {
+    pub start_tick_index: i32,
+    pub ticks: [Tick; TICK_ARRAY_SIZE as usize],
+    pub whirlpool: Pubkey,
+}
+
+impl Whirlpool {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        if data.len() < 8 || data[..8] != WHIRLPOOL_DISCRIMINATOR {
+            return Err(());
+        }
+        Self::deserialize(&mut &data[8..]).map_err(|_| ())
+    }
+    pub async fn fetch(rpc: &RpcClient, whirlpool_address: &Pubkey) -> Result<Self, ()> {
+        let account = rpc.get_account(whirlpool_address).await.map_err(|_| ())?;
+
+        if account.owner != whirlpools_program_id() {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+
+    pub fn ticks_in_array(&self) -> i32 {
+        self.tick_spacing as i32 * TICK_ARRAY_SIZE
+    }
+    /// Start index of the tick array containing `tick`
+    pub fn tick_array_start_index(&self, tick: i32) -> i32 {
+        tick.div_euclid(self.ticks_in_array()) * self.ticks_in_array()
+    }
+    /// Start indexes of the three tick arrays a swap walks through. For b to a
+    /// swaps the current array is looked up one tick spacing ahead, as the
+    /// program does when the price sits on the last tick of an array.
+    pub fn swap_tick_array_start_indexes(&self, a_to_b: bool) -> [i32; 3] {
+        let (start_index, step) = if a_to_b {
+            (
+                self.tick_array_start_index(self.tick_current_index),
+                -self.ticks_in_array(),
+            )
+        } else {
+            (
+                self.tick_array_start_index(self.tick_current_index + self.tick_spacing as i32),
+                self.ticks_in_array(),
+            )
+        };
+        [start_index, start_index + step, start_index + 2 * step]
+    }
+
+    /// Quotes a swap against the supplied tick arrays, which must cover every
+    /// tick the swap crosses.
+    ///
+    /// # Arguments
+    /// * `amount` - Exact input when `amount_specified_is_input`, exact output otherwise
+    /// * `sqrt_price_limit` - Defaults to the pool bounds
+    pub fn quote(
+        &self,
+        tick_arrays: &[TickArray],
+        amount: u64,
+        amount_specified_is_input: bool,
+        a_to_b: bool,
+        sqrt_price_limit: Option<u128>,
+    ) -> Result<ClmmSwapResult, ()> {
+        let tick_lower_bound = tick_arrays
+            .iter()
+            .map(|x| x.start_tick_index)
+            .min()
+            .ok_or(())?;
+        let tick_upper_bound = tick_arrays
+            .iter()
+            .map(|x| x.start_tick_index)
+            .max()
+            .ok_or(())?
+            + self.ticks_in_array();
+        let mut ticks: Vec<ClmmTick> = tick_arrays
+            .iter()
+            .flat_map(|array| {
+                array
+                    .ticks
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, x)| x.initialized)
+                    .map(|(i, x)| ClmmTick {
+                        tick: array.start_tick_index + i as i32 * self.tick_spacing as i32,
+                        liquidity_net: x.liquidity_net,
+                    })
+            })
+            .collect();
+        ticks.sort_by_key(|x| x.tick);
+        ticks.dedup_by_key(|x| x.tick);
+
+        let pool = ClmmPool {
+            sqrt_price_x64: self.sqrt_price,
+            tick_current: self.tick_current_index,
+            liquidity: self.liquidity,
+            fee_rate: self.fee_rate as u32,
+            tick_math: TickMath::Whirlpool,
+            tick_lower_bound,
+            tick_upper_bound,
+        };
+        clmm::swap(
+            &pool,
+            &ticks,
+            amount,
+            amount_specified_is_input,
+            a_to_b,
+            sqrt_price_limit,
+        )
+    }
+}
+
+impl TickArray {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        if data.len() < 8 || data[..8] != TICK_ARRAY_DISCRIMINATOR {
+            return Err(());
+        }
+        Self::deserialize(&mut &data[8..]).map_err(|_| ())
+    }
+    /// Fetches the tick arrays a swap walks through, missing arrays are skipped
+    pub async fn fetch_for_swap(
+        rpc: &RpcClient,
+        whirlpool_address: &Pubkey,
+        whirlpool: &Whirlpool,
+        a_to_b: bool,
+    ) -> Result<Vec<Self>, ()> {
+        let keys = whirlpool
+            .swap_tick_array_start_indexes(a_to_b)
+            .map(|start_index| get_tick_array_pda(whirlpool_address, start_index));
+        let accounts = rpc.get_multiple_accounts(&keys).await.map_err(|_| ())?;
+        Ok(accounts
+            .into_iter()
+            .flatten()
+            .filter_map(|account| Self::from_bytes(&account.data).ok())
+            .collect())
+    }
+}
+
+/// The start index is encoded as a decimal string in the seed
+pub fn get_tick_array_pda(whirlpool: &Pubkey, start_tick_index: i32) -> Pubkey {
+    Pubkey::find_program_address(
+        &[
+            TICK_ARRAY_SEED,
+            whirlpool.as_ref(),
+            start_tick_index.to_string().as_bytes(),
+        ],
+        &whirlpools_program_id(),
+    )
+    .0
+}
+
+pub fn get_oracle_pda(whirlpool: &Pubkey) -> Pubkey {
+    Pubkey::find_program_address(&[ORACLE_SEED, whirlpool.as_ref()], &whirlpools_program_id()).0
+}
+
+/// Accounts shared by swap and swap_v2, only swap_v2 passes the mints
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WhirlpoolSwapAccounts {
+    #[serde_as(as = "DisplayFromStr")]
+    pub token_authority: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub whirlpool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub token_owner_account_a: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub token_vault_a: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub token_owner_account_b: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub token_vault_b: Pubkey,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub token_mint_a: Option<Pubkey>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub token_mint_b: Option<Pubkey>,
+}
+
+impl WhirlpoolSwapAccounts {
+    /// Reads the accounts in program order, swap starts with the token program
+    /// and swap_v2 with both token programs and the memo program
+    pub fn from_accounts(accounts: &[Pubkey], swap_v2: bool) -> Result<Self, ()> {
+        if swap_v2 {
+            if accounts.len() < 11 {
+                return Err(());
+            }
+            return Ok(Self {
+                token_authority: accounts[3],
+                whirlpool: accounts[4],
+                token_mint_a: Some(accounts[5]),
+                token_mint_b: Some(accounts[6]),
+                token_owner_account_a: accounts[7],
+                token_vault_a: accounts[8],
+                token_owner_account_b: accounts[9],
+                token_vault_b: accounts[10],
+            });
+        }
+        if accounts.len() < 7 {
+            return Err(());
+        }
+        Ok(Self {
+            token_authority: accounts[1],
+            whirlpool: accounts[2],
+            token_owner_account_a: accounts[3],
+            token_vault_a: accounts[4],
+            token_owner_account_b: accounts[5],
+            token_vault_b: accounts[6],
+            token_mint_a: None,
+            token_mint_b: None,
+        })
+    }
+}
+
+/// A Whirlpool swap decoded from a transaction
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum WhirlpoolInstruction {
+    Swap {
+        args: SwapArgs,
+        accounts: WhirlpoolSwapAccounts,
+    },
+    SwapV2 {
+        args: SwapV2Args,
+        accounts: WhirlpoolSwapAccounts,
+    },
+}
+
+impl WhirlpoolInstruction {
+    /// Decodes the instruction data and its resolved accounts
+    ///
+    /// # Returns
+    /// * `Err(())` - Not a swap, or truncated data or accounts
+    pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+        if data.len() < 8 {
+            return Err(());
+        }
+        let (discriminator, mut args) = data.split_at(8);
+        let discriminator = <[u8; 8]>::try_from(discriminator).map_err(|_| ())?;
+        Ok(if discriminator == anchor_sighash_global_swap() {
+            Self::Swap {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: WhirlpoolSwapAccounts::from_accounts(accounts, false)?,
+            }
+        } else if discriminator == anchor_sighash_global_swap_v2() {
+            Self::SwapV2 {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: WhirlpoolSwapAccounts::from_accounts(accounts, true)?,
+            }
+        } else {
+            return Err(());
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WhirlpoolInstructionEvent {
+    pub signature: String,
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions, `None` for an outer instruction
+    pub inner_index: Option<usize>,
+    pub instruction: WhirlpoolInstruction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::{accounts, whirlpool};
+
+    fn args() -> SwapArgs {
+        SwapArgs {
+            amount: 2_000_000_000,
+            other_amount_threshold: 290_000_000,
+            sqrt_price_limit: MIN_SQRT_PRICE_X64,
+            amount_specified_is_input: true,
+            a_to_b: true,
+        }
+    }
+
+    #[test]
+    fn swap_tick_arrays_on_an_array_boundary() {
+        // the last tick of the array starting at 0
+        let pool = whirlpool(5_568);
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(true),
+            [0, -5_632, -11_264]
+        );
+        // b to a looks one tick spacing ahead, into the next array
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(false),
+            [5_632, 11_264, 16_896]
+        );
+
+        // the first tick of an array belongs to it in both directions
+        let pool = whirlpool(5_632);
+        assert_eq!(pool.swap_tick_array_start_indexes(true), [5_632, 0, -5_632]);
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(false),
+            [5_632, 11_264, 16_896]
+        );
+
+        // negative ticks round down to their array
+        let pool = whirlpool(-1);
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(true),
+            [-5_632, -11_264, -16_896]
+        );
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(false),
+            [0, 5_632, 11_264]
+        );
+        let pool = whirlpool(-65);
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(false),
+            [-5_632, 0, 5_632]
+        );
+    }
+
+    #[test]
+    fn swap_v2_accounts_follow_the_idl() {
+        let (address, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool = whirlpool(5_568);
+        let ix = build_whirlpool_swap_ix_for_programs(
+            &address,
+            &pool,
+            &user,
+            spl_token_2022::ID,
+            spl_token::ID,
+            &SwapArgs {
+                a_to_b: false,
+                ..args()
+            },
+        );
+        assert_eq!(ix.program_id, whirlpools_program_id());
+        assert_eq!(&ix.data[..8], &anchor_sighash_global_swap_v2());
+        let mut args = SwapV2Args::from(&SwapArgs {
+            a_to_b: false,
+            ..args()
+        });
+        assert_eq!(ix.data[8..], borsh::to_vec(&args).unwrap());
+        let expected = [
+            (spl_token_2022::ID, false, false),
+            (spl_token::ID, false, false),
+            (MEMO_PROGRAM, false, false),
+            (user, false, true),
+            (address, true, false),
+            (pool.token_mint_a, false, false),
+            (pool.token_mint_b, false, false),
+            (
+                get_associated_token_address_with_program_id(
+                    &user,
+                    &pool.token_mint_a,
+                    &spl_token_2022::ID,
+                ),
+                true,
+                false,
+            ),
+            (pool.token_vault_a, true, false),
+            (
+                get_associated_token_address(&user, &pool.token_mint_b),
+                true,
+                false,
+            ),
+            (pool.token_vault_b, true, false),
+            (get_tick_array_pda(&address, 5_632), true, false),
+            (get_tick_array_pda(&address, 11_264), true, false),
+            (get_tick_array_pda(&address, 16_896), true, false),
+            (get_oracle_pda(&address), true, false),
+        ];
+        let actual: Vec<_> = ix
+            .accounts
+            .iter()
+            .map(|x| (x.pubkey, x.is_writable, x.is_signer))
+            .collect();
+        assert_eq!(actual, expected);
+
+        // transfer hook accounts follow the fixed accounts
+        let hook = Pubkey::new_unique();
+        let mut accounts = SwapV2Accounts::from_whirlpool(
+            &address,
+            &pool,
+            &user,
+            spl_token_2022::ID,
+            spl_token::ID,
+            false,
+        );
+        accounts
+            .remaining_accounts
+            .push(solana_sdk::instruction::AccountMeta::new_readonly(
+                hook, false,
+            ));
+        args.remaining_accounts_info = RemainingAccountsInfo::transfer_hooks(1, 0);
+        let ix = build_whirlpool_swap_v2_ix(&accounts, &args);
+        assert_eq!(ix.accounts.len(), 16);
+        assert_eq!(ix.accounts[15].pubkey, hook);
+    }
+
+    #[test]
+    fn token_2022_on_either_side_selects_swap_v2() {
+        let (address, user) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let pool = whirlpool(0);
+        let build = |token_program_a, token_program_b| {
+            build_whirlpool_swap_ix_for_programs(
+                &address,
+                &pool,
+                &user,
+                token_program_a,
+                token_program_b,
+                &args(),
+            )
+        };
+
+        let ix = build(spl_token::ID, spl_token::ID);
+        assert_eq!(&ix.data[..8], &anchor_sighash_global_swap());
+        assert_eq!(ix.accounts.len(), 11);
+        assert_eq!(ix.accounts[1].pubkey, spl_token::ID);
+
+        for (token_program_a, token_program_b) in [
+            (spl_token_2022::ID, spl_token::ID),
+            (spl_token::ID, spl_token_2022::ID),
+            (spl_token_2022::ID, spl_token_2022::ID),
+        ] {
+            let ix = build(token_program_a, token_program_b);
+            assert_eq!(&ix.data[..8], &anchor_sighash_global_swap_v2());
+            assert_eq!(ix.accounts.len(), 15);
+            assert_eq!(ix.accounts[0].pubkey, token_program_a);
+            assert_eq!(ix.accounts[1].pubkey, token_program_b);
+            assert_eq!(
+                ix.accounts[9].pubkey,
+                get_associated_token_address_with_program_id(
+                    &user,
+                    &pool.token_mint_b,
+                    &token_program_b
+                )
+            );
+        }
+    }
+
+    #[test]
+    fn decodes_swap() {
+        let data = [
+            &anchor_sighash_global_swap()[..],
+            &borsh::to_vec(&args()).unwrap(),
+        ]
+        .concat();
+        let keys = accounts(11);
+        let WhirlpoolInstruction::Swap { args, accounts } =
+            WhirlpoolInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a swap");
+        };
+        assert_eq!(args.amount, 2_000_000_000);
+        assert!(args.a_to_b);
+        assert_eq!(accounts.token_authority, keys[1]);
+        assert_eq!(accounts.whirlpool, keys[2]);
+        assert_eq!(accounts.token_vault_b, keys[6]);
+        assert_eq!(accounts.token_mint_a, None);
+    }
+
+    #[test]
+    fn decodes_swap_v2_with_its_remaining_accounts() {
+        let mut args = SwapV2Args::from(&args());
+        args.remaining_accounts_info = RemainingAccountsInfo::transfer_hooks(1, 0);
+        let data = [
+            &anchor_sighash_global_swap_v2()[..],
+            &borsh::to_vec(&args).unwrap(),
+        ]
+        .concat();
+        let keys = accounts(16);
+        let WhirlpoolInstruction::SwapV2 { args, accounts } =
+            WhirlpoolInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a swap_v2");
+        };
+        assert_eq!(args.remaining_accounts_info.unwrap().slices.len(), 1);
+        assert_eq!(accounts.token_authority, keys[3]);
+        assert_eq!(accounts.whirlpool, keys[4]);
+        assert_eq!(accounts.token_mint_a, Some(keys[5]));
+        assert_eq!(accounts.token_owner_account_a, keys[7]);
+        assert_eq!(accounts.token_vault_b, keys[10]);
+        assert!(WhirlpoolInstruction::decode(&data, &keys[..10]).is_err());
+    }
+}
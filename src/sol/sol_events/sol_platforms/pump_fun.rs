@@ -1,4 +1,3 @@
-use base64::{Engine, prelude::BASE64_STANDARD};
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
 use solana_client::nonblocking::rpc_client::RpcClient;
@@ -13,8 +12,6 @@ use solana_pubkey::pubkey;
 use solana_signer::Signer;
 use spl_associated_token_account::get_associated_token_address;
 
-use crate::sol::sol_events::MutEvents;
-
 pub const GLOBAL_SEED: &[u8] = b"global";
 
 pub const FEE_BASIS_POINTS: u64 = 95;
@@ -57,6 +54,41 @@ pub const RENT: Pubkey = pubkey!("SysvarRent111111111111111111111111111111111");
 pub const PUMP_FUN_BUY_DISCRIMINATOR: &[u8; 8] = &[102, 6, 61, 18, 1, 218, 235, 234];
 pub const PUMP_FUN_SELL_DISCRIMINATOR: &[u8; 8] = &[51, 230, 133, 164, 1, 127, 131, 173];
 pub const PUMPFUN_ADDRESS: &str = "6EF8rrecthR5Dkzon8Nwu78hRvfCKubJ14M5uBEwF6P";
+pub const BONDING_CURVE_DISCRIMINATOR: [u8; 8] = [23, 183, 248, 55, 96, 216, 172, 96];
+/// Anchor error names, custom code `6000 + index`
+pub const ERROR_NAMES: &[&str] = &[
+    "NotAuthorized",
+    "AlreadyInitialized",
+    "TooMuchSolRequired",
+    "TooLittleSolReceived",
+    "MintDoesNotMatchBondingCurve",
+    "BondingCurveComplete",
+    "BondingCurveNotComplete",
+    "NotInitialized",
+    "WithdrawTooFrequent",
+    "NewSizeShouldBeGreaterThanCurrentSize",
+    "AccountTypeNotSupported",
+    "InitialRealTokenReservesShouldBeLessThanTokenTotalSupply",
+    "InitialVirtualTokenReservesShouldBeGreaterThanInitialRealTokenReserves",
+    "FeeBasisPointsGreaterThanMaximum",
+    "AllZerosWithdrawAuthority",
+    "PoolMigrationFeeShouldBeLessThanFinalRealSolReserves",
+    "PoolMigrationFeeShouldBeGreaterThanCreatorFeePlusMaxMigrateFees",
+    "DisabledWithdraw",
+    "DisabledMigrate",
+    "InvalidMigration",
+    "BuyZeroAmount",
+    "NotEnoughTokensToBuy",
+    "SellZeroAmount",
+    "NotEnoughTokensToSell",
+    "Overflow",
+    "Truncation",
+    "DivisionByZero",
+    "NotEnoughRemainingAccounts",
+    "AllFeeRecipientsShouldBeNonZero",
+    "UnsortedNotUniqueFeeRecipients",
+    "CreatorShouldNotBeZero",
+];
 pub struct PumpFun;
 #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
 pub struct BondingCurveAccount {
@@ -193,13 +225,6 @@ impl BondingCurveAccount {
         })
     }
 
-    /// Calculates the amount of SOL received for selling tokens
-    ///
-    /// # Arguments
-    /// * `amount` - Amount of tokens to sell
-    /// * `fee_basis_points` - Fee in basis points (1/100th of a percent)
-    ///
-
     pub fn get_buy_token_amount_from_sol_amount(&self, amount: u64) -> u64 {
         if amount == 0 {
             return 0;
@@ -239,6 +264,12 @@ impl BondingCurveAccount {
 
         tokens_received.min(real_token_reserves) as u64
     }
+    /// Calculates the amount of SOL received for selling tokens
+    ///
+    /// # Arguments
+    /// * `amount` - Amount of tokens to sell
+    /// * `fee_basis_points` - Fee in basis points (1/100th of a percent)
+    ///
     /// # Returns
     /// * `Ok(u64)` - Amount of SOL that would be received after fees
     /// * `Err(&str)` - Error message if curve is complete
@@ -364,13 +395,11 @@ impl PumpFun {
 
         let account = rpc.get_account(&bonding_curve_pda).await.map_err(|x| {
             println!("{:?}", x);
-            ()
         })?;
 
         solana_sdk::borsh1::try_from_slice_unchecked::<BondingCurveAccount>(&account.data).map_err(
             |x| {
                 println!("{:?}", x);
-                ()
             },
         )
     }
@@ -472,11 +501,13 @@ impl PumpFunSell {
 }
 
 // create
-#[derive(BorshSerialize, BorshDeserialize, Clone)]
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
 pub struct CreatePumpFun {
     pub name: String,
     pub symbol: String,
     pub uri: String,
+    #[serde_as(as = "DisplayFromStr")]
     pub creator: Pubkey,
 }
 
@@ -487,7 +518,7 @@ impl CreatePumpFun {
     pub fn data(&self) -> Vec<u8> {
         let mut data = Vec::with_capacity(256);
         data.extend_from_slice(&Self::DISCRIMINATOR);
-        self.serialize(&mut data).unwrap();
+        BorshSerialize::serialize(self, &mut data).unwrap();
         data
     }
 }
@@ -520,7 +551,7 @@ pub fn create(payer: &Keypair, mint: &Keypair, args: CreatePumpFun) -> Instructi
 
 // buy
 
-#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
 pub struct Buy {
     pub amount: u64,
     pub max_sol_cost: u64,
@@ -530,12 +561,12 @@ impl Buy {
     pub fn data(&self) -> Result<Vec<u8>, ()> {
         let mut data = Vec::with_capacity(256);
         data.extend_from_slice(&Self::DISCRIMINATOR);
-        self.serialize(&mut data).map_err(|_| ())?;
+        BorshSerialize::serialize(self, &mut data).map_err(|_| ())?;
         Ok(data)
     }
 }
 
-#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
 pub struct Sell {
     pub amount: u64,
     pub min_sol_output: u64,
@@ -553,7 +584,7 @@ impl Sell {
     pub fn data(&self) -> Vec<u8> {
         let mut data = Vec::with_capacity(256);
         data.extend_from_slice(&Self::DISCRIMINATOR);
-        self.serialize(&mut data).unwrap();
+        BorshSerialize::serialize(self, &mut data).unwrap();
         data
     }
 }
@@ -575,8 +606,8 @@ pub fn sell(
             AccountMeta::new_readonly(*mint, false),
             AccountMeta::new(bonding_curve, false),
             AccountMeta::new(get_associated_token_address(&bonding_curve, mint), false),
-            AccountMeta::new(get_associated_token_address(&payer, mint), false),
-            AccountMeta::new(payer.clone(), true),
+            AccountMeta::new(get_associated_token_address(payer, mint), false),
+            AccountMeta::new(*payer, true),
             AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
             AccountMeta::new(creator_vault, false),
             AccountMeta::new_readonly(TOKEN_PROGRAM, false),
@@ -616,15 +647,15 @@ pub fn buy(
             AccountMeta::new_readonly(*mint, false),
             AccountMeta::new(bonding_curve, false),
             AccountMeta::new(get_associated_token_address(&bonding_curve, mint), false),
-            AccountMeta::new(get_associated_token_address(&payer, mint), false),
-            AccountMeta::new(payer.clone(), true),
+            AccountMeta::new(get_associated_token_address(payer, mint), false),
+            AccountMeta::new(*payer, true),
             AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
             AccountMeta::new_readonly(TOKEN_PROGRAM, false),
             AccountMeta::new(creator_vault, false),
             AccountMeta::new_readonly(EVENT_AUTHORITY, false),
             AccountMeta::new_readonly(PUMPFUN, false),
             AccountMeta::new(GLOBAL_VOLUME_ACCUMULATOR, false),
-            AccountMeta::new(PumpFun::get_user_volume_accumulator_pda(&payer), false),
+            AccountMeta::new(PumpFun::get_user_volume_accumulator_pda(payer), false),
             AccountMeta::new(
                 pubkey!("8Wf5TiAheLUqBrKXeYg2JtAFFMWtKdG2BSFgqUcPVwTt"),
                 false,
@@ -691,6 +722,128 @@ impl GlobalAccount {
     }
 }
 
+/// Accounts shared by buy and sell, both start with the same seven accounts:
+/// global, fee recipient, mint, curve, curve ATA, user ATA and user
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PumpFunTradeAccounts {
+    #[serde_as(as = "DisplayFromStr")]
+    pub fee_recipient: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub bonding_curve: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub associated_bonding_curve: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub associated_user: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+}
+
+impl PumpFunTradeAccounts {
+    pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+        if accounts.len() < 7 {
+            return Err(());
+        }
+        Ok(Self {
+            fee_recipient: accounts[1],
+            mint: accounts[2],
+            bonding_curve: accounts[3],
+            associated_bonding_curve: accounts[4],
+            associated_user: accounts[5],
+            user: accounts[6],
+        })
+    }
+}
+
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PumpFunCreateAccounts {
+    #[serde_as(as = "DisplayFromStr")]
+    pub mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub bonding_curve: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub associated_bonding_curve: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub metadata: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+}
+
+impl PumpFunCreateAccounts {
+    pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+        if accounts.len() < 8 {
+            return Err(());
+        }
+        Ok(Self {
+            mint: accounts[0],
+            bonding_curve: accounts[2],
+            associated_bonding_curve: accounts[3],
+            metadata: accounts[6],
+            user: accounts[7],
+        })
+    }
+}
+
+/// A pump.fun instruction decoded from a transaction, carries the user limits
+/// even when the trade failed and emitted no event
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PumpFunInstruction {
+    Create {
+        args: CreatePumpFun,
+        accounts: PumpFunCreateAccounts,
+    },
+    Buy {
+        args: Buy,
+        accounts: PumpFunTradeAccounts,
+    },
+    Sell {
+        args: Sell,
+        accounts: PumpFunTradeAccounts,
+    },
+}
+
+impl PumpFunInstruction {
+    /// Decodes the instruction data and its resolved accounts
+    ///
+    /// # Returns
+    /// * `Err(())` - Not a create, buy or sell, or truncated data or accounts
+    pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+        if data.len() < 8 {
+            return Err(());
+        }
+        let (discriminator, mut args) = data.split_at(8);
+        // instructions may carry trailing optional args
+        Ok(match <[u8; 8]>::try_from(discriminator).map_err(|_| ())? {
+            CreatePumpFun::DISCRIMINATOR => Self::Create {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: PumpFunCreateAccounts::from_accounts(accounts)?,
+            },
+            Buy::DISCRIMINATOR => Self::Buy {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: PumpFunTradeAccounts::from_accounts(accounts)?,
+            },
+            Sell::DISCRIMINATOR => Self::Sell {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: PumpFunTradeAccounts::from_accounts(accounts)?,
+            },
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PumpFunInstructionEvent {
+    pub signature: String,
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions, `None` for an outer instruction
+    pub inner_index: Option<usize>,
+    pub instruction: PumpFunInstruction,
+}
+
 pub struct CreatePumpFunMetadata {
     pub name: String,
     pub symbol: String,
@@ -776,3 +929,95 @@ pub async fn create_token_metadata(
 
     Ok(serde_json::from_str(&post).unwrap())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::accounts;
+
+    #[test]
+    fn decodes_create() {
+        let creator = Pubkey::new_unique();
+        let data = CreatePumpFun {
+            name: "Token".to_string(),
+            symbol: "TKN".to_string(),
+            uri: "https://example.com/token.json".to_string(),
+            creator,
+        }
+        .data();
+        let keys = accounts(14);
+        let PumpFunInstruction::Create { args, accounts } =
+            PumpFunInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a create");
+        };
+        assert_eq!(args.name, "Token");
+        assert_eq!(args.symbol, "TKN");
+        assert_eq!(args.uri, "https://example.com/token.json");
+        assert_eq!(args.creator, creator);
+        assert_eq!(accounts.mint, keys[0]);
+        assert_eq!(accounts.bonding_curve, keys[2]);
+        assert_eq!(accounts.associated_bonding_curve, keys[3]);
+        assert_eq!(accounts.metadata, keys[6]);
+        assert_eq!(accounts.user, keys[7]);
+
+        assert!(PumpFunInstruction::decode(&data, &keys[..7]).is_err());
+    }
+
+    #[test]
+    fn decodes_buy() {
+        let mut data = Buy {
+            amount: 1_000_000,
+            max_sol_cost: 35_000_000,
+        }
+        .data()
+        .unwrap();
+        // newer clients append an optional `track_volume` flag
+        data.extend([1, 1]);
+        let keys = accounts(16);
+        let PumpFunInstruction::Buy { args, accounts } =
+            PumpFunInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a buy");
+        };
+        assert_eq!(args.amount, 1_000_000);
+        assert_eq!(args.max_sol_cost, 35_000_000);
+        assert_eq!(accounts.fee_recipient, keys[1]);
+        assert_eq!(accounts.mint, keys[2]);
+        assert_eq!(accounts.bonding_curve, keys[3]);
+        assert_eq!(accounts.associated_bonding_curve, keys[4]);
+        assert_eq!(accounts.associated_user, keys[5]);
+        assert_eq!(accounts.user, keys[6]);
+
+        // the user is the seventh account
+        assert!(PumpFunInstruction::decode(&data, &keys[..7]).is_ok());
+        assert!(PumpFunInstruction::decode(&data, &keys[..6]).is_err());
+        assert!(PumpFunInstruction::decode(&data[..12], &keys).is_err());
+    }
+
+    #[test]
+    fn decodes_sell() {
+        let data = Sell {
+            amount: 2_500_000,
+            min_sol_output: 80_000_000,
+        }
+        .data();
+        let keys = accounts(14);
+        let PumpFunInstruction::Sell { args, accounts } =
+            PumpFunInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a sell");
+        };
+        assert_eq!(args.amount, 2_500_000);
+        assert_eq!(args.min_sol_output, 80_000_000);
+        assert_eq!(accounts.mint, keys[2]);
+        assert_eq!(accounts.user, keys[6]);
+    }
+
+    #[test]
+    fn rejects_other_instructions() {
+        let keys = accounts(16);
+        assert!(PumpFunInstruction::decode(&[0; 24], &keys).is_err());
+        assert!(PumpFunInstruction::decode(&Buy::DISCRIMINATOR[..4], &keys).is_err());
+    }
+}
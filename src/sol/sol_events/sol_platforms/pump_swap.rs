@@ -0,0 +1,623 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_pubkey::pubkey;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+use spl_associated_token_account::get_associated_token_address_with_program_id;
+
+use super::pump_fun::{ASSOCIATED_TOKEN_PROGRAM, SYSTEM_PROGRAM};
+
+pub const PUMPSWAP_ADDRESS: &str = "pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA";
+pub const PUMPSWAP: Pubkey = pubkey!("pAMMBay6oceH9fJKBRHGP5D4bD4sWpmSwMn52FMfXEA");
+
+/// Program holding the fee configuration shared by pump.fun and PumpSwap
+pub const PUMP_FEE_PROGRAM: Pubkey = pubkey!("pfeeUxB6jkeY1Hxd7CsFCAjcbHA9rWtchMGdZ6VojVZ");
+
+/// Seed for the global config PDA
+pub const GLOBAL_CONFIG_SEED: &[u8] = b"global_config";
+
+/// Seed for the anchor event authority PDA
+pub const EVENT_AUTHORITY_SEED: &[u8] = b"__event_authority";
+
+/// Seed for the coin creator vault authority PDA
+pub const CREATOR_VAULT_SEED: &[u8] = b"creator_vault";
+
+/// Seed for the fee config PDA, owned by the fee program
+pub const FEE_CONFIG_SEED: &[u8] = b"fee_config";
+
+pub const POOL_ACCOUNT_DISCRIMINATOR: [u8; 8] = [241, 154, 109, 4, 17, 177, 109, 188];
+pub const GLOBAL_CONFIG_ACCOUNT_DISCRIMINATOR: [u8; 8] = [149, 8, 156, 202, 160, 252, 176, 217];
+
+pub const BUY_EVENT_DISCRIMINATOR: [u8; 8] = [103, 244, 82, 31, 44, 245, 119, 119];
+pub const SELL_EVENT_DISCRIMINATOR: [u8; 8] = [62, 47, 55, 10, 165, 3, 220, 42];
+pub const CREATE_POOL_EVENT_DISCRIMINATOR: [u8; 8] = [177, 49, 12, 210, 160, 118, 167, 116];
+pub const DEPOSIT_EVENT_DISCRIMINATOR: [u8; 8] = [120, 248, 61, 83, 31, 142, 107, 144];
+pub const WITHDRAW_EVENT_DISCRIMINATOR: [u8; 8] = [22, 9, 133, 26, 160, 44, 71, 192];
+
+/// Decodes the known prefix of a PumpSwap event or account.
+///
+/// The program appends new fields to its types over time (volume tracking,
+/// ix names...), so trailing bytes are ignored instead of failing the decode.
+pub fn decode_event<T: BorshDeserialize>(data: &[u8]) -> Result<T, ()> {
+    let mut slice = data;
+    T::deserialize(&mut slice).map_err(|_| ())
+}
+
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct PumpSwapBuyEvent {
+    #[borsh(skip)]
+    pub signature: String,
+    pub timestamp: i64,
+    pub base_amount_out: u64,
+    pub max_quote_amount_in: u64,
+    pub user_base_token_reserves: u64,
+    pub user_quote_token_reserves: u64,
+    pub pool_base_token_reserves: u64,
+    pub pool_quote_token_reserves: u64,
+    pub quote_amount_in: u64,
+    pub lp_fee_basis_points: u64,
+    pub lp_fee: u64,
+    pub protocol_fee_basis_points: u64,
+    pub protocol_fee: u64,
+    pub quote_amount_in_with_lp_fee: u64,
+    pub user_quote_amount_in: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_base_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_quote_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub protocol_fee_recipient: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub protocol_fee_recipient_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub coin_creator: Pubkey,
+    pub coin_creator_fee_basis_points: u64,
+    pub coin_creator_fee: u64,
+}
+
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct PumpSwapSellEvent {
+    #[borsh(skip)]
+    pub signature: String,
+    pub timestamp: i64,
+    pub base_amount_in: u64,
+    pub min_quote_amount_out: u64,
+    pub user_base_token_reserves: u64,
+    pub user_quote_token_reserves: u64,
+    pub pool_base_token_reserves: u64,
+    pub pool_quote_token_reserves: u64,
+    pub quote_amount_out: u64,
+    pub lp_fee_basis_points: u64,
+    pub lp_fee: u64,
+    pub protocol_fee_basis_points: u64,
+    pub protocol_fee: u64,
+    pub quote_amount_out_without_lp_fee: u64,
+    pub user_quote_amount_out: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_base_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_quote_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub protocol_fee_recipient: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub protocol_fee_recipient_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub coin_creator: Pubkey,
+    pub coin_creator_fee_basis_points: u64,
+    pub coin_creator_fee: u64,
+}
+
+/// A PumpSwap trade, buy and sell carry different amounts so they are kept apart
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PumpSwapTradeEvent {
+    Buy(PumpSwapBuyEvent),
+    Sell(PumpSwapSellEvent),
+}
+
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct PumpSwapCreatePoolEvent {
+    #[borsh(skip)]
+    pub signature: String,
+    pub timestamp: i64,
+    pub index: u16,
+    #[serde_as(as = "DisplayFromStr")]
+    pub creator: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub base_mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub quote_mint: Pubkey,
+    pub base_mint_decimals: u8,
+    pub quote_mint_decimals: u8,
+    pub base_amount_in: u64,
+    pub quote_amount_in: u64,
+    pub pool_base_amount: u64,
+    pub pool_quote_amount: u64,
+    pub minimum_liquidity: u64,
+    pub initial_liquidity: u64,
+    pub lp_token_amount_out: u64,
+    pub pool_bump: u8,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub lp_mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_base_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_quote_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub coin_creator: Pubkey,
+}
+
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct PumpSwapDepositEvent {
+    #[borsh(skip)]
+    pub signature: String,
+    pub timestamp: i64,
+    pub lp_token_amount_out: u64,
+    pub max_base_amount_in: u64,
+    pub max_quote_amount_in: u64,
+    pub user_base_token_reserves: u64,
+    pub user_quote_token_reserves: u64,
+    pub pool_base_token_reserves: u64,
+    pub pool_quote_token_reserves: u64,
+    pub base_amount_in: u64,
+    pub quote_amount_in: u64,
+    pub lp_mint_supply: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_base_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_quote_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_pool_token_account: Pubkey,
+}
+
+#[serde_as]
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct PumpSwapWithdrawEvent {
+    #[borsh(skip)]
+    pub signature: String,
+    pub timestamp: i64,
+    pub lp_token_amount_in: u64,
+    pub min_base_amount_out: u64,
+    pub min_quote_amount_out: u64,
+    pub user_base_token_reserves: u64,
+    pub user_quote_token_reserves: u64,
+    pub pool_base_token_reserves: u64,
+    pub pool_quote_token_reserves: u64,
+    pub base_amount_out: u64,
+    pub quote_amount_out: u64,
+    pub lp_mint_supply: u64,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_base_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_quote_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_pool_token_account: Pubkey,
+}
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct Pool {
+    pub pool_bump: u8,
+    pub index: u16,
+    /// Creator of the pool, the pump.fun migration authority for graduated tokens
+    pub creator: Pubkey,
+    pub base_mint: Pubkey,
+    pub quote_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub pool_base_token_account: Pubkey,
+    pub pool_quote_token_account: Pubkey,
+    /// True circulating supply without burns and lock ups
+    pub lp_supply: u64,
+    /// Creator of the coin, receives the coin creator fee
+    pub coin_creator: Pubkey,
+}
+
+impl Pool {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        if data.len() < 8 || data[..8] != POOL_ACCOUNT_DISCRIMINATOR {
+            return Err(());
+        }
+        decode_event(&data[8..])
+    }
+    pub async fn fetch(rpc: &RpcClient, pool_address: &Pubkey) -> Result<Self, ()> {
+        let account = rpc.get_account(pool_address).await.map_err(|_| ())?;
+
+        if account.owner != PUMPSWAP {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+}
+
+#[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
+pub struct GlobalConfig {
+    /// The admin pubkey
+    pub admin: Pubkey,
+    /// Fee going to liquidity providers, in basis points
+    pub lp_fee_basis_points: u64,
+    /// Fee going to the protocol, in basis points
+    pub protocol_fee_basis_points: u64,
+    /// Bit flags disabling create pool, deposit, withdraw, buy and sell
+    pub disable_flags: u8,
+    /// Addresses of the protocol fee recipients
+    pub protocol_fee_recipients: [Pubkey; 8],
+    /// Fee going to the coin creator, in basis points
+    pub coin_creator_fee_basis_points: u64,
+    /// Authority allowed to set the coin creator of a pool
+    pub admin_set_coin_creator_authority: Pubkey,
+}
+
+impl GlobalConfig {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        if data.len() < 8 || data[..8] != GLOBAL_CONFIG_ACCOUNT_DISCRIMINATOR {
+            return Err(());
+        }
+        decode_event(&data[8..])
+    }
+    pub async fn fetch(rpc: &RpcClient) -> Result<Self, ()> {
+        let account = rpc
+            .get_account(&PumpSwap::get_global_config_pda())
+            .await
+            .map_err(|_| ())?;
+
+        if account.owner != PUMPSWAP {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+    /// Picks one of the protocol fee recipients, any of them is accepted by the program
+    pub fn protocol_fee_recipient(&self) -> Option<Pubkey> {
+        self.protocol_fee_recipients
+            .iter()
+            .find(|x| **x != Pubkey::default())
+            .copied()
+    }
+}
+
+pub struct PumpSwap;
+impl PumpSwap {
+    pub fn get_global_config_pda() -> Pubkey {
+        Pubkey::find_program_address(&[GLOBAL_CONFIG_SEED], &PUMPSWAP).0
+    }
+    pub fn get_event_authority_pda() -> Pubkey {
+        Pubkey::find_program_address(&[EVENT_AUTHORITY_SEED], &PUMPSWAP).0
+    }
+    pub fn get_coin_creator_vault_authority_pda(coin_creator: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[CREATOR_VAULT_SEED, coin_creator.as_ref()], &PUMPSWAP).0
+    }
+    pub fn get_coin_creator_vault_ata(
+        coin_creator: &Pubkey,
+        quote_mint: &Pubkey,
+        quote_token_program: &Pubkey,
+    ) -> Pubkey {
+        get_associated_token_address_with_program_id(
+            &Self::get_coin_creator_vault_authority_pda(coin_creator),
+            quote_mint,
+            quote_token_program,
+        )
+    }
+    pub fn get_global_volume_accumulator_pda() -> Pubkey {
+        Pubkey::find_program_address(&[b"global_volume_accumulator"], &PUMPSWAP).0
+    }
+    pub fn get_user_volume_accumulator_pda(user: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[b"user_volume_accumulator", user.as_ref()], &PUMPSWAP).0
+    }
+    pub fn get_fee_config_pda() -> Pubkey {
+        Pubkey::find_program_address(&[FEE_CONFIG_SEED, PUMPSWAP.as_ref()], &PUMP_FEE_PROGRAM).0
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct Buy {
+    pub base_amount_out: u64,
+    pub max_quote_amount_in: u64,
+}
+impl Buy {
+    pub const DISCRIMINATOR: [u8; 8] = [102, 6, 61, 18, 1, 218, 235, 234];
+    pub fn data(&self) -> Result<Vec<u8>, ()> {
+        let mut data = Vec::with_capacity(256);
+        data.extend_from_slice(&Self::DISCRIMINATOR);
+        BorshSerialize::serialize(self, &mut data).map_err(|_| ())?;
+        Ok(data)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct Sell {
+    pub base_amount_in: u64,
+    pub min_quote_amount_out: u64,
+}
+impl Sell {
+    pub const DISCRIMINATOR: [u8; 8] = [51, 230, 133, 164, 1, 127, 131, 173];
+    pub fn data(&self) -> Result<Vec<u8>, ()> {
+        let mut data = Vec::with_capacity(256);
+        data.extend_from_slice(&Self::DISCRIMINATOR);
+        BorshSerialize::serialize(self, &mut data).map_err(|_| ())?;
+        Ok(data)
+    }
+}
+
+/// Accounts shared by the buy and sell instructions, in program order
+fn swap_accounts(
+    payer: &Pubkey,
+    pool_address: &Pubkey,
+    pool: &Pool,
+    protocol_fee_recipient: &Pubkey,
+    base_token_program: &Pubkey,
+    quote_token_program: &Pubkey,
+) -> Vec<AccountMeta> {
+    vec![
+        AccountMeta::new(*pool_address, false),
+        AccountMeta::new(*payer, true),
+        AccountMeta::new_readonly(PumpSwap::get_global_config_pda(), false),
+        AccountMeta::new_readonly(pool.base_mint, false),
+        AccountMeta::new_readonly(pool.quote_mint, false),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(
+                payer,
+                &pool.base_mint,
+                base_token_program,
+            ),
+            false,
+        ),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(
+                payer,
+                &pool.quote_mint,
+                quote_token_program,
+            ),
+            false,
+        ),
+        AccountMeta::new(pool.pool_base_token_account, false),
+        AccountMeta::new(pool.pool_quote_token_account, false),
+        AccountMeta::new_readonly(*protocol_fee_recipient, false),
+        AccountMeta::new(
+            get_associated_token_address_with_program_id(
+                protocol_fee_recipient,
+                &pool.quote_mint,
+                quote_token_program,
+            ),
+            false,
+        ),
+        AccountMeta::new_readonly(*base_token_program, false),
+        AccountMeta::new_readonly(*quote_token_program, false),
+        AccountMeta::new_readonly(SYSTEM_PROGRAM, false),
+        AccountMeta::new_readonly(ASSOCIATED_TOKEN_PROGRAM, false),
+        AccountMeta::new_readonly(PumpSwap::get_event_authority_pda(), false),
+        AccountMeta::new_readonly(PUMPSWAP, false),
+        AccountMeta::new(
+            PumpSwap::get_coin_creator_vault_ata(
+                &pool.coin_creator,
+                &pool.quote_mint,
+                quote_token_program,
+            ),
+            false,
+        ),
+        AccountMeta::new_readonly(
+            PumpSwap::get_coin_creator_vault_authority_pda(&pool.coin_creator),
+            false,
+        ),
+    ]
+}
+
+/// Buys `base_amount_out` of the pool base token, paying at most `max_quote_amount_in`
+///
+/// The user base and quote ATAs must exist, see `SolMut::create_ata`.
+pub fn buy(
+    payer: &Pubkey,
+    pool_address: &Pubkey,
+    pool: &Pool,
+    protocol_fee_recipient: &Pubkey,
+    base_token_program: &Pubkey,
+    quote_token_program: &Pubkey,
+    args: Buy,
+) -> Result<Instruction, ()> {
+    let mut accounts = swap_accounts(
+        payer,
+        pool_address,
+        pool,
+        protocol_fee_recipient,
+        base_token_program,
+        quote_token_program,
+    );
+    accounts.extend([
+        AccountMeta::new(PumpSwap::get_global_volume_accumulator_pda(), false),
+        AccountMeta::new(PumpSwap::get_user_volume_accumulator_pda(payer), false),
+        AccountMeta::new_readonly(PumpSwap::get_fee_config_pda(), false),
+        AccountMeta::new_readonly(PUMP_FEE_PROGRAM, false),
+    ]);
+    Ok(Instruction::new_with_bytes(
+        PUMPSWAP,
+        &args.data()?,
+        accounts,
+    ))
+}
+
+/// Sells `base_amount_in` of the pool base token, receiving at least `min_quote_amount_out`
+pub fn sell(
+    payer: &Pubkey,
+    pool_address: &Pubkey,
+    pool: &Pool,
+    protocol_fee_recipient: &Pubkey,
+    base_token_program: &Pubkey,
+    quote_token_program: &Pubkey,
+    args: Sell,
+) -> Result<Instruction, ()> {
+    let mut accounts = swap_accounts(
+        payer,
+        pool_address,
+        pool,
+        protocol_fee_recipient,
+        base_token_program,
+        quote_token_program,
+    );
+    accounts.extend([
+        AccountMeta::new_readonly(PumpSwap::get_fee_config_pda(), false),
+        AccountMeta::new_readonly(PUMP_FEE_PROGRAM, false),
+    ]);
+    Ok(Instruction::new_with_bytes(
+        PUMPSWAP,
+        &args.data()?,
+        accounts,
+    ))
+}
+
+/// Accounts shared by buy and sell, the leading accounts of `swap_accounts`
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PumpSwapTradeAccounts {
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub base_mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub quote_mint: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_base_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_quote_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool_base_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool_quote_token_account: Pubkey,
+}
+
+impl PumpSwapTradeAccounts {
+    pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+        if accounts.len() < 9 {
+            return Err(());
+        }
+        Ok(Self {
+            pool: accounts[0],
+            user: accounts[1],
+            base_mint: accounts[3],
+            quote_mint: accounts[4],
+            user_base_token_account: accounts[5],
+            user_quote_token_account: accounts[6],
+            pool_base_token_account: accounts[7],
+            pool_quote_token_account: accounts[8],
+        })
+    }
+}
+
+/// A PumpSwap buy or sell decoded from a transaction, carries the user limits even
+/// when the trade failed and emitted no event
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum PumpSwapInstruction {
+    Buy {
+        args: Buy,
+        accounts: PumpSwapTradeAccounts,
+    },
+    Sell {
+        args: Sell,
+        accounts: PumpSwapTradeAccounts,
+    },
+}
+
+impl PumpSwapInstruction {
+    /// Decodes the instruction data and its resolved accounts
+    ///
+    /// # Returns
+    /// * `Err(())` - Not a buy or sell, or truncated data or accounts
+    pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+        if data.len() < 8 {
+            return Err(());
+        }
+        let (discriminator, mut args) = data.split_at(8);
+        // instructions may carry trailing optional args
+        Ok(match <[u8; 8]>::try_from(discriminator).map_err(|_| ())? {
+            Buy::DISCRIMINATOR => Self::Buy {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: PumpSwapTradeAccounts::from_accounts(accounts)?,
+            },
+            Sell::DISCRIMINATOR => Self::Sell {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: PumpSwapTradeAccounts::from_accounts(accounts)?,
+            },
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct PumpSwapInstructionEvent {
+    pub signature: String,
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions, `None` for an outer instruction
+    pub inner_index: Option<usize>,
+    pub instruction: PumpSwapInstruction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::accounts;
+
+    #[test]
+    fn decodes_buy_and_sell() {
+        let keys = accounts(23);
+        let mut data = Buy {
+            base_amount_out: 1_500_000_000,
+            max_quote_amount_in: 42_000_000,
+        }
+        .data()
+        .unwrap();
+        // newer clients append an optional `track_volume` flag
+        data.extend([1, 1]);
+        let PumpSwapInstruction::Buy { args, accounts } =
+            PumpSwapInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a buy");
+        };
+        assert_eq!(args.base_amount_out, 1_500_000_000);
+        assert_eq!(args.max_quote_amount_in, 42_000_000);
+        assert_eq!(accounts.pool, keys[0]);
+        assert_eq!(accounts.user, keys[1]);
+        assert_eq!(accounts.base_mint, keys[3]);
+        assert_eq!(accounts.quote_mint, keys[4]);
+        assert_eq!(accounts.pool_quote_token_account, keys[8]);
+
+        let data = Sell {
+            base_amount_in: 1_500_000_000,
+            min_quote_amount_out: 39_000_000,
+        }
+        .data()
+        .unwrap();
+        let PumpSwapInstruction::Sell { args, accounts } =
+            PumpSwapInstruction::decode(&data, &keys[..21]).unwrap()
+        else {
+            panic!("not a sell");
+        };
+        assert_eq!(args.min_quote_amount_out, 39_000_000);
+        assert_eq!(accounts.user_base_token_account, keys[5]);
+        assert!(PumpSwapInstruction::decode(&data, &keys[..8]).is_err());
+        assert!(PumpSwapInstruction::decode(&data[..12], &keys).is_err());
+    }
+}
@@ -0,0 +1,449 @@
+use borsh::{BorshDeserialize, BorshSerialize};
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_pubkey::pubkey;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
+
+pub const RAYDIUM_AMM_V4_ADDRESS: &str = "675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8";
+pub const RAYDIUM_AMM_V4: Pubkey = pubkey!("675kPX9MHTjS2zt1qfr1NYHuzeLXfQM9H24wFSUt1Mp8");
+
+/// PDA signing for every AMM v4 pool vault
+pub const AMM_AUTHORITY: Pubkey = pubkey!("5Q544fKrFoe6tsEbD7S8EmxGTJYAKtTVhAW5Q5pge4j1");
+
+/// OpenBook (serum v3) program the pools were created against
+pub const OPENBOOK_PROGRAM: Pubkey = pubkey!("srmqPvymJeFKQ4zGQed1GFppgkRHL9kaELCbyksJtPX");
+
+/// Prefix of the base64 record logged by every AMM v4 instruction
+pub const RAY_LOG_PREFIX: &str = "Program log: ray_log: ";
+
+pub const SWAP_BASE_IN_TAG: u8 = 9;
+pub const SWAP_BASE_OUT_TAG: u8 = 11;
+
+/// First byte of a ray_log record
+#[repr(u8)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogType {
+    Init = 0,
+    Deposit = 1,
+    Withdraw = 2,
+    SwapBaseIn = 3,
+    SwapBaseOut = 4,
+}
+
+/// Swap direction as logged by the program
+pub const DIRECTION_PC_TO_COIN: u64 = 1;
+pub const DIRECTION_COIN_TO_PC: u64 = 2;
+
+#[derive(BorshDeserialize, Clone, Debug)]
+pub struct SwapBaseInLog {
+    pub log_type: u8,
+    pub amount_in: u64,
+    pub minimum_out: u64,
+    pub direction: u64,
+    /// Balance of the user source token account before the swap
+    pub user_source: u64,
+    pub pool_coin: u64,
+    pub pool_pc: u64,
+    pub out_amount: u64,
+}
+
+#[derive(BorshDeserialize, Clone, Debug)]
+pub struct SwapBaseOutLog {
+    pub log_type: u8,
+    pub max_in: u64,
+    pub amount_out: u64,
+    pub direction: u64,
+    /// Balance of the user source token account before the swap
+    pub user_source: u64,
+    pub pool_coin: u64,
+    pub pool_pc: u64,
+    pub deduct_in: u64,
+}
+
+/// A swap decoded from a `ray_log` record, normalised over base-in and base-out
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RaydiumAmmV4SwapEvent {
+    pub signature: String,
+    /// True for swap_base_in, false for swap_base_out
+    pub base_input: bool,
+    /// True when coin is sold for pc
+    pub coin_to_pc: bool,
+    /// Amount actually taken from the user
+    pub amount_in: u64,
+    /// Amount actually sent to the user
+    pub amount_out: u64,
+    /// `minimum_out` for base-in swaps, `max_in` for base-out swaps
+    pub limit: u64,
+    /// Balance of the user source token account before the swap
+    pub user_source: u64,
+    /// Pool coin reserve before the swap
+    pub pool_coin: u64,
+    /// Pool pc reserve before the swap
+    pub pool_pc: u64,
+}
+
+impl RaydiumAmmV4SwapEvent {
+    /// Decodes a base64-decoded ray_log record, returns `None` for non swap records
+    pub fn from_ray_log(decoded: &[u8]) -> Option<Self> {
+        let log_type = *decoded.first()?;
+        if log_type == LogType::SwapBaseIn as u8 {
+            let log = SwapBaseInLog::deserialize(&mut &decoded[..]).ok()?;
+            Some(Self {
+                signature: String::new(),
+                base_input: true,
+                coin_to_pc: log.direction == DIRECTION_COIN_TO_PC,
+                amount_in: log.amount_in,
+                amount_out: log.out_amount,
+                limit: log.minimum_out,
+                user_source: log.user_source,
+                pool_coin: log.pool_coin,
+                pool_pc: log.pool_pc,
+            })
+        } else if log_type == LogType::SwapBaseOut as u8 {
+            let log = SwapBaseOutLog::deserialize(&mut &decoded[..]).ok()?;
+            Some(Self {
+                signature: String::new(),
+                base_input: false,
+                coin_to_pc: log.direction == DIRECTION_COIN_TO_PC,
+                amount_in: log.deduct_in,
+                amount_out: log.amount_out,
+                limit: log.max_in,
+                user_source: log.user_source,
+                pool_coin: log.pool_coin,
+                pool_pc: log.pool_pc,
+            })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct Fees {
+    pub min_separate_numerator: u64,
+    pub min_separate_denominator: u64,
+    pub trade_fee_numerator: u64,
+    pub trade_fee_denominator: u64,
+    pub pnl_numerator: u64,
+    pub pnl_denominator: u64,
+    pub swap_fee_numerator: u64,
+    pub swap_fee_denominator: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct StateData {
+    pub need_take_pnl_coin: u64,
+    pub need_take_pnl_pc: u64,
+    pub total_pnl_pc: u64,
+    pub total_pnl_coin: u64,
+    pub pool_open_time: u64,
+    pub padding: [u64; 2],
+    pub orderbook_to_init_time: u64,
+    pub swap_coin_in_amount: u128,
+    pub swap_pc_out_amount: u128,
+    pub swap_acc_pc_fee: u64,
+    pub swap_pc_in_amount: u128,
+    pub swap_coin_out_amount: u128,
+    pub swap_acc_coin_fee: u64,
+}
+
+/// The AMM v4 pool account, a plain 752 bytes struct without anchor discriminator
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct AmmInfo {
+    pub status: u64,
+    pub nonce: u64,
+    pub order_num: u64,
+    pub depth: u64,
+    pub coin_decimals: u64,
+    pub pc_decimals: u64,
+    pub state: u64,
+    pub reset_flag: u64,
+    pub min_size: u64,
+    pub vol_max_cut_ratio: u64,
+    pub amount_wave: u64,
+    pub coin_lot_size: u64,
+    pub pc_lot_size: u64,
+    pub min_price_multiplier: u64,
+    pub max_price_multiplier: u64,
+    pub sys_decimal_value: u64,
+    pub fees: Fees,
+    pub state_data: StateData,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub coin_vault_mint: Pubkey,
+    pub pc_vault_mint: Pubkey,
+    pub lp_mint: Pubkey,
+    pub open_orders: Pubkey,
+    pub market: Pubkey,
+    pub market_program: Pubkey,
+    pub target_orders: Pubkey,
+    pub padding1: [u64; 8],
+    pub amm_owner: Pubkey,
+    pub lp_amount: u64,
+    pub client_order_id: u64,
+    pub recent_epoch: u64,
+    pub padding2: u64,
+}
+
+impl AmmInfo {
+    pub const LEN: usize = 752;
+
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        if data.len() < Self::LEN {
+            return Err(());
+        }
+        Self::try_from_slice(&data[..Self::LEN]).map_err(|_| ())
+    }
+    pub async fn fetch(rpc: &RpcClient, amm: &Pubkey) -> Result<Self, ()> {
+        let account = rpc.get_account(amm).await.map_err(|_| ())?;
+
+        if account.owner != RAYDIUM_AMM_V4 {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+}
+
+/// OpenBook market accounts required by the swap instructions
+#[derive(Clone, Copy, Debug)]
+pub struct SerumMarketKeys {
+    pub bids: Pubkey,
+    pub asks: Pubkey,
+    pub event_queue: Pubkey,
+    pub coin_vault: Pubkey,
+    pub pc_vault: Pubkey,
+    pub vault_signer: Pubkey,
+}
+
+impl SerumMarketKeys {
+    /// Reads the keys out of a serum v3 `MarketState` account
+    pub fn from_market_account(
+        market: &Pubkey,
+        market_program: &Pubkey,
+        data: &[u8],
+    ) -> Result<Self, ()> {
+        fn key_at(data: &[u8], offset: usize) -> Result<Pubkey, ()> {
+            let bytes: [u8; 32] = data
+                .get(offset..offset + 32)
+                .ok_or(())?
+                .try_into()
+                .map_err(|_| ())?;
+            Ok(Pubkey::new_from_array(bytes))
+        }
+        // offsets include the 5 bytes of "serum" padding preceding the state
+        let nonce = u64::from_le_bytes(data.get(45..53).ok_or(())?.try_into().map_err(|_| ())?);
+        let vault_signer = Pubkey::create_program_address(
+            &[market.as_ref(), &nonce.to_le_bytes()],
+            market_program,
+        )
+        .map_err(|_| ())?;
+        Ok(Self {
+            coin_vault: key_at(data, 117)?,
+            pc_vault: key_at(data, 165)?,
+            event_queue: key_at(data, 253)?,
+            bids: key_at(data, 285)?,
+            asks: key_at(data, 317)?,
+            vault_signer,
+        })
+    }
+    pub async fn fetch(rpc: &RpcClient, amm: &AmmInfo) -> Result<Self, ()> {
+        let account = rpc.get_account(&amm.market).await.map_err(|_| ())?;
+
+        if account.owner != amm.market_program {
+            return Err(());
+        }
+        Self::from_market_account(&amm.market, &amm.market_program, &account.data)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct SwapBaseIn {
+    pub amount_in: u64,
+    pub minimum_amount_out: u64,
+}
+impl SwapBaseIn {
+    pub fn data(&self) -> Result<Vec<u8>, ()> {
+        let mut data = Vec::with_capacity(17);
+        data.push(SWAP_BASE_IN_TAG);
+        BorshSerialize::serialize(self, &mut data).map_err(|_| ())?;
+        Ok(data)
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct SwapBaseOut {
+    pub max_amount_in: u64,
+    pub amount_out: u64,
+}
+
+/// Swaps exactly `amount_in` of the source token, the direction follows the source
+/// and destination token accounts
+pub fn swap_base_in(
+    amm: &Pubkey,
+    amm_info: &AmmInfo,
+    market_keys: &SerumMarketKeys,
+    user_source_token_account: &Pubkey,
+    user_destination_token_account: &Pubkey,
+    user_owner: &Pubkey,
+    args: SwapBaseIn,
+) -> Result<Instruction, ()> {
+    Ok(Instruction::new_with_bytes(
+        RAYDIUM_AMM_V4,
+        &args.data()?,
+        vec![
+            AccountMeta::new_readonly(spl_token::ID, false),
+            AccountMeta::new(*amm, false),
+            AccountMeta::new_readonly(AMM_AUTHORITY, false),
+            AccountMeta::new(amm_info.open_orders, false),
+            AccountMeta::new(amm_info.target_orders, false),
+            AccountMeta::new(amm_info.coin_vault, false),
+            AccountMeta::new(amm_info.pc_vault, false),
+            AccountMeta::new_readonly(amm_info.market_program, false),
+            AccountMeta::new(amm_info.market, false),
+            AccountMeta::new(market_keys.bids, false),
+            AccountMeta::new(market_keys.asks, false),
+            AccountMeta::new(market_keys.event_queue, false),
+            AccountMeta::new(market_keys.coin_vault, false),
+            AccountMeta::new(market_keys.pc_vault, false),
+            AccountMeta::new_readonly(market_keys.vault_signer, false),
+            AccountMeta::new(*user_source_token_account, false),
+            AccountMeta::new(*user_destination_token_account, false),
+            AccountMeta::new_readonly(*user_owner, true),
+        ],
+    ))
+}
+
+/// Accounts of a swap, the pool takes 17 or, with its target orders, 18 accounts
+/// and the user accounts come last
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AmmV4SwapAccounts {
+    #[serde_as(as = "DisplayFromStr")]
+    pub amm: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub coin_vault: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pc_vault: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_source_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_destination_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub user_owner: Pubkey,
+}
+
+impl AmmV4SwapAccounts {
+    pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+        let vaults = match accounts.len() {
+            17 => 4,
+            18 => 5,
+            _ => return Err(()),
+        };
+        Ok(Self {
+            amm: accounts[1],
+            coin_vault: accounts[vaults],
+            pc_vault: accounts[vaults + 1],
+            user_source_token_account: accounts[accounts.len() - 3],
+            user_destination_token_account: accounts[accounts.len() - 2],
+            user_owner: accounts[accounts.len() - 1],
+        })
+    }
+}
+
+/// An AMM v4 swap decoded from a transaction
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum AmmV4Instruction {
+    SwapBaseIn {
+        args: SwapBaseIn,
+        accounts: AmmV4SwapAccounts,
+    },
+    SwapBaseOut {
+        args: SwapBaseOut,
+        accounts: AmmV4SwapAccounts,
+    },
+}
+
+impl AmmV4Instruction {
+    /// Decodes the instruction data, a one byte tag then the args, and its
+    /// resolved accounts
+    ///
+    /// # Returns
+    /// * `Err(())` - Not a swap, or truncated data or accounts
+    pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+        let (tag, mut args) = data.split_first().ok_or(())?;
+        Ok(match *tag {
+            SWAP_BASE_IN_TAG => Self::SwapBaseIn {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: AmmV4SwapAccounts::from_accounts(accounts)?,
+            },
+            SWAP_BASE_OUT_TAG => Self::SwapBaseOut {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: AmmV4SwapAccounts::from_accounts(accounts)?,
+            },
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct AmmV4InstructionEvent {
+    pub signature: String,
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions, `None` for an outer instruction
+    pub inner_index: Option<usize>,
+    pub instruction: AmmV4Instruction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::accounts;
+
+    #[test]
+    fn decodes_swaps_with_and_without_target_orders() {
+        let data = SwapBaseIn {
+            amount_in: 1_000_000_000,
+            minimum_amount_out: 148_000_000,
+        }
+        .data()
+        .unwrap();
+        let keys = accounts(18);
+        let AmmV4Instruction::SwapBaseIn { args, accounts } =
+            AmmV4Instruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a swap_base_in");
+        };
+        assert_eq!(args.amount_in, 1_000_000_000);
+        assert_eq!(args.minimum_amount_out, 148_000_000);
+        assert_eq!(accounts.amm, keys[1]);
+        assert_eq!(accounts.coin_vault, keys[5]);
+        assert_eq!(accounts.pc_vault, keys[6]);
+        assert_eq!(accounts.user_source_token_account, keys[15]);
+        assert_eq!(accounts.user_owner, keys[17]);
+
+        let data = [
+            &[SWAP_BASE_OUT_TAG][..],
+            &borsh::to_vec(&SwapBaseOut {
+                max_amount_in: 1_010_000_000,
+                amount_out: 148_000_000,
+            })
+            .unwrap(),
+        ]
+        .concat();
+        let AmmV4Instruction::SwapBaseOut { args, accounts } =
+            AmmV4Instruction::decode(&data, &keys[1..]).unwrap()
+        else {
+            panic!("not a swap_base_out");
+        };
+        assert_eq!(args.max_amount_in, 1_010_000_000);
+        assert_eq!(accounts.coin_vault, keys[5]);
+        assert_eq!(accounts.user_owner, keys[17]);
+        assert!(AmmV4Instruction::decode(&data, &keys[2..]).is_err());
+        assert!(AmmV4Instruction::decode(&[10], &keys).is_err());
+    }
+}
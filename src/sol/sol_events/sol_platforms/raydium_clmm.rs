@@ -1,12 +1,88 @@
-use base64::{Engine, prelude::BASE64_STANDARD};
 use borsh::{BorshDeserialize, BorshSerialize};
 use serde::{Deserialize, Serialize};
-use solana_sdk::pubkey::Pubkey;
+use solana_client::nonblocking::rpc_client::RpcClient;
+use solana_pubkey::pubkey;
+use solana_sdk::{
+    instruction::{AccountMeta, Instruction},
+    pubkey::Pubkey,
+};
 // use solana_pubkey::Pubkey;
 use serde_with::{DisplayFromStr, serde_as};
 
-use crate::sol::sol_events::MutEvents;
+use crate::sol::sol_math::clmm::{self, ClmmPool, ClmmSwapResult, ClmmTick, TickMath};
 pub const RAYDIUM_CLMM_ADDRESS: &str = "CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK";
+pub const RAYDIUM_CLMM: Pubkey = pubkey!("CAMMCzo5YL8w4VFF8KVHrK22GGUsp5VTaW7grrKgrWqK");
+
+pub const MEMO_PROGRAM: Pubkey = pubkey!("MemoSq4gqABAXKb96qnH8TysNcWxMyWCqXgDLGmfcHr");
+
+pub const POOL_STATE_DISCRIMINATOR: [u8; 8] = [247, 237, 227, 245, 215, 195, 222, 70];
+pub const AMM_CONFIG_DISCRIMINATOR: [u8; 8] = [218, 244, 33, 104, 203, 203, 43, 111];
+pub const TICK_ARRAY_STATE_DISCRIMINATOR: [u8; 8] = [192, 155, 85, 205, 49, 249, 129, 42];
+pub const SWAP_EVENT_DISCRIMINATOR: [u8; 8] = [64, 198, 205, 232, 38, 8, 113, 226];
+/// The legacy swap, it takes the same args as swap_v2
+pub const SWAP_DISCRIMINATOR: [u8; 8] = [248, 198, 158, 145, 225, 117, 135, 200];
+
+pub const POOL_SEED: &[u8] = b"pool";
+pub const POOL_VAULT_SEED: &[u8] = b"pool_vault";
+pub const OBSERVATION_SEED: &[u8] = b"observation";
+pub const TICK_ARRAY_SEED: &[u8] = b"tick_array";
+pub const POOL_TICK_ARRAY_BITMAP_SEED: &[u8] = b"pool_tick_array_bitmap_extension";
+
+pub const TICK_ARRAY_SIZE: i32 = 60;
+/// The pool bitmap tracks 512 tick arrays on each side of tick 0, arrays further
+/// away are tracked by the bitmap extension account
+pub const TICK_ARRAY_BITMAP_SIZE: i32 = 512;
+pub const MIN_TICK: i32 = -443636;
+pub const MAX_TICK: i32 = -MIN_TICK;
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+pub const MAX_SQRT_PRICE_X64: u128 = 79226673521066979257578248091;
+/// Anchor error names, custom code `6000 + index`
+pub const ERROR_NAMES: &[&str] = &[
+    "LOK",
+    "NotApproved",
+    "InvalidUpdateConfigFlag",
+    "AccountLack",
+    "ClosePositionErr",
+    "ZeroMintAmount",
+    "InvaildTickIndex",
+    "TickInvaildOrder",
+    "TickLowerOverflow",
+    "TickUpperOverflow",
+    "TickAndSpacingNotMatch",
+    "InvalidTickArray",
+    "InvalidTickArrayBoundary",
+    "SqrtPriceLimitOverflow",
+    "SqrtPriceX64",
+    "LiquiditySubValueErr",
+    "LiquidityAddValueErr",
+    "InvaildLiquidity",
+    "ForbidBothZeroForSupplyLiquidity",
+    "LiquidityInsufficient",
+    "TransactionTooOld",
+    "PriceSlippageCheck",
+    "TooLittleOutputReceived",
+    "TooMuchInputPaid",
+    "ZeroAmountSpecified",
+    "InvalidInputPoolVault",
+    "TooSmallInputOrOutputAmount",
+    "NotEnoughTickArrayAccount",
+    "InvalidFirstTickArrayAccount",
+    "InvalidRewardIndex",
+    "FullRewardInfo",
+    "RewardTokenAlreadyInUse",
+    "ExceptPoolVaultMint",
+    "InvalidRewardInitParam",
+    "InvalidRewardDesiredAmount",
+    "InvalidRewardInputAccountNumber",
+    "InvalidRewardPeriod",
+    "NotApproveUpdateRewardEmissiones",
+    "UnInitializedRewardInfo",
+    "NotSupportMint",
+    "MissingTickArrayBitmapExtensionAccount",
+    "InsufficientLiquidityForDirection",
+    "MaxTokenOverflow",
+    "CalculateOverflow",
+];
 #[serde_as]
 #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
 pub struct RaydiumClmmSwapEvent {
@@ -54,3 +130,662 @@ pub struct RaydiumClmmSwapEvent {
     /// The log base 1.0001 of price of the pool after the swap
     pub tick: i32,
 }
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct RewardInfo {
+    pub reward_state: u8,
+    pub open_time: u64,
+    pub end_time: u64,
+    pub last_update_time: u64,
+    pub emissions_per_second_x64: u128,
+    pub reward_total_emissioned: u64,
+    pub reward_claimed: u64,
+    pub token_mint: Pubkey,
+    pub token_vault: Pubkey,
+    pub authority: Pubkey,
+    pub reward_growth_global_x64: u128,
+}
+
+/// The CLMM pool account, a packed zero copy struct
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct PoolState {
+    pub bump: [u8; 1],
+    pub amm_config: Pubkey,
+    pub owner: Pubkey,
+    pub token_mint_0: Pubkey,
+    pub token_mint_1: Pubkey,
+    pub token_vault_0: Pubkey,
+    pub token_vault_1: Pubkey,
+    pub observation_key: Pubkey,
+    pub mint_decimals_0: u8,
+    pub mint_decimals_1: u8,
+    pub tick_spacing: u16,
+    /// Liquidity of the current tick range
+    pub liquidity: u128,
+    /// Q64.64 square root of the price of token 0 in token 1
+    pub sqrt_price_x64: u128,
+    pub tick_current: i32,
+    pub padding3: u16,
+    pub padding4: u16,
+    pub fee_growth_global_0_x64: u128,
+    pub fee_growth_global_1_x64: u128,
+    pub protocol_fees_token_0: u64,
+    pub protocol_fees_token_1: u64,
+    pub swap_in_amount_token_0: u128,
+    pub swap_out_amount_token_1: u128,
+    pub swap_in_amount_token_1: u128,
+    pub swap_out_amount_token_0: u128,
+    pub status: u8,
+    pub padding: [u8; 7],
+    pub reward_infos: [RewardInfo; 3],
+    /// One bit per initialized tick array, from -512 to 511 arrays around tick 0
+    pub tick_array_bitmap: [u64; 16],
+    pub total_fees_token_0: u64,
+    pub total_fees_claimed_token_0: u64,
+    pub total_fees_token_1: u64,
+    pub total_fees_claimed_token_1: u64,
+    pub fund_fees_token_0: u64,
+    pub fund_fees_token_1: u64,
+    pub open_time: u64,
+    pub recent_epoch: u64,
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct AmmConfig {
+    pub bump: u8,
+    pub index: u16,
+    pub owner: Pubkey,
+    /// Share of the trade fee going to the protocol, over 1e6
+    pub protocol_fee_rate: u32,
+    /// Trade fee, over 1e6
+    pub trade_fee_rate: u32,
+    pub tick_spacing: u16,
+    pub fund_fee_rate: u32,
+    pub padding_u32: u32,
+    pub fund_owner: Pubkey,
+    pub padding: [u64; 3],
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Copy, Debug, Default)]
+pub struct TickState {
+    pub tick: i32,
+    /// Liquidity added when crossing the tick left to right
+    pub liquidity_net: i128,
+    pub liquidity_gross: u128,
+    pub fee_growth_outside_0_x64: u128,
+    pub fee_growth_outside_1_x64: u128,
+    pub reward_growths_outside_x64: [u128; 3],
+    pub padding: [u32; 13],
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug)]
+pub struct TickArrayState {
+    pub pool_id: Pubkey,
+    pub start_tick_index: i32,
+    pub ticks: [TickState; TICK_ARRAY_SIZE as usize],
+    pub initialized_tick_count: u8,
+    pub recent_epoch: u64,
+}
+
+fn check_discriminator(data: &[u8], discriminator: &[u8; 8]) -> Result<(), ()> {
+    if data.len() < 8 || data[..8] != *discriminator {
+        return Err(());
+    }
+    Ok(())
+}
+
+impl PoolState {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        check_discriminator(data, &POOL_STATE_DISCRIMINATOR)?;
+        // trailing padding is not decoded
+        Self::deserialize(&mut &data[8..]).map_err(|_| ())
+    }
+    pub async fn fetch(rpc: &RpcClient, pool_address: &Pubkey) -> Result<Self, ()> {
+        let account = rpc.get_account(pool_address).await.map_err(|_| ())?;
+
+        if account.owner != RAYDIUM_CLMM {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+
+    /// Number of ticks covered by one tick array of this pool
+    pub fn ticks_in_array(&self) -> i32 {
+        self.tick_spacing as i32 * TICK_ARRAY_SIZE
+    }
+    /// Start index of the tick array containing `tick`
+    pub fn tick_array_start_index(&self, tick: i32) -> i32 {
+        tick.div_euclid(self.ticks_in_array()) * self.ticks_in_array()
+    }
+    /// Whether the pool bitmap marks the tick array as initialized, `None` when the
+    /// array is only tracked by the bitmap extension
+    pub fn is_tick_array_initialized(&self, start_index: i32) -> Option<bool> {
+        let offset = start_index / self.ticks_in_array() + TICK_ARRAY_BITMAP_SIZE;
+        if !(0..2 * TICK_ARRAY_BITMAP_SIZE).contains(&offset) {
+            return None;
+        }
+        let offset = offset as usize;
+        Some((self.tick_array_bitmap[offset / 64] >> (offset % 64)) & 1 == 1)
+    }
+    /// Start indexes of the first `count` initialized tick arrays a swap walks
+    /// through, starting at the array holding the current tick
+    pub fn swap_tick_array_start_indexes(&self, zero_for_one: bool, count: usize) -> Vec<i32> {
+        let step = if zero_for_one {
+            -self.ticks_in_array()
+        } else {
+            self.ticks_in_array()
+        };
+        let mut start_index = self.tick_array_start_index(self.tick_current);
+        let mut res = Vec::with_capacity(count);
+        while res.len() < count {
+            match self.is_tick_array_initialized(start_index) {
+                Some(true) => res.push(start_index),
+                Some(false) => (),
+                None => break,
+            }
+            start_index += step;
+        }
+        res
+    }
+}
+
+impl PoolState {
+    /// Quotes a swap against the supplied tick arrays, which must cover every
+    /// tick the swap crosses.
+    ///
+    /// # Arguments
+    /// * `amount` - Exact input when `is_base_input`, exact output otherwise
+    /// * `sqrt_price_limit_x64` - Defaults to the pool bounds
+    pub fn quote(
+        &self,
+        amm_config: &AmmConfig,
+        tick_arrays: &[TickArrayState],
+        amount: u64,
+        is_base_input: bool,
+        zero_for_one: bool,
+        sqrt_price_limit_x64: Option<u128>,
+    ) -> Result<ClmmSwapResult, ()> {
+        let tick_lower_bound = tick_arrays
+            .iter()
+            .map(|x| x.start_tick_index)
+            .min()
+            .ok_or(())?;
+        let tick_upper_bound = tick_arrays
+            .iter()
+            .map(|x| x.start_tick_index)
+            .max()
+            .ok_or(())?
+            + self.ticks_in_array();
+        let mut ticks: Vec<ClmmTick> = tick_arrays
+            .iter()
+            .flat_map(|x| x.ticks.iter())
+            .filter(|x| x.liquidity_gross != 0)
+            .map(|x| ClmmTick {
+                tick: x.tick,
+                liquidity_net: x.liquidity_net,
+            })
+            .collect();
+        ticks.sort_by_key(|x| x.tick);
+        ticks.dedup_by_key(|x| x.tick);
+
+        let pool = ClmmPool {
+            sqrt_price_x64: self.sqrt_price_x64,
+            tick_current: self.tick_current,
+            liquidity: self.liquidity,
+            fee_rate: amm_config.trade_fee_rate,
+            tick_math: TickMath::RaydiumClmm,
+            tick_lower_bound,
+            tick_upper_bound,
+        };
+        clmm::swap(
+            &pool,
+            &ticks,
+            amount,
+            is_base_input,
+            zero_for_one,
+            sqrt_price_limit_x64,
+        )
+    }
+}
+
+impl AmmConfig {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        check_discriminator(data, &AMM_CONFIG_DISCRIMINATOR)?;
+        Self::deserialize(&mut &data[8..]).map_err(|_| ())
+    }
+    pub async fn fetch(rpc: &RpcClient, amm_config: &Pubkey) -> Result<Self, ()> {
+        let account = rpc.get_account(amm_config).await.map_err(|_| ())?;
+
+        if account.owner != RAYDIUM_CLMM {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+}
+
+impl TickArrayState {
+    pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+        check_discriminator(data, &TICK_ARRAY_STATE_DISCRIMINATOR)?;
+        Self::deserialize(&mut &data[8..]).map_err(|_| ())
+    }
+    pub async fn fetch(rpc: &RpcClient, tick_array: &Pubkey) -> Result<Self, ()> {
+        let account = rpc.get_account(tick_array).await.map_err(|_| ())?;
+
+        if account.owner != RAYDIUM_CLMM {
+            return Err(());
+        }
+        Self::from_bytes(&account.data)
+    }
+}
+
+pub struct RaydiumClmm;
+impl RaydiumClmm {
+    pub fn get_pool_pda(amm_config: &Pubkey, mint_0: &Pubkey, mint_1: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                POOL_SEED,
+                amm_config.as_ref(),
+                mint_0.as_ref(),
+                mint_1.as_ref(),
+            ],
+            &RAYDIUM_CLMM,
+        )
+        .0
+    }
+    pub fn get_pool_vault_pda(pool_state: &Pubkey, mint: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[POOL_VAULT_SEED, pool_state.as_ref(), mint.as_ref()],
+            &RAYDIUM_CLMM,
+        )
+        .0
+    }
+    pub fn get_observation_pda(pool_state: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(&[OBSERVATION_SEED, pool_state.as_ref()], &RAYDIUM_CLMM).0
+    }
+    /// The start index is encoded big endian in the seed
+    pub fn get_tick_array_pda(pool_state: &Pubkey, start_tick_index: i32) -> Pubkey {
+        Pubkey::find_program_address(
+            &[
+                TICK_ARRAY_SEED,
+                pool_state.as_ref(),
+                &start_tick_index.to_be_bytes(),
+            ],
+            &RAYDIUM_CLMM,
+        )
+        .0
+    }
+    pub fn get_tick_array_bitmap_extension_pda(pool_state: &Pubkey) -> Pubkey {
+        Pubkey::find_program_address(
+            &[POOL_TICK_ARRAY_BITMAP_SEED, pool_state.as_ref()],
+            &RAYDIUM_CLMM,
+        )
+        .0
+    }
+}
+
+#[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+pub struct SwapV2 {
+    pub amount: u64,
+    /// Minimum out for base input swaps, maximum in otherwise
+    pub other_amount_threshold: u64,
+    /// 0 lets the program use the min or max price
+    pub sqrt_price_limit_x64: u128,
+    pub is_base_input: bool,
+}
+impl SwapV2 {
+    pub const DISCRIMINATOR: [u8; 8] = [43, 4, 237, 11, 26, 201, 30, 98];
+    pub fn data(&self) -> Result<Vec<u8>, ()> {
+        let mut data = Vec::with_capacity(41);
+        data.extend_from_slice(&Self::DISCRIMINATOR);
+        BorshSerialize::serialize(self, &mut data).map_err(|_| ())?;
+        Ok(data)
+    }
+}
+
+/// Number of tick arrays passed to a swap, a swap crossing more fails on chain
+pub const SWAP_TICK_ARRAY_COUNT: usize = 3;
+
+/// Builds a swap_v2, tick arrays are picked from the pool bitmap for the given
+/// direction. Fails when no initialized tick array is tracked by the pool bitmap.
+pub fn swap_v2(
+    payer: &Pubkey,
+    pool_address: &Pubkey,
+    pool: &PoolState,
+    input_token_account: &Pubkey,
+    output_token_account: &Pubkey,
+    zero_for_one: bool,
+    args: SwapV2,
+) -> Result<Instruction, ()> {
+    let tick_arrays = pool.swap_tick_array_start_indexes(zero_for_one, SWAP_TICK_ARRAY_COUNT);
+    if tick_arrays.is_empty() {
+        return Err(());
+    }
+    let (input_vault, output_vault, input_mint, output_mint) = if zero_for_one {
+        (
+            pool.token_vault_0,
+            pool.token_vault_1,
+            pool.token_mint_0,
+            pool.token_mint_1,
+        )
+    } else {
+        (
+            pool.token_vault_1,
+            pool.token_vault_0,
+            pool.token_mint_1,
+            pool.token_mint_0,
+        )
+    };
+
+    let mut accounts = vec![
+        AccountMeta::new_readonly(*payer, true),
+        AccountMeta::new_readonly(pool.amm_config, false),
+        AccountMeta::new(*pool_address, false),
+        AccountMeta::new(*input_token_account, false),
+        AccountMeta::new(*output_token_account, false),
+        AccountMeta::new(input_vault, false),
+        AccountMeta::new(output_vault, false),
+        AccountMeta::new(pool.observation_key, false),
+        AccountMeta::new_readonly(spl_token::ID, false),
+        AccountMeta::new_readonly(spl_token_2022::ID, false),
+        AccountMeta::new_readonly(MEMO_PROGRAM, false),
+        AccountMeta::new_readonly(input_mint, false),
+        AccountMeta::new_readonly(output_mint, false),
+        // remaining accounts, the bitmap extension comes first
+        AccountMeta::new_readonly(
+            RaydiumClmm::get_tick_array_bitmap_extension_pda(pool_address),
+            false,
+        ),
+    ];
+    accounts.extend(tick_arrays.into_iter().map(|start_index| {
+        AccountMeta::new(
+            RaydiumClmm::get_tick_array_pda(pool_address, start_index),
+            false,
+        )
+    }));
+
+    Ok(Instruction::new_with_bytes(
+        RAYDIUM_CLMM,
+        &args.data()?,
+        accounts,
+    ))
+}
+
+/// Accounts shared by swap and swap_v2, only swap_v2 passes the mints
+#[serde_as]
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClmmSwapAccounts {
+    #[serde_as(as = "DisplayFromStr")]
+    pub payer: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub amm_config: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub pool_state: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub input_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub output_token_account: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub input_vault: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub output_vault: Pubkey,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub input_vault_mint: Option<Pubkey>,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub output_vault_mint: Option<Pubkey>,
+}
+
+impl ClmmSwapAccounts {
+    pub fn from_accounts(accounts: &[Pubkey], swap_v2: bool) -> Result<Self, ()> {
+        if accounts.len() < if swap_v2 { 13 } else { 7 } {
+            return Err(());
+        }
+        Ok(Self {
+            payer: accounts[0],
+            amm_config: accounts[1],
+            pool_state: accounts[2],
+            input_token_account: accounts[3],
+            output_token_account: accounts[4],
+            input_vault: accounts[5],
+            output_vault: accounts[6],
+            input_vault_mint: swap_v2.then(|| accounts[11]),
+            output_vault_mint: swap_v2.then(|| accounts[12]),
+        })
+    }
+}
+
+/// A Raydium CLMM swap decoded from a transaction
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub enum ClmmInstruction {
+    Swap {
+        args: SwapV2,
+        accounts: ClmmSwapAccounts,
+    },
+    SwapV2 {
+        args: SwapV2,
+        accounts: ClmmSwapAccounts,
+    },
+}
+
+impl ClmmInstruction {
+    /// Decodes the instruction data and its resolved accounts
+    ///
+    /// # Returns
+    /// * `Err(())` - Not a swap, or truncated data or accounts
+    pub fn decode(data: &[u8], accounts: &[Pubkey]) -> Result<Self, ()> {
+        if data.len() < 8 {
+            return Err(());
+        }
+        let (discriminator, mut args) = data.split_at(8);
+        Ok(match <[u8; 8]>::try_from(discriminator).map_err(|_| ())? {
+            SWAP_DISCRIMINATOR => Self::Swap {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: ClmmSwapAccounts::from_accounts(accounts, false)?,
+            },
+            SwapV2::DISCRIMINATOR => Self::SwapV2 {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: ClmmSwapAccounts::from_accounts(accounts, true)?,
+            },
+            _ => return Err(()),
+        })
+    }
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct ClmmInstructionEvent {
+    pub signature: String,
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions, `None` for an outer instruction
+    pub inner_index: Option<usize>,
+    pub instruction: ClmmInstruction,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::{accounts, clmm_pool};
+
+    fn swap_args() -> SwapV2 {
+        SwapV2 {
+            amount: 5_000_000_000,
+            other_amount_threshold: 720_000_000,
+            sqrt_price_limit_x64: 0,
+            is_base_input: true,
+        }
+    }
+
+    #[test]
+    fn tick_array_start_indexes_round_towards_negative_infinity() {
+        let initialized = [-1_800, -1_200, -600, 0, 600, 1_200];
+        let pool = clmm_pool(-1, &initialized);
+        assert_eq!(pool.tick_array_start_index(-1), -600);
+        assert_eq!(pool.tick_array_start_index(-600), -600);
+        assert_eq!(pool.tick_array_start_index(-601), -1_200);
+        assert_eq!(pool.tick_array_start_index(599), 0);
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(true, 3),
+            [-600, -1_200, -1_800]
+        );
+        assert_eq!(pool.swap_tick_array_start_indexes(false, 3), [-600, 0, 600]);
+
+        // a tick on an array edge starts in that array in both directions
+        let pool = clmm_pool(600, &initialized);
+        assert_eq!(pool.swap_tick_array_start_indexes(true, 3), [600, 0, -600]);
+        assert_eq!(pool.swap_tick_array_start_indexes(false, 3), [600, 1_200]);
+        let pool = clmm_pool(-600, &initialized);
+        assert_eq!(
+            pool.swap_tick_array_start_indexes(true, 3),
+            [-600, -1_200, -1_800]
+        );
+    }
+
+    #[test]
+    fn swap_tick_arrays_skip_uninitialized_arrays_and_stop_at_the_bitmap_edge() {
+        let pool = clmm_pool(-1, &[-3_000, -600, 1_800]);
+        assert_eq!(pool.swap_tick_array_start_indexes(true, 2), [-600, -3_000]);
+        assert_eq!(pool.swap_tick_array_start_indexes(false, 2), [-600, 1_800]);
+        // the pool bitmap ends with the array starting at 511 * 600
+        let pool = clmm_pool(306_601, &[306_600]);
+        assert_eq!(pool.is_tick_array_initialized(307_200), None);
+        assert_eq!(pool.swap_tick_array_start_indexes(false, 3), [306_600]);
+        let pool = clmm_pool(-307_200, &[-307_200]);
+        assert_eq!(pool.swap_tick_array_start_indexes(true, 3), [-307_200]);
+        // the current array is only tracked by the bitmap extension
+        let pool = clmm_pool(-307_201, &[-307_200]);
+        assert!(pool.swap_tick_array_start_indexes(false, 3).is_empty());
+    }
+
+    #[test]
+    fn swap_v2_accounts_follow_the_idl() {
+        let pool_address = Pubkey::new_unique();
+        let pool = clmm_pool(-1, &[-1_200, -600]);
+        let (payer, input, output) = (
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+            Pubkey::new_unique(),
+        );
+        let ix = swap_v2(
+            &payer,
+            &pool_address,
+            &pool,
+            &input,
+            &output,
+            false,
+            swap_args(),
+        )
+        .unwrap();
+        assert_eq!(ix.program_id, RAYDIUM_CLMM);
+        assert_eq!(ix.data, swap_args().data().unwrap());
+        assert_eq!(ix.data.len(), 41);
+        let expected = [
+            (payer, false, true),
+            (pool.amm_config, false, false),
+            (pool_address, true, false),
+            (input, true, false),
+            (output, true, false),
+            // one for zero, the input vault and mint are token 1's
+            (pool.token_vault_1, true, false),
+            (pool.token_vault_0, true, false),
+            (pool.observation_key, true, false),
+            (spl_token::ID, false, false),
+            (spl_token_2022::ID, false, false),
+            (MEMO_PROGRAM, false, false),
+            (pool.token_mint_1, false, false),
+            (pool.token_mint_0, false, false),
+            (
+                RaydiumClmm::get_tick_array_bitmap_extension_pda(&pool_address),
+                false,
+                false,
+            ),
+            // -1200 sits behind the current array for a one for zero swap
+            (
+                RaydiumClmm::get_tick_array_pda(&pool_address, -600),
+                true,
+                false,
+            ),
+        ];
+        let actual: Vec<_> = ix
+            .accounts
+            .iter()
+            .map(|x| (x.pubkey, x.is_writable, x.is_signer))
+            .collect();
+        assert_eq!(actual, expected);
+
+        // the decoder reads back the accounts the builder wrote
+        let keys: Vec<_> = ix.accounts.iter().map(|x| x.pubkey).collect();
+        let ClmmInstruction::SwapV2 { accounts, .. } =
+            ClmmInstruction::decode(&ix.data, &keys).unwrap()
+        else {
+            panic!("not a swap_v2");
+        };
+        assert_eq!(accounts.input_vault, pool.token_vault_1);
+        assert_eq!(accounts.output_vault_mint, Some(pool.token_mint_0));
+
+        let ix = swap_v2(
+            &payer,
+            &pool_address,
+            &pool,
+            &input,
+            &output,
+            true,
+            swap_args(),
+        )
+        .unwrap();
+        assert_eq!(ix.accounts.len(), 16);
+        assert_eq!(ix.accounts[5].pubkey, pool.token_vault_0);
+        assert_eq!(ix.accounts[11].pubkey, pool.token_mint_0);
+        assert_eq!(
+            ix.accounts[15].pubkey,
+            RaydiumClmm::get_tick_array_pda(&pool_address, -1_200)
+        );
+        // no initialized tick array in the pool bitmap
+        let pool = clmm_pool(-1, &[]);
+        assert!(
+            swap_v2(
+                &payer,
+                &pool_address,
+                &pool,
+                &input,
+                &output,
+                true,
+                swap_args()
+            )
+            .is_err()
+        );
+    }
+
+    #[test]
+    fn decodes_swap_v2() {
+        let keys = accounts(17);
+        let ClmmInstruction::SwapV2 { args, accounts } =
+            ClmmInstruction::decode(&swap_args().data().unwrap(), &keys).unwrap()
+        else {
+            panic!("not a swap_v2");
+        };
+        assert_eq!(args.amount, 5_000_000_000);
+        assert_eq!(args.other_amount_threshold, 720_000_000);
+        assert!(args.is_base_input);
+        assert_eq!(accounts.payer, keys[0]);
+        assert_eq!(accounts.pool_state, keys[2]);
+        assert_eq!(accounts.input_vault, keys[5]);
+        assert_eq!(accounts.output_vault, keys[6]);
+        assert_eq!(accounts.input_vault_mint, Some(keys[11]));
+        assert_eq!(accounts.output_vault_mint, Some(keys[12]));
+        assert!(ClmmInstruction::decode(&swap_args().data().unwrap(), &keys[..12]).is_err());
+    }
+
+    #[test]
+    fn decodes_the_legacy_swap() {
+        let data = [&SWAP_DISCRIMINATOR[..], &swap_args().data().unwrap()[8..]].concat();
+        let keys = accounts(11);
+        let ClmmInstruction::Swap { args, accounts } =
+            ClmmInstruction::decode(&data, &keys).unwrap()
+        else {
+            panic!("not a swap");
+        };
+        assert_eq!(args.amount, 5_000_000_000);
+        assert_eq!(accounts.output_token_account, keys[4]);
+        assert_eq!(accounts.input_vault_mint, None);
+        assert!(ClmmInstruction::decode(&data[..20], &keys).is_err());
+    }
+}
@@ -1,6 +1,6 @@
 pub mod cpmm {
     use serde_with::{DisplayFromStr, serde_as};
-    #[derive(Debug, Clone, BorshDeserialize)]
+    #[derive(Debug, Clone, BorshSerialize, BorshDeserialize)]
     pub struct Pool {
         pub amm_config: Pubkey,
         pub pool_creator: Pubkey,
@@ -45,28 +45,98 @@ pub mod cpmm {
         pub input_transfer_fee: u64,
         pub output_transfer_fee: u64,
         pub base_input: bool,
-        // pub user: Pubkey,
+        /// Only logged by program versions with creator fees
+        #[borsh(skip)]
+        pub extension: Option<RaydiumCpmmSwapEventExtension>,
+    }
+
+    /// Fields appended to `SwapEvent` by later program versions
+    #[serde_as]
+    #[derive(Clone, Debug, Default, PartialEq, Eq, BorshDeserialize, Deserialize, Serialize)]
+    pub struct RaydiumCpmmSwapEventExtension {
+        #[serde_as(as = "DisplayFromStr")]
+        pub input_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub output_mint: Pubkey,
+        pub trade_fee: u64,
+        pub creator_fee: u64,
+        pub creator_fee_on_input: bool,
     }
 
+    impl RaydiumCpmmSwapEvent {
+        /// Decodes the event data following the discriminator, both with and
+        /// without the trailing fields of newer program versions
+        pub fn decode(data: &[u8]) -> Result<Self, ()> {
+            let mut data = data;
+            let mut event = <Self as BorshDeserialize>::deserialize(&mut data).map_err(|_| ())?;
+            if !data.is_empty() {
+                event.extension = BorshDeserialize::deserialize(&mut data).ok();
+            }
+            Ok(event)
+        }
+    }
+
+    /// A CPMM swap attributed to its user, out of the event and the swap instruction
+    /// that emitted it
+    #[serde_as]
     #[derive(Clone, Debug, Deserialize, Serialize)]
     pub struct RaydiumParsedCpmmEvent {
+        pub signature: String,
+        /// Default when the swap instruction could not be found
+        #[serde_as(as = "DisplayFromStr")]
         pub user: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
         pub input_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
         pub output_mint: Pubkey,
         pub input_amount: u64,
-        // pub output_amount: u64,
+        pub output_amount: u64,
+        #[serde_as(as = "DisplayFromStr")]
         pub pool_state: Pubkey,
+        pub event: RaydiumCpmmSwapEvent,
+    }
+
+    impl RaydiumParsedCpmmEvent {
+        /// # Arguments
+        /// * `swap_accounts` - Accounts of the swap instruction that emitted the event
+        ///
+        /// # Returns
+        /// * `None` - Neither the instruction nor the event carry the mints
+        pub fn new(event: RaydiumCpmmSwapEvent, swap_accounts: Option<&[Pubkey]>) -> Option<Self> {
+            let (user, pool_state, input_mint, output_mint) =
+                match (swap_accounts, &event.extension) {
+                    (Some(accounts), _) if accounts.len() > 11 => {
+                        (accounts[0], accounts[3], accounts[10], accounts[11])
+                    }
+                    (_, Some(extension)) => (
+                        Pubkey::default(),
+                        event.pool_id,
+                        extension.input_mint,
+                        extension.output_mint,
+                    ),
+                    _ => return None,
+                };
+            Some(Self {
+                signature: event.signature.clone(),
+                user,
+                input_mint,
+                output_mint,
+                input_amount: event.input_amount,
+                output_amount: event.output_amount,
+                pool_state,
+                event,
+            })
+        }
     }
     use borsh::{BorshDeserialize, BorshSerialize};
     use serde::{Deserialize, Serialize};
     use solana_client::nonblocking::rpc_client::RpcClient;
     use solana_program::pubkey;
     use solana_sdk::{
-        bs58,
         instruction::{AccountMeta, Instruction},
         pubkey::Pubkey,
     };
-    use solana_transaction_status::UiCompiledInstruction;
+    use spl_associated_token_account::get_associated_token_address_with_program_id;
 
     pub const POOL_SEED: &[u8] = b"pool";
     pub const POOL_VAULT_SEED: &[u8] = b"pool_vault";
@@ -79,33 +149,288 @@ pub mod cpmm {
     pub const SWAP_BASE_IN_DISCRIMINATOR: &[u8] = &[143, 190, 90, 218, 196, 30, 51, 222];
     pub const SWAP_BASE_OUT_DISCRIMINATOR: &[u8] = &[55, 217, 98, 86, 163, 74, 180, 173];
     pub const INITIALIZE_DISCRI: &[u8] = &[175, 175, 109, 31, 13, 152, 155, 237];
+    pub const AMM_CONFIG_DISCRIMINATOR: [u8; 8] = [218, 244, 33, 104, 203, 203, 43, 111];
+    /// `PoolState`, shared with the CLMM pool account
+    pub const POOL_STATE_DISCRIMINATOR: [u8; 8] = [247, 237, 227, 245, 215, 195, 222, 70];
+    /// Fee rates are expressed over 1e6
+    pub const FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+    pub const DEPOSIT_DISCRIMINATOR: &[u8] = &[242, 35, 198, 137, 82, 225, 242, 182];
+    pub const WITHDRAW_DISCRIMINATOR: &[u8] = &[183, 18, 70, 156, 148, 109, 161, 34];
+    /// `SwapEvent` shares its name, hence its discriminator, with the CLMM event
+    pub const SWAP_EVENT_DISCRIMINATOR: [u8; 8] = [64, 198, 205, 232, 38, 8, 113, 226];
     pub const RAYDIUM_CPMM: &str = "CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C";
-    #[derive(BorshSerialize, BorshDeserialize)]
+    pub const RAYDIUM_CPMM_PROGRAM: Pubkey =
+        pubkey!("CPMMoo8L3F4NbTegBCKVNunggL7H1ZpdTHKxQB5qKP1C");
+    /// Anchor error names, custom code `6000 + index`
+    pub const ERROR_NAMES: &[&str] = &[
+        "NotApproved",
+        "InvalidOwner",
+        "EmptySupply",
+        "InvalidInput",
+        "IncorrectLpMint",
+        "ExceededSlippage",
+        "ZeroTradingTokens",
+        "NotSupportMint",
+        "InvalidVault",
+        "InitLpAmountTooLess",
+        "TransferFeeCalculateNotMatch",
+        "MathOverflow",
+        "InsufficientVault",
+        "InvalidFeeModel",
+        "NoFeeCollect",
+    ];
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
     pub struct RaydiumBuyBaseIn {
         pub amount_in: u64,
         pub minimum_amount_out: u64,
     }
 
-    #[derive(BorshSerialize, BorshDeserialize)]
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+    pub struct RaydiumSwapBaseOut {
+        pub max_amount_in: u64,
+        pub amount_out: u64,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
     pub struct RaydiumCpmmInit {
-        init_amount_0: u64,
-        init_amount_1: u64,
-        open_time: u64,
+        pub init_amount_0: u64,
+        pub init_amount_1: u64,
+        pub open_time: u64,
     }
 
-    pub async fn parse_raydium_cpmm_ix(data: &str, accounts: Vec<String>) {
-        let decoded = bs58::decode(data).into_vec().unwrap();
-        let discriminator = &decoded[..8];
-        match &discriminator {
-            &[143, 190, 90, 218, 196, 30, 51, 222] => {
-                // swap base in
-            }
-            &[55, 217, 98, 86, 163, 74, 180, 173] => {
-                // swap base out
-            }
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+    pub struct RaydiumCpmmDeposit {
+        pub lp_token_amount: u64,
+        pub maximum_token_0_amount: u64,
+        pub maximum_token_1_amount: u64,
+    }
+
+    #[derive(BorshSerialize, BorshDeserialize, Clone, Debug, Serialize, Deserialize)]
+    pub struct RaydiumCpmmWithdraw {
+        pub lp_token_amount: u64,
+        pub minimum_token_0_amount: u64,
+        pub minimum_token_1_amount: u64,
+    }
+
+    fn account_at(accounts: &[Pubkey], index: usize) -> Result<Pubkey, ()> {
+        accounts.get(index).copied().ok_or(())
+    }
+
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CpmmSwapAccounts {
+        #[serde_as(as = "DisplayFromStr")]
+        pub payer: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub amm_config: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub pool_state: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub input_token_account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub output_token_account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub input_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub output_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub input_token_program: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub output_token_program: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub input_token_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub output_token_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub observation_state: Pubkey,
+    }
+
+    impl CpmmSwapAccounts {
+        pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+            Ok(Self {
+                payer: account_at(accounts, 0)?,
+                amm_config: account_at(accounts, 2)?,
+                pool_state: account_at(accounts, 3)?,
+                input_token_account: account_at(accounts, 4)?,
+                output_token_account: account_at(accounts, 5)?,
+                input_vault: account_at(accounts, 6)?,
+                output_vault: account_at(accounts, 7)?,
+                input_token_program: account_at(accounts, 8)?,
+                output_token_program: account_at(accounts, 9)?,
+                input_token_mint: account_at(accounts, 10)?,
+                output_token_mint: account_at(accounts, 11)?,
+                observation_state: account_at(accounts, 12)?,
+            })
+        }
+    }
+
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CpmmInitializeAccounts {
+        #[serde_as(as = "DisplayFromStr")]
+        pub creator: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub amm_config: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub pool_state: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_0_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_1_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub lp_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub creator_token_0: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub creator_token_1: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub creator_lp_token: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_0_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_1_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub create_pool_fee: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub observation_state: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_0_program: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_1_program: Pubkey,
+    }
+
+    impl CpmmInitializeAccounts {
+        pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+            Ok(Self {
+                creator: account_at(accounts, 0)?,
+                amm_config: account_at(accounts, 1)?,
+                pool_state: account_at(accounts, 3)?,
+                token_0_mint: account_at(accounts, 4)?,
+                token_1_mint: account_at(accounts, 5)?,
+                lp_mint: account_at(accounts, 6)?,
+                creator_token_0: account_at(accounts, 7)?,
+                creator_token_1: account_at(accounts, 8)?,
+                creator_lp_token: account_at(accounts, 9)?,
+                token_0_vault: account_at(accounts, 10)?,
+                token_1_vault: account_at(accounts, 11)?,
+                create_pool_fee: account_at(accounts, 12)?,
+                observation_state: account_at(accounts, 13)?,
+                token_0_program: account_at(accounts, 15)?,
+                token_1_program: account_at(accounts, 16)?,
+            })
+        }
+    }
+
+    /// Accounts shared by deposit and withdraw
+    #[serde_as]
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CpmmLiquidityAccounts {
+        #[serde_as(as = "DisplayFromStr")]
+        pub owner: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub pool_state: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub owner_lp_token: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_0_account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_1_account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_0_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub token_1_vault: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub vault_0_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub vault_1_mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        pub lp_mint: Pubkey,
+    }
+
+    impl CpmmLiquidityAccounts {
+        pub fn from_accounts(accounts: &[Pubkey]) -> Result<Self, ()> {
+            Ok(Self {
+                owner: account_at(accounts, 0)?,
+                pool_state: account_at(accounts, 2)?,
+                owner_lp_token: account_at(accounts, 3)?,
+                token_0_account: account_at(accounts, 4)?,
+                token_1_account: account_at(accounts, 5)?,
+                token_0_vault: account_at(accounts, 6)?,
+                token_1_vault: account_at(accounts, 7)?,
+                vault_0_mint: account_at(accounts, 10)?,
+                vault_1_mint: account_at(accounts, 11)?,
+                lp_mint: account_at(accounts, 12)?,
+            })
+        }
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub enum CpmmInstruction {
+        SwapBaseInput {
+            args: RaydiumBuyBaseIn,
+            accounts: CpmmSwapAccounts,
+        },
+        SwapBaseOutput {
+            args: RaydiumSwapBaseOut,
+            accounts: CpmmSwapAccounts,
+        },
+        /// A new pool launch
+        Initialize {
+            args: RaydiumCpmmInit,
+            accounts: CpmmInitializeAccounts,
+        },
+        Deposit {
+            args: RaydiumCpmmDeposit,
+            accounts: CpmmLiquidityAccounts,
+        },
+        Withdraw {
+            args: RaydiumCpmmWithdraw,
+            accounts: CpmmLiquidityAccounts,
+        },
+    }
+
+    #[derive(Clone, Debug, Serialize, Deserialize)]
+    pub struct CpmmInstructionEvent {
+        pub signature: String,
+        /// Index of the outer instruction
+        pub index: usize,
+        /// Position among the inner instructions, `None` for an outer instruction
+        pub inner_index: Option<usize>,
+        pub instruction: CpmmInstruction,
+    }
 
-            _ => {}
+    /// Decodes a CPMM instruction out of its raw data and resolved accounts
+    ///
+    /// # Returns
+    /// * `Err(())` - Unknown discriminator, truncated data or missing accounts
+    pub fn parse_raydium_cpmm_ix(data: &[u8], accounts: &[Pubkey]) -> Result<CpmmInstruction, ()> {
+        if data.len() < 8 {
+            return Err(());
         }
+        let (discriminator, mut args) = data.split_at(8);
+        Ok(match discriminator {
+            SWAP_BASE_IN_DISCRIMINATOR => CpmmInstruction::SwapBaseInput {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: CpmmSwapAccounts::from_accounts(accounts)?,
+            },
+            SWAP_BASE_OUT_DISCRIMINATOR => CpmmInstruction::SwapBaseOutput {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: CpmmSwapAccounts::from_accounts(accounts)?,
+            },
+            INITIALIZE_DISCRI => CpmmInstruction::Initialize {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: CpmmInitializeAccounts::from_accounts(accounts)?,
+            },
+            DEPOSIT_DISCRIMINATOR => CpmmInstruction::Deposit {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: CpmmLiquidityAccounts::from_accounts(accounts)?,
+            },
+            WITHDRAW_DISCRIMINATOR => CpmmInstruction::Withdraw {
+                args: BorshDeserialize::deserialize(&mut args).map_err(|_| ())?,
+                accounts: CpmmLiquidityAccounts::from_accounts(accounts)?,
+            },
+            _ => return Err(()),
+        })
     }
 
     impl RaydiumBuyBaseIn {
@@ -114,10 +439,102 @@ pub mod cpmm {
         pub fn data(&self) -> Vec<u8> {
             let mut data = Vec::with_capacity(256);
             data.extend_from_slice(&Self::DISCRIMINATOR);
-            self.serialize(&mut data).unwrap();
+            BorshSerialize::serialize(self, &mut data).unwrap();
             data
         }
     }
+    impl RaydiumSwapBaseOut {
+        pub const DISCRIMINATOR: [u8; 8] = [55, 217, 98, 86, 163, 74, 180, 173];
+
+        pub fn data(&self) -> Vec<u8> {
+            let mut data = Vec::with_capacity(24);
+            data.extend_from_slice(&Self::DISCRIMINATOR);
+            BorshSerialize::serialize(self, &mut data).unwrap();
+            data
+        }
+    }
+
+    /// Accounts shared by both swap instructions, the user token accounts are its
+    /// associated token accounts under each mint token program
+    fn swap_accounts(
+        user: &Pubkey,
+        pool_address: &Pubkey,
+        pool: &Pool,
+        zero_for_one: bool,
+    ) -> Vec<AccountMeta> {
+        let (input_vault, output_vault, input_program, output_program, input_mint, output_mint) =
+            if zero_for_one {
+                (
+                    pool.token0_vault,
+                    pool.token1_vault,
+                    pool.token0_program,
+                    pool.token1_program,
+                    pool.token0_mint,
+                    pool.token1_mint,
+                )
+            } else {
+                (
+                    pool.token1_vault,
+                    pool.token0_vault,
+                    pool.token1_program,
+                    pool.token0_program,
+                    pool.token1_mint,
+                    pool.token0_mint,
+                )
+            };
+        vec![
+            AccountMeta::new_readonly(*user, true),
+            AccountMeta::new_readonly(AUTHORITY, false),
+            AccountMeta::new_readonly(pool.amm_config, false),
+            AccountMeta::new(*pool_address, false),
+            AccountMeta::new(
+                get_associated_token_address_with_program_id(user, &input_mint, &input_program),
+                false,
+            ),
+            AccountMeta::new(
+                get_associated_token_address_with_program_id(user, &output_mint, &output_program),
+                false,
+            ),
+            AccountMeta::new(input_vault, false),
+            AccountMeta::new(output_vault, false),
+            AccountMeta::new_readonly(input_program, false),
+            AccountMeta::new_readonly(output_program, false),
+            AccountMeta::new_readonly(input_mint, false),
+            AccountMeta::new_readonly(output_mint, false),
+            AccountMeta::new(pool.observation_key, false),
+        ]
+    }
+
+    /// Swaps exactly `args.amount_in`, token0 for token1 when `zero_for_one`
+    pub fn swap_base_input(
+        user: &Pubkey,
+        pool_address: &Pubkey,
+        pool: &Pool,
+        zero_for_one: bool,
+        args: RaydiumBuyBaseIn,
+    ) -> Instruction {
+        Instruction::new_with_bytes(
+            RAYDIUM_CPMM_PROGRAM,
+            &args.data(),
+            swap_accounts(user, pool_address, pool, zero_for_one),
+        )
+    }
+
+    /// Swaps for exactly `args.amount_out`, token0 for token1 when `zero_for_one`
+    pub fn swap_base_output(
+        user: &Pubkey,
+        pool_address: &Pubkey,
+        pool: &Pool,
+        zero_for_one: bool,
+        args: RaydiumSwapBaseOut,
+    ) -> Instruction {
+        Instruction::new_with_bytes(
+            RAYDIUM_CPMM_PROGRAM,
+            &args.data(),
+            swap_accounts(user, pool_address, pool, zero_for_one),
+        )
+    }
+
     fn get_radium_clmm_vault_pda(pool_state: &Pubkey, mint: &Pubkey) -> Option<Pubkey> {
         let seeds: &[&[u8]; 3] = &[POOL_VAULT_SEED, pool_state.as_ref(), mint.as_ref()];
         let program_id: &Pubkey = &RAYDIUM_CPMM.parse().ok()?;
@@ -137,11 +554,7 @@ pub mod cpmm {
         pool: &Pool,
         sol_amount: u64,
     ) -> Result<u64, ()> {
-        let is_token0_input = if pool.token0_mint == WSOL_TOKEN_ACCOUNT {
-            true
-        } else {
-            false
-        };
+        let is_token0_input = pool.token0_mint == WSOL_TOKEN_ACCOUNT;
         let (reserve_in, reserve_out) = if is_token0_input {
             (token0_balance, token1_balance)
         } else {
@@ -155,8 +568,8 @@ pub mod cpmm {
 
         // 使用 u128 防止溢出
         let amount_in_128 = sol_amount as u128;
-        let reserve_in_128 = reserve_in as u128;
-        let reserve_out_128 = reserve_out as u128;
+        let reserve_in_128 = reserve_in;
+        let reserve_out_128 = reserve_out;
 
         // 恒定乘积公式: amount_out = (amount_in * reserve_out) / (reserve_in + amount_in)
         let numerator = amount_in_128 * reserve_out_128;
@@ -183,11 +596,7 @@ pub mod cpmm {
         pool: &Pool,
         token_amount: u64,
     ) -> Result<u64, ()> {
-        let is_token0_sol = if pool.token0_mint == WSOL_TOKEN_ACCOUNT {
-            true
-        } else {
-            false
-        };
+        let is_token0_sol = pool.token0_mint == WSOL_TOKEN_ACCOUNT;
         let (reserve_in, reserve_out) = if is_token0_sol {
             (token1_balance, token0_balance)
         } else {
@@ -200,8 +609,8 @@ pub mod cpmm {
 
         // 使用 u128 防止溢出
         let amount_in_128 = token_amount as u128;
-        let reserve_in_128 = reserve_in as u128;
-        let reserve_out_128 = reserve_out as u128;
+        let reserve_in_128 = reserve_in;
+        let reserve_out_128 = reserve_out;
 
         // 恒定乘积公式: amount_out = (amount_in * reserve_out) / (reserve_in + amount_in)
         let numerator = amount_in_128 * reserve_out_128;
@@ -230,24 +639,448 @@ pub mod cpmm {
         let token0_balance = rpc.get_token_account_balance(&token0_vault).await.unwrap();
         let token1_vault = get_radium_clmm_vault_pda(pool_state, token1_mint).unwrap();
         let token1_balance = rpc.get_token_account_balance(&token1_vault).await.unwrap();
-        let token0_amount = token0_balance.amount.parse::<u64>().map_err(|e| ())?;
-        let token1_amount = token1_balance.amount.parse::<u64>().map_err(|e| ())?;
+        let token0_amount = token0_balance.amount.parse::<u64>().map_err(|_| ())?;
+        let token1_amount = token1_balance.amount.parse::<u64>().map_err(|_| ())?;
 
         Ok((token0_amount, token1_amount))
     }
 
+    #[derive(Debug, Clone, BorshDeserialize)]
+    pub struct AmmConfig {
+        pub bump: u8,
+        pub disable_create_pool: bool,
+        pub index: u16,
+        /// Trade fee, over 1e6
+        pub trade_fee_rate: u64,
+        /// Share of the trade fee going to the protocol, over 1e6
+        pub protocol_fee_rate: u64,
+        /// Share of the trade fee going to the fund, over 1e6
+        pub fund_fee_rate: u64,
+        pub create_pool_fee: u64,
+        pub protocol_owner: Pubkey,
+        pub fund_owner: Pubkey,
+    }
+
+    impl AmmConfig {
+        pub fn from_bytes(data: &[u8]) -> Result<Self, ()> {
+            if data.len() < 8 || data[..8] != AMM_CONFIG_DISCRIMINATOR {
+                return Err(());
+            }
+            // newer versions append fields before the padding
+            Self::deserialize(&mut &data[8..]).map_err(|_| ())
+        }
+        pub async fn fetch(rpc: &RpcClient, amm_config: &Pubkey) -> Result<Self, ()> {
+            let account = rpc.get_account(amm_config).await.map_err(|_| ())?;
+
+            if account.owner != RAYDIUM_CPMM_PROGRAM {
+                return Err(());
+            }
+            Self::from_bytes(&account.data)
+        }
+    }
+
+    #[derive(Clone, Copy, Debug, Default, Serialize, Deserialize)]
+    pub struct CpmmQuote {
+        /// Input amount, trade fee included
+        pub amount_in: u64,
+        pub amount_out: u64,
+        /// Trade fee, in the input token
+        pub trade_fee: u64,
+        /// Share of `trade_fee` going to the protocol
+        pub protocol_fee: u64,
+        /// Share of `trade_fee` going to the fund
+        pub fund_fee: u64,
+        /// Relative difference between the execution price and the spot price, fees included
+        pub price_impact: f64,
+    }
+
+    impl CpmmQuote {
+        fn new(
+            config: &AmmConfig,
+            reserve_in: u128,
+            reserve_out: u128,
+            amount_in: u128,
+            amount_out: u128,
+            trade_fee: u128,
+        ) -> Result<Self, ()> {
+            let spot_price = reserve_out as f64 / reserve_in as f64;
+            let execution_price = if amount_in == 0 {
+                spot_price
+            } else {
+                amount_out as f64 / amount_in as f64
+            };
+            Ok(Self {
+                amount_in: u64::try_from(amount_in).map_err(|_| ())?,
+                amount_out: u64::try_from(amount_out).map_err(|_| ())?,
+                trade_fee: trade_fee as u64,
+                protocol_fee: (trade_fee * config.protocol_fee_rate as u128 / FEE_RATE_DENOMINATOR)
+                    as u64,
+                fund_fee: (trade_fee * config.fund_fee_rate as u128 / FEE_RATE_DENOMINATOR) as u64,
+                price_impact: (1.0 - execution_price / spot_price).abs(),
+            })
+        }
+    }
+
+    impl Pool {
+        /// Tradable reserves, the vaults also hold the uncollected protocol and fund fees
+        pub fn reserves(&self, vault_0_amount: u64, vault_1_amount: u64) -> Result<(u64, u64), ()> {
+            let reserve_0 = vault_0_amount
+                .checked_sub(self.protocol_fees_token0 + self.fund_fees_token0)
+                .ok_or(())?;
+            let reserve_1 = vault_1_amount
+                .checked_sub(self.protocol_fees_token1 + self.fund_fees_token1)
+                .ok_or(())?;
+            Ok((reserve_0, reserve_1))
+        }
+        fn reserves_for_input(
+            &self,
+            vault_0_amount: u64,
+            vault_1_amount: u64,
+            input_mint: &Pubkey,
+        ) -> Result<(u128, u128), ()> {
+            let (reserve_0, reserve_1) = self.reserves(vault_0_amount, vault_1_amount)?;
+            let (reserve_in, reserve_out) = if *input_mint == self.token0_mint {
+                (reserve_0, reserve_1)
+            } else if *input_mint == self.token1_mint {
+                (reserve_1, reserve_0)
+            } else {
+                return Err(());
+            };
+            if reserve_in == 0 || reserve_out == 0 {
+                return Err(());
+            }
+            Ok((reserve_in as u128, reserve_out as u128))
+        }
+
+        /// Quotes swapping exactly `amount_in` of `input_mint`, as swap_base_input does.
+        /// Creator fees of newer pools are not accounted for.
+        ///
+        /// # Arguments
+        /// * `vault_0_amount` - Balance of `token0_vault`
+        /// * `vault_1_amount` - Balance of `token1_vault`
+        pub fn quote_exact_in(
+            &self,
+            config: &AmmConfig,
+            vault_0_amount: u64,
+            vault_1_amount: u64,
+            input_mint: &Pubkey,
+            amount_in: u64,
+        ) -> Result<CpmmQuote, ()> {
+            let (reserve_in, reserve_out) =
+                self.reserves_for_input(vault_0_amount, vault_1_amount, input_mint)?;
+            let amount_in = amount_in as u128;
+            let trade_fee =
+                (amount_in * config.trade_fee_rate as u128).div_ceil(FEE_RATE_DENOMINATOR);
+            let amount_in_less_fee = amount_in - trade_fee;
+            let amount_out = amount_in_less_fee * reserve_out / (reserve_in + amount_in_less_fee);
+            CpmmQuote::new(
+                config,
+                reserve_in,
+                reserve_out,
+                amount_in,
+                amount_out,
+                trade_fee,
+            )
+        }
+
+        /// Quotes the input needed to receive exactly `amount_out`, as swap_base_output does.
+        /// Creator fees of newer pools are not accounted for.
+        ///
+        /// # Arguments
+        /// * `vault_0_amount` - Balance of `token0_vault`
+        /// * `vault_1_amount` - Balance of `token1_vault`
+        pub fn quote_exact_out(
+            &self,
+            config: &AmmConfig,
+            vault_0_amount: u64,
+            vault_1_amount: u64,
+            input_mint: &Pubkey,
+            amount_out: u64,
+        ) -> Result<CpmmQuote, ()> {
+            let (reserve_in, reserve_out) =
+                self.reserves_for_input(vault_0_amount, vault_1_amount, input_mint)?;
+            let amount_out = amount_out as u128;
+            if amount_out >= reserve_out {
+                return Err(());
+            }
+            let amount_in_less_fee = (amount_out * reserve_in).div_ceil(reserve_out - amount_out);
+            let trade_fee_rate = config.trade_fee_rate as u128;
+            if trade_fee_rate >= FEE_RATE_DENOMINATOR {
+                return Err(());
+            }
+            let amount_in = (amount_in_less_fee * FEE_RATE_DENOMINATOR)
+                .div_ceil(FEE_RATE_DENOMINATOR - trade_fee_rate);
+            CpmmQuote::new(
+                config,
+                reserve_in,
+                reserve_out,
+                amount_in,
+                amount_out,
+                amount_in - amount_in_less_fee,
+            )
+        }
+    }
+
     impl Pool {
         pub fn from_bytes(data: &[u8]) -> Result<Self, std::io::Error> {
-            let pool = Pool::try_from_slice(&data[8..])?;
+            let data = data
+                .strip_prefix(&POOL_STATE_DISCRIMINATOR)
+                .ok_or_else(|| {
+                    std::io::Error::new(std::io::ErrorKind::InvalidData, "not a CPMM pool state")
+                })?;
+            let pool = Pool::try_from_slice(data)?;
             Ok(pool)
         }
         pub async fn fetch(rpc: &RpcClient, pool_address: &Pubkey) -> Result<Self, ()> {
-            let account = rpc.get_account(pool_address).await.map_err(|x| ())?;
+            let account = rpc.get_account(pool_address).await.map_err(|_| ())?;
 
             if account.owner != RAYDIUM_CPMM.parse().unwrap() {
                 return Err(());
             }
-            Self::from_bytes(&account.data).map_err(|x| ())
+            Self::from_bytes(&account.data).map_err(|_| ())
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use solana_sdk::pubkey::Pubkey;
+    use spl_associated_token_account::get_associated_token_address_with_program_id;
+
+    use super::cpmm::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::cpmm_pool;
+
+    // A SOL/USDC pool around 150 USDC with a 0.25% trade fee, 12% of it to the
+    // protocol and 4% to the fund.
+    const VAULT_0: u64 = 1_250_004_000_000;
+    const VAULT_1: u64 = 187_500_600_000;
+
+    fn config() -> AmmConfig {
+        AmmConfig {
+            bump: 255,
+            disable_create_pool: false,
+            index: 0,
+            trade_fee_rate: 2500,
+            protocol_fee_rate: 120_000,
+            fund_fee_rate: 40_000,
+            create_pool_fee: 0,
+            protocol_owner: Pubkey::default(),
+            fund_owner: Pubkey::default(),
+        }
+    }
+
+    #[test]
+    fn swap_accounts_follow_the_idl() {
+        let (user, pool_address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let mut pool = cpmm_pool();
+        pool.token1_program = spl_token_2022::ID;
+        let ix = swap_base_input(
+            &user,
+            &pool_address,
+            &pool,
+            false,
+            RaydiumBuyBaseIn {
+                amount_in: 150_000_000,
+                minimum_amount_out: 990_000_000,
+            },
+        );
+        assert_eq!(ix.program_id, RAYDIUM_CPMM_PROGRAM);
+        // token1 for token0, the input side uses the Token-2022 program
+        let expected = [
+            (user, false, true),
+            (AUTHORITY, false, false),
+            (pool.amm_config, false, false),
+            (pool_address, true, false),
+            (
+                get_associated_token_address_with_program_id(
+                    &user,
+                    &pool.token1_mint,
+                    &spl_token_2022::ID,
+                ),
+                true,
+                false,
+            ),
+            (
+                get_associated_token_address_with_program_id(
+                    &user,
+                    &pool.token0_mint,
+                    &spl_token::ID,
+                ),
+                true,
+                false,
+            ),
+            (pool.token1_vault, true, false),
+            (pool.token0_vault, true, false),
+            (spl_token_2022::ID, false, false),
+            (spl_token::ID, false, false),
+            (pool.token1_mint, false, false),
+            (pool.token0_mint, false, false),
+            (pool.observation_key, true, false),
+        ];
+        let actual: Vec<_> = ix
+            .accounts
+            .iter()
+            .map(|x| (x.pubkey, x.is_writable, x.is_signer))
+            .collect();
+        assert_eq!(actual, expected);
+
+        let ix = swap_base_output(
+            &user,
+            &pool_address,
+            &pool,
+            true,
+            RaydiumSwapBaseOut {
+                max_amount_in: 1_010_000_000,
+                amount_out: 150_000_000,
+            },
+        );
+        assert_eq!(ix.accounts.len(), 13);
+        assert_eq!(ix.accounts[6].pubkey, pool.token0_vault);
+        assert_eq!(ix.accounts[8].pubkey, spl_token::ID);
+        assert_eq!(ix.accounts[9].pubkey, spl_token_2022::ID);
+        assert_eq!(ix.accounts[10].pubkey, pool.token0_mint);
+    }
+
+    #[test]
+    fn swap_data_layout() {
+        let (user, pool_address) = (Pubkey::new_unique(), Pubkey::new_unique());
+        let ix = swap_base_input(
+            &user,
+            &pool_address,
+            &cpmm_pool(),
+            true,
+            RaydiumBuyBaseIn {
+                amount_in: 0x0102_0304_0506_0708,
+                minimum_amount_out: 42,
+            },
+        );
+        assert_eq!(
+            ix.data,
+            [
+                SWAP_BASE_IN_DISCRIMINATOR,
+                &[8, 7, 6, 5, 4, 3, 2, 1],
+                &[42, 0, 0, 0, 0, 0, 0, 0],
+            ]
+            .concat()
+        );
+        let ix = swap_base_output(
+            &user,
+            &pool_address,
+            &cpmm_pool(),
+            true,
+            RaydiumSwapBaseOut {
+                max_amount_in: 1_000,
+                amount_out: 7,
+            },
+        );
+        assert_eq!(
+            ix.data,
+            [
+                SWAP_BASE_OUT_DISCRIMINATOR,
+                &1_000u64.to_le_bytes(),
+                &7u64.to_le_bytes(),
+            ]
+            .concat()
+        );
+
+        // the decoder reads back what the builder wrote
+        let keys: Vec<_> = ix.accounts.iter().map(|x| x.pubkey).collect();
+        let CpmmInstruction::SwapBaseOutput { args, accounts } =
+            parse_raydium_cpmm_ix(&ix.data, &keys).unwrap()
+        else {
+            panic!("not a swap_base_output");
+        };
+        assert_eq!((args.max_amount_in, args.amount_out), (1_000, 7));
+        assert_eq!(accounts.payer, user);
+        assert_eq!(accounts.pool_state, pool_address);
+    }
+
+    #[test]
+    fn leaves_the_uncollected_fees_out_of_the_reserves() {
+        assert_eq!(
+            cpmm_pool().reserves(VAULT_0, VAULT_1),
+            Ok((1_250_000_000_000, 187_500_000_000))
+        );
+        assert!(cpmm_pool().reserves(3_999_999, VAULT_1).is_err());
+    }
+
+    #[test]
+    fn quotes_exact_in_both_ways() {
+        let pool = cpmm_pool();
+        let quote = pool
+            .quote_exact_in(
+                &config(),
+                VAULT_0,
+                VAULT_1,
+                &pool.token0_mint,
+                2_000_000_000,
+            )
+            .unwrap();
+        assert_eq!(quote.amount_in, 2_000_000_000);
+        assert_eq!(quote.amount_out, 298_773_158);
+        assert_eq!(quote.trade_fee, 5_000_000);
+        assert_eq!(quote.protocol_fee, 600_000);
+        assert_eq!(quote.fund_fee, 200_000);
+
+        let quote = pool
+            .quote_exact_in(&config(), VAULT_0, VAULT_1, &pool.token1_mint, 300_000_000)
+            .unwrap();
+        assert_eq!(quote.amount_out, 1_991_821_053);
+        assert_eq!(quote.trade_fee, 750_000);
+        assert_eq!(quote.protocol_fee, 90_000);
+        assert_eq!(quote.fund_fee, 30_000);
+    }
+
+    #[test]
+    fn quotes_exact_out() {
+        let pool = cpmm_pool();
+        let quote = pool
+            .quote_exact_out(&config(), VAULT_0, VAULT_1, &pool.token0_mint, 150_000_000)
+            .unwrap();
+        assert_eq!(quote.amount_in, 1_003_308_914);
+        assert_eq!(quote.amount_out, 150_000_000);
+        assert_eq!(quote.trade_fee, 2_508_273);
+        assert_eq!(quote.protocol_fee, 300_992);
+        assert_eq!(quote.fund_fee, 100_330);
+    }
+
+    #[test]
+    fn round_trips_exact_in_and_exact_out() {
+        let pool = cpmm_pool();
+        let mint = pool.token0_mint;
+        let exact_in = pool
+            .quote_exact_in(&config(), VAULT_0, VAULT_1, &mint, 2_000_000_000)
+            .unwrap();
+        let exact_out = pool
+            .quote_exact_out(&config(), VAULT_0, VAULT_1, &mint, exact_in.amount_out)
+            .unwrap();
+        assert!(exact_out.amount_in <= exact_in.amount_in);
+
+        let exact_out = pool
+            .quote_exact_out(&config(), VAULT_0, VAULT_1, &mint, 150_000_000)
+            .unwrap();
+        let exact_in = pool
+            .quote_exact_in(&config(), VAULT_0, VAULT_1, &mint, exact_out.amount_in)
+            .unwrap();
+        assert!(exact_in.amount_out >= 150_000_000);
+    }
+
+    #[test]
+    fn rejects_other_mints_and_draining_the_pool() {
+        let pool = cpmm_pool();
+        assert!(
+            pool.quote_exact_in(&config(), VAULT_0, VAULT_1, &Pubkey::new_unique(), 1)
+                .is_err()
+        );
+        assert!(
+            pool.quote_exact_out(
+                &config(),
+                VAULT_0,
+                VAULT_1,
+                &pool.token0_mint,
+                187_500_000_000
+            )
+            .is_err()
+        );
+    }
+}
@@ -1,139 +1,648 @@
+use std::str::FromStr;
+
 use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_program::system_instruction::SystemInstruction;
+use solana_pubkey::{Pubkey, pubkey};
+use solana_transaction_status::{
+    UiInstruction, UiParsedInstruction, parse_instruction::ParsedInstruction,
+};
+use spl_token::instruction::TokenInstruction;
+use spl_token_2022::{
+    extension::transfer_fee::instruction::TransferFeeInstruction,
+    instruction::TokenInstruction as Token2022Instruction,
+};
 
 #[derive(Deserialize)]
 pub struct SolanaIx {
     #[serde(alias = "type")]
     pub ix_type: String,
 }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(tag = "type", content = "info", rename_all = "camelCase")]
-// pub enum SolanaInstruction {
-//     GetAccountDataSize {
-//         mint: String,
-//         #[serde(rename = "extensionTypes")]
-//         extension_types: Vec<String>,
-//     },
-//     CreateAccount {
-//         source: String,
-//         #[serde(alias = "newAccount")]
-//         new_account: String,
-//         lamports: u64,
-//         space: u64,
-//         owner: String,
-//     },
-//     InitializeImmutableOwner {
-//         account: String,
-//     },
-//     InitializeAccount3 {
-//         account: String,
-//         mint: String,
-//         owner: String,
-//     },
-//     Transfer {
-//         source: String,
-//         destination: String,
-//         // #[serde(alias = "amount")]
-//         // lamports: u64,
-//         // authority: String,
-//     },
-//     TransferChecked {
-//         source: String,
-//         mint: String,
-//         destination: String,
-//         #[serde(alias = "tokenAmount")]
-//         token_amount: TokenAmount,
-//         authority: String,
-//     },
-//     Create(CreateInfo),               // <--i
-//     Allocate(AllocateInfo),           // <-- New variant here
-//     InitializeMint2(InitializeMint2), // <--- new variant here
-//     CloseAccount(CloseAccountInfo),
-//     Assign(AssignInfo),
-//     CreateIdempotent(CreateIdempotentInfo),
-//     BurnChecked(BurnCheckedInfo),
-//     SyncNative {
-//         account: String,
-//     },
-//     MintTo(MintToInfo),
-//     Burn(BurnInfo),
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct BurnInfo {
-//     pub account: String,
-//     pub mint: String,
-//     pub amount: String,
-//     pub authority: String,
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct MintToInfo {
-//     pub mint: String,
-//     pub account: String,
-//     pub amount: String,
-//     pub mint_authority: String,
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct BurnCheckedInfo {
-//     pub account: String,
-//     pub mint: String,
-//     pub token_amount: TokenAmount,
-//     pub authority: String,
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct CreateIdempotentInfo {
-//     pub source: String,
-//     pub account: String,
-//     pub wallet: String,
-//     pub mint: String,
-//     pub system_program: String,
-//     pub token_program: String,
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct AssignInfo {
-//     pub account: String,
-//     pub owner: String,
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct CloseAccountInfo {
-//     pub account: String,
-//     pub destination: String,
-//     pub owner: String,
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct CreateInfo {
-//     pub source: String,
-//     pub account: String,
-//     pub wallet: String,
-//     pub mint: String,
-//     pub system_program: String,
-//     pub token_program: String,
-// }
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct TokenAmount {
-//     pub ui_amount: f64,
-//     pub decimals: u8,
-//     pub amount: String,
-//     pub ui_amount_string: String,
-// }
-
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct AllocateInfo {
-//     pub account: String,
-//     pub space: u64,
-// }
-
-// #[derive(Debug, Deserialize, Serialize)]
-// #[serde(rename_all = "camelCase")]
-// pub struct InitializeMint2 {
-//     pub mint: String,
-//     pub decimals: u8,
-//     pub mint_authority: String,
-// }
+
+/// The `parsed` field of a `jsonParsed` System, SPL Token, Token-2022 or associated
+/// token account instruction
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(tag = "type", content = "info", rename_all = "camelCase")]
+pub enum SolanaInstruction {
+    CreateAccount {
+        source: String,
+        #[serde(alias = "newAccount")]
+        new_account: String,
+        lamports: u64,
+        space: u64,
+        owner: String,
+    },
+    /// System transfers carry `lamports`, token transfers `amount` and `authority`
+    Transfer {
+        source: String,
+        destination: String,
+        #[serde(default)]
+        amount: Option<String>,
+        #[serde(default)]
+        lamports: Option<u64>,
+        #[serde(default, alias = "multisigAuthority")]
+        authority: Option<String>,
+    },
+    TransferChecked {
+        source: String,
+        mint: String,
+        destination: String,
+        #[serde(alias = "tokenAmount")]
+        token_amount: TokenAmount,
+        #[serde(alias = "multisigAuthority")]
+        authority: String,
+    },
+    /// Token-2022 transfer of a mint with the transfer fee extension
+    TransferCheckedWithFee {
+        source: String,
+        mint: String,
+        destination: String,
+        #[serde(alias = "tokenAmount")]
+        token_amount: TokenAmount,
+        #[serde(alias = "feeAmount")]
+        fee_amount: TokenAmount,
+        #[serde(alias = "multisigAuthority")]
+        authority: String,
+    },
+    Create(CreateInfo),
+    CreateIdempotent(CreateIdempotentInfo),
+    CloseAccount(CloseAccountInfo),
+    SyncNative {
+        account: String,
+    },
+    MintTo(MintToInfo),
+    MintToChecked(MintToCheckedInfo),
+    Burn(BurnInfo),
+    BurnChecked(BurnCheckedInfo),
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BurnInfo {
+    pub account: String,
+    pub mint: String,
+    pub amount: String,
+    #[serde(alias = "multisigAuthority")]
+    pub authority: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MintToInfo {
+    pub mint: String,
+    pub account: String,
+    pub amount: String,
+    #[serde(alias = "multisigMintAuthority")]
+    pub mint_authority: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct MintToCheckedInfo {
+    pub mint: String,
+    pub account: String,
+    pub token_amount: TokenAmount,
+    #[serde(alias = "multisigMintAuthority")]
+    pub mint_authority: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct BurnCheckedInfo {
+    pub account: String,
+    pub mint: String,
+    pub token_amount: TokenAmount,
+    #[serde(alias = "multisigAuthority")]
+    pub authority: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateIdempotentInfo {
+    pub source: String,
+    pub account: String,
+    pub wallet: String,
+    pub mint: String,
+    pub system_program: String,
+    pub token_program: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CloseAccountInfo {
+    pub account: String,
+    pub destination: String,
+    #[serde(alias = "multisigOwner")]
+    pub owner: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateInfo {
+    pub source: String,
+    pub account: String,
+    pub wallet: String,
+    pub mint: String,
+    pub system_program: String,
+    pub token_program: String,
+}
+#[derive(Debug, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TokenAmount {
+    pub ui_amount: Option<f64>,
+    pub decimals: u8,
+    pub amount: String,
+    pub ui_amount_string: String,
+}
+
+/// Lamports or tokens moving, created, or destroyed by an instruction
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub enum TokenFlow {
+    SolTransfer {
+        #[serde_as(as = "DisplayFromStr")]
+        source: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        destination: Pubkey,
+        lamports: u64,
+    },
+    CreateAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        source: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        new_account: Pubkey,
+        lamports: u64,
+        space: u64,
+        #[serde_as(as = "DisplayFromStr")]
+        owner: Pubkey,
+    },
+    /// `mint` and `decimals` are only known for checked transfers, `fee` is the
+    /// Token-2022 transfer fee withheld out of `amount`
+    Transfer {
+        #[serde_as(as = "DisplayFromStr")]
+        source: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        destination: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        authority: Pubkey,
+        #[serde_as(as = "Option<DisplayFromStr>")]
+        mint: Option<Pubkey>,
+        amount: u64,
+        decimals: Option<u8>,
+        fee: Option<u64>,
+    },
+    MintTo {
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint_authority: Pubkey,
+        amount: u64,
+        decimals: Option<u8>,
+    },
+    Burn {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        authority: Pubkey,
+        amount: u64,
+        decimals: Option<u8>,
+    },
+    /// The remaining lamports go to `destination`
+    CloseAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        destination: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        owner: Pubkey,
+    },
+    /// Wrapped SOL account topped up with lamports
+    SyncNative {
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+    },
+    CreateTokenAccount {
+        #[serde_as(as = "DisplayFromStr")]
+        payer: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        account: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        wallet: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        mint: Pubkey,
+        #[serde_as(as = "DisplayFromStr")]
+        token_program: Pubkey,
+        idempotent: bool,
+    },
+}
+
+pub const SYSTEM_PROGRAM: Pubkey = pubkey!("11111111111111111111111111111111");
+
+/// Accounts Jito bundle tips are paid to
+pub const JITO_TIP_ACCOUNTS: [Pubkey; 8] = [
+    pubkey!("96gYZGLnJYVFmbjzopPSU6QiEV5fGqZNyN9nmNhvrZU5"),
+    pubkey!("HFqU5x63VTqvQss8hp11i4wVV8bD44PvwucfZ2bU7gRe"),
+    pubkey!("Cw8CFyM9FkoMi7K7Crf6HNQqf4uEMzpKw6QNghXLvLkY"),
+    pubkey!("ADaUMid9yfUytqMBgopwjb2DTLSokTSzL1zt6iGPaS49"),
+    pubkey!("DfXygSm4jCyNCybVYYK6DwvWqjKee8pbDmJGcLWNDXjh"),
+    pubkey!("ADuUkR4vqLUMWXxW9gh6D6L8pMSawimctcNZ5pGwDcEt"),
+    pubkey!("DttWaMuVvTiduZRnguLF7jNxTgiMBZ1hyAumKUiL2KRL"),
+    pubkey!("3AVi9Tg9Uo68tJfuvoKvqKNWKkC5wPdSSdeBnizKZ6jT"),
+];
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenFlowEvent {
+    pub signature: String,
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions, `None` for an outer instruction
+    pub inner_index: Option<usize>,
+    pub flow: TokenFlow,
+}
+
+fn key(x: &str) -> Option<Pubkey> {
+    Pubkey::from_str(x).ok()
+}
+
+fn amount(x: &str) -> Option<u64> {
+    x.parse().ok()
+}
+
+impl SolanaInstruction {
+    pub fn token_flow(&self) -> Option<TokenFlow> {
+        let flow = match self {
+            SolanaInstruction::CreateAccount {
+                source,
+                new_account,
+                lamports,
+                space,
+                owner,
+            } => TokenFlow::CreateAccount {
+                source: key(source)?,
+                new_account: key(new_account)?,
+                lamports: *lamports,
+                space: *space,
+                owner: key(owner)?,
+            },
+            SolanaInstruction::Transfer {
+                source,
+                destination,
+                amount: token_amount,
+                lamports,
+                authority,
+            } => match (token_amount, lamports) {
+                (Some(token_amount), _) => TokenFlow::Transfer {
+                    source: key(source)?,
+                    destination: key(destination)?,
+                    authority: key(authority.as_deref()?)?,
+                    mint: None,
+                    amount: amount(token_amount)?,
+                    decimals: None,
+                    fee: None,
+                },
+                (None, Some(lamports)) => TokenFlow::SolTransfer {
+                    source: key(source)?,
+                    destination: key(destination)?,
+                    lamports: *lamports,
+                },
+                (None, None) => return None,
+            },
+            SolanaInstruction::TransferChecked {
+                source,
+                mint,
+                destination,
+                token_amount,
+                authority,
+            } => TokenFlow::Transfer {
+                source: key(source)?,
+                destination: key(destination)?,
+                authority: key(authority)?,
+                mint: Some(key(mint)?),
+                amount: amount(&token_amount.amount)?,
+                decimals: Some(token_amount.decimals),
+                fee: None,
+            },
+            SolanaInstruction::TransferCheckedWithFee {
+                source,
+                mint,
+                destination,
+                token_amount,
+                fee_amount,
+                authority,
+            } => TokenFlow::Transfer {
+                source: key(source)?,
+                destination: key(destination)?,
+                authority: key(authority)?,
+                mint: Some(key(mint)?),
+                amount: amount(&token_amount.amount)?,
+                decimals: Some(token_amount.decimals),
+                fee: Some(amount(&fee_amount.amount)?),
+            },
+            SolanaInstruction::Create(x) => TokenFlow::CreateTokenAccount {
+                payer: key(&x.source)?,
+                account: key(&x.account)?,
+                wallet: key(&x.wallet)?,
+                mint: key(&x.mint)?,
+                token_program: key(&x.token_program)?,
+                idempotent: false,
+            },
+            SolanaInstruction::CreateIdempotent(x) => TokenFlow::CreateTokenAccount {
+                payer: key(&x.source)?,
+                account: key(&x.account)?,
+                wallet: key(&x.wallet)?,
+                mint: key(&x.mint)?,
+                token_program: key(&x.token_program)?,
+                idempotent: true,
+            },
+            SolanaInstruction::CloseAccount(x) => TokenFlow::CloseAccount {
+                account: key(&x.account)?,
+                destination: key(&x.destination)?,
+                owner: key(&x.owner)?,
+            },
+            SolanaInstruction::SyncNative { account } => TokenFlow::SyncNative {
+                account: key(account)?,
+            },
+            SolanaInstruction::MintTo(x) => TokenFlow::MintTo {
+                mint: key(&x.mint)?,
+                account: key(&x.account)?,
+                mint_authority: key(&x.mint_authority)?,
+                amount: amount(&x.amount)?,
+                decimals: None,
+            },
+            SolanaInstruction::MintToChecked(x) => TokenFlow::MintTo {
+                mint: key(&x.mint)?,
+                account: key(&x.account)?,
+                mint_authority: key(&x.mint_authority)?,
+                amount: amount(&x.token_amount.amount)?,
+                decimals: Some(x.token_amount.decimals),
+            },
+            SolanaInstruction::Burn(x) => TokenFlow::Burn {
+                account: key(&x.account)?,
+                mint: key(&x.mint)?,
+                authority: key(&x.authority)?,
+                amount: amount(&x.amount)?,
+                decimals: None,
+            },
+            SolanaInstruction::BurnChecked(x) => TokenFlow::Burn {
+                account: key(&x.account)?,
+                mint: key(&x.mint)?,
+                authority: key(&x.authority)?,
+                amount: amount(&x.token_amount.amount)?,
+                decimals: Some(x.token_amount.decimals),
+            },
+        };
+        Some(flow)
+    }
+}
+
+impl TokenFlow {
+    /// Jito tips and token account creations, part of nearly every swap transaction
+    /// and moving nothing the trade events don't already show
+    pub fn is_incidental(&self) -> bool {
+        match self {
+            TokenFlow::SolTransfer { destination, .. } => JITO_TIP_ACCOUNTS.contains(destination),
+            TokenFlow::CreateTokenAccount { .. } => true,
+            _ => false,
+        }
+    }
+
+    /// Decodes a raw System, SPL Token, Token-2022 or associated token account
+    /// instruction, `None` for the instructions moving nothing
+    pub fn from_compiled(program_id: &Pubkey, accounts: &[Pubkey], data: &[u8]) -> Option<Self> {
+        let account = |index: usize| accounts.get(index).copied();
+        if *program_id == SYSTEM_PROGRAM {
+            return match bincode::deserialize::<SystemInstruction>(data).ok()? {
+                SystemInstruction::Transfer { lamports } => Some(TokenFlow::SolTransfer {
+                    source: account(0)?,
+                    destination: account(1)?,
+                    lamports,
+                }),
+                SystemInstruction::TransferWithSeed { lamports, .. } => {
+                    Some(TokenFlow::SolTransfer {
+                        source: account(0)?,
+                        destination: account(2)?,
+                        lamports,
+                    })
+                }
+                SystemInstruction::CreateAccount {
+                    lamports,
+                    space,
+                    owner,
+                } => Some(TokenFlow::CreateAccount {
+                    source: account(0)?,
+                    new_account: account(1)?,
+                    lamports,
+                    space,
+                    owner,
+                }),
+                _ => None,
+            };
+        }
+        if *program_id == spl_associated_token_account::ID {
+            // an empty instruction is the original `Create`
+            let idempotent = match data.first() {
+                None | Some(0) => false,
+                Some(1) => true,
+                _ => return None,
+            };
+            return Some(TokenFlow::CreateTokenAccount {
+                payer: account(0)?,
+                account: account(1)?,
+                wallet: account(2)?,
+                mint: account(3)?,
+                token_program: account(5)?,
+                idempotent,
+            });
+        }
+        if *program_id != spl_token::ID && *program_id != spl_token_2022::ID {
+            return None;
+        }
+        // Token-2022 shares the instruction layout of the base program, of its
+        // extension instructions only the transfer fee ones move tokens
+        if *program_id == spl_token_2022::ID
+            && matches!(
+                Token2022Instruction::unpack(data),
+                Ok(Token2022Instruction::TransferFeeExtension)
+            )
+        {
+            return match TransferFeeInstruction::unpack(&data[1..]).ok()? {
+                TransferFeeInstruction::TransferCheckedWithFee {
+                    amount,
+                    decimals,
+                    fee,
+                } => Some(TokenFlow::Transfer {
+                    source: account(0)?,
+                    destination: account(2)?,
+                    authority: account(3)?,
+                    mint: Some(account(1)?),
+                    amount,
+                    decimals: Some(decimals),
+                    fee: Some(fee),
+                }),
+                _ => None,
+            };
+        }
+        let flow = match TokenInstruction::unpack(data).ok()? {
+            TokenInstruction::Transfer { amount } => TokenFlow::Transfer {
+                source: account(0)?,
+                destination: account(1)?,
+                authority: account(2)?,
+                mint: None,
+                amount,
+                decimals: None,
+                fee: None,
+            },
+            TokenInstruction::TransferChecked { amount, decimals } => TokenFlow::Transfer {
+                source: account(0)?,
+                destination: account(2)?,
+                authority: account(3)?,
+                mint: Some(account(1)?),
+                amount,
+                decimals: Some(decimals),
+                fee: None,
+            },
+            TokenInstruction::MintTo { amount } => TokenFlow::MintTo {
+                mint: account(0)?,
+                account: account(1)?,
+                mint_authority: account(2)?,
+                amount,
+                decimals: None,
+            },
+            TokenInstruction::MintToChecked { amount, decimals } => TokenFlow::MintTo {
+                mint: account(0)?,
+                account: account(1)?,
+                mint_authority: account(2)?,
+                amount,
+                decimals: Some(decimals),
+            },
+            TokenInstruction::Burn { amount } => TokenFlow::Burn {
+                account: account(0)?,
+                mint: account(1)?,
+                authority: account(2)?,
+                amount,
+                decimals: None,
+            },
+            TokenInstruction::BurnChecked { amount, decimals } => TokenFlow::Burn {
+                account: account(0)?,
+                mint: account(1)?,
+                authority: account(2)?,
+                amount,
+                decimals: Some(decimals),
+            },
+            TokenInstruction::CloseAccount => TokenFlow::CloseAccount {
+                account: account(0)?,
+                destination: account(1)?,
+                owner: account(2)?,
+            },
+            TokenInstruction::SyncNative => TokenFlow::SyncNative {
+                account: account(0)?,
+            },
+            _ => return None,
+        };
+        Some(flow)
+    }
+
+    /// Decodes a `jsonParsed` instruction
+    pub fn from_parsed(ix: &ParsedInstruction) -> Option<Self> {
+        serde_json::from_value::<SolanaInstruction>(ix.parsed.clone())
+            .ok()?
+            .token_flow()
+    }
+
+    /// Decodes a compiled, partially decoded or parsed instruction, `account_keys`
+    /// resolve the account indexes of compiled ones, see `sol_tx::account_keys`
+    pub fn from_ui_instruction(ix: &UiInstruction, account_keys: &[Pubkey]) -> Option<Self> {
+        match ix {
+            UiInstruction::Compiled(ix) => {
+                let accounts = ix
+                    .accounts
+                    .iter()
+                    .map(|x| account_keys.get(*x as usize).copied())
+                    .collect::<Option<Vec<Pubkey>>>()?;
+                let data = bs58::decode(&ix.data).into_vec().ok()?;
+                TokenFlow::from_compiled(
+                    account_keys.get(ix.program_id_index as usize)?,
+                    &accounts,
+                    &data,
+                )
+            }
+            UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) => TokenFlow::from_parsed(ix),
+            UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => {
+                let accounts = ix
+                    .accounts
+                    .iter()
+                    .map(|x| key(x))
+                    .collect::<Option<Vec<Pubkey>>>()?;
+                let data = bs58::decode(&ix.data).into_vec().ok()?;
+                TokenFlow::from_compiled(&key(&ix.program_id)?, &accounts, &data)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::test_utils::accounts;
+
+    #[test]
+    fn decodes_transfer_checked_with_fee() {
+        let accounts = accounts(4);
+        let mut data = vec![];
+        TransferFeeInstruction::TransferCheckedWithFee {
+            amount: 1_000_000,
+            decimals: 6,
+            fee: 5_000,
+        }
+        .pack(&mut data);
+        let data = [&[26][..], &data].concat();
+        let Some(TokenFlow::Transfer {
+            source,
+            destination,
+            authority,
+            mint,
+            amount,
+            decimals,
+            fee,
+        }) = TokenFlow::from_compiled(&spl_token_2022::ID, &accounts, &data)
+        else {
+            panic!("not a transfer");
+        };
+        assert_eq!(source, accounts[0]);
+        assert_eq!(mint, Some(accounts[1]));
+        assert_eq!(destination, accounts[2]);
+        assert_eq!(authority, accounts[3]);
+        assert_eq!((amount, decimals, fee), (1_000_000, Some(6), Some(5_000)));
+        // the fee extension is Token-2022 only
+        assert!(TokenFlow::from_compiled(&spl_token::ID, &accounts, &data).is_none());
+    }
+
+    #[test]
+    fn decodes_parsed_transfer_checked_with_fee() {
+        let parsed = serde_json::json!({
+            "type": "transferCheckedWithFee",
+            "info": {
+                "source": Pubkey::new_unique().to_string(),
+                "mint": Pubkey::new_unique().to_string(),
+                "destination": Pubkey::new_unique().to_string(),
+                "authority": Pubkey::new_unique().to_string(),
+                "tokenAmount": {
+                    "uiAmount": 1.0,
+                    "decimals": 6,
+                    "amount": "1000000",
+                    "uiAmountString": "1"
+                },
+                "feeAmount": {
+                    "uiAmount": 0.005,
+                    "decimals": 6,
+                    "amount": "5000",
+                    "uiAmountString": "0.005"
+                }
+            }
+        });
+        let flow = serde_json::from_value::<SolanaInstruction>(parsed)
+            .unwrap()
+            .token_flow();
+        let Some(TokenFlow::Transfer { amount, fee, .. }) = flow else {
+            panic!("not a transfer");
+        };
+        assert_eq!((amount, fee), (1_000_000, Some(5_000)));
+    }
+}
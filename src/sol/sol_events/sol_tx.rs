@@ -0,0 +1,509 @@
+use std::collections::BTreeMap;
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use serde_with::{DisplayFromStr, serde_as};
+use solana_sdk::pubkey::Pubkey;
+use solana_transaction_status::{
+    EncodedTransaction, EncodedTransactionWithStatusMeta, UiInstruction, UiMessage,
+    UiParsedInstruction, UiTransactionStatusMeta, UiTransactionTokenBalance,
+    option_serializer::OptionSerializer, parse_instruction::ParsedInstruction,
+};
+
+/// An outer or inner instruction with its accounts resolved
+#[derive(Debug, Clone)]
+pub struct FlatInstruction {
+    /// Index of the outer instruction
+    pub index: usize,
+    /// Position among the inner instructions of `index`, `None` for the outer instruction
+    pub inner_index: Option<usize>,
+    /// 1 for outer instructions
+    pub stack_height: u32,
+    pub program_id: Pubkey,
+    pub accounts: Vec<Pubkey>,
+    pub data: Vec<u8>,
+    /// Set when a `jsonParsed` transaction only carries the parsed form, `accounts`
+    /// and `data` are empty then
+    pub parsed: Option<ParsedInstruction>,
+}
+
+fn with_loaded_addresses(
+    mut keys: Vec<Pubkey>,
+    meta: Option<&UiTransactionStatusMeta>,
+) -> Option<Vec<Pubkey>> {
+    if let Some(OptionSerializer::Some(loaded)) = meta.map(|x| &x.loaded_addresses) {
+        for address in loaded.writable.iter().chain(loaded.readonly.iter()) {
+            keys.push(Pubkey::from_str(address).ok()?);
+        }
+    }
+    Some(keys)
+}
+
+/// Static keys followed by the writable then readonly lookup table addresses
+pub fn account_keys(tx: &EncodedTransactionWithStatusMeta) -> Option<Vec<Pubkey>> {
+    match &tx.transaction {
+        EncodedTransaction::Json(ui_tx) => match &ui_tx.message {
+            // parsed messages already list the lookup table addresses
+            UiMessage::Parsed(message) => message
+                .account_keys
+                .iter()
+                .map(|x| Pubkey::from_str(&x.pubkey).ok())
+                .collect(),
+            UiMessage::Raw(message) => with_loaded_addresses(
+                message
+                    .account_keys
+                    .iter()
+                    .map(|x| Pubkey::from_str(x).ok())
+                    .collect::<Option<Vec<Pubkey>>>()?,
+                tx.meta.as_ref(),
+            ),
+        },
+        encoded => with_loaded_addresses(
+            encoded.decode()?.message.static_account_keys().to_vec(),
+            tx.meta.as_ref(),
+        ),
+    }
+}
+
+fn resolve(keys: &[Pubkey], indexes: &[u8]) -> Option<Vec<Pubkey>> {
+    indexes
+        .iter()
+        .map(|x| keys.get(*x as usize).copied())
+        .collect()
+}
+
+/// Resolves a compiled, partially decoded or parsed instruction
+fn flatten_ui_instruction(
+    ix: &UiInstruction,
+    keys: &[Pubkey],
+    index: usize,
+    inner_index: Option<usize>,
+    default_stack_height: u32,
+) -> Option<FlatInstruction> {
+    let (program_id, accounts, data, parsed, stack_height) = match ix {
+        UiInstruction::Compiled(ix) => (
+            *keys.get(ix.program_id_index as usize)?,
+            resolve(keys, &ix.accounts)?,
+            bs58::decode(&ix.data).into_vec().ok()?,
+            None,
+            ix.stack_height,
+        ),
+        UiInstruction::Parsed(UiParsedInstruction::PartiallyDecoded(ix)) => (
+            Pubkey::from_str(&ix.program_id).ok()?,
+            ix.accounts
+                .iter()
+                .map(|x| Pubkey::from_str(x).ok())
+                .collect::<Option<Vec<Pubkey>>>()?,
+            bs58::decode(&ix.data).into_vec().ok()?,
+            None,
+            ix.stack_height,
+        ),
+        UiInstruction::Parsed(UiParsedInstruction::Parsed(ix)) => (
+            Pubkey::from_str(&ix.program_id).ok()?,
+            Vec::new(),
+            Vec::new(),
+            Some(ix.clone()),
+            ix.stack_height,
+        ),
+    };
+    Some(FlatInstruction {
+        index,
+        inner_index,
+        stack_height: stack_height.unwrap_or(default_stack_height),
+        program_id,
+        accounts,
+        data,
+        parsed,
+    })
+}
+
+/// Outer and inner instructions in execution order, out of a binary, json or
+/// `jsonParsed` encoded transaction
+pub fn flatten_instructions(tx: &EncodedTransactionWithStatusMeta) -> Option<Vec<FlatInstruction>> {
+    let keys = account_keys(tx)?;
+    let outer = match &tx.transaction {
+        EncodedTransaction::Json(ui_tx) => match &ui_tx.message {
+            UiMessage::Parsed(message) => message
+                .instructions
+                .iter()
+                .enumerate()
+                .map(|(index, ix)| flatten_ui_instruction(ix, &keys, index, None, 1))
+                .collect::<Option<Vec<FlatInstruction>>>()?,
+            UiMessage::Raw(message) => message
+                .instructions
+                .iter()
+                .enumerate()
+                .map(|(index, ix)| {
+                    flatten_ui_instruction(
+                        &UiInstruction::Compiled(ix.clone()),
+                        &keys,
+                        index,
+                        None,
+                        1,
+                    )
+                })
+                .collect::<Option<Vec<FlatInstruction>>>()?,
+        },
+        encoded => encoded
+            .decode()?
+            .message
+            .instructions()
+            .iter()
+            .enumerate()
+            .map(|(index, ix)| {
+                Some(FlatInstruction {
+                    index,
+                    inner_index: None,
+                    stack_height: 1,
+                    program_id: *keys.get(ix.program_id_index as usize)?,
+                    accounts: resolve(&keys, &ix.accounts)?,
+                    data: ix.data.clone(),
+                    parsed: None,
+                })
+            })
+            .collect::<Option<Vec<FlatInstruction>>>()?,
+    };
+    let inner_instructions = match tx.meta.as_ref().map(|x| &x.inner_instructions) {
+        Some(OptionSerializer::Some(x)) => x.as_slice(),
+        _ => &[],
+    };
+
+    let mut res = Vec::new();
+    for ix in outer {
+        let index = ix.index;
+        res.push(ix);
+        let Some(inner) = inner_instructions
+            .iter()
+            .find(|x| x.index as usize == index)
+        else {
+            continue;
+        };
+        for (inner_index, ix) in inner.instructions.iter().enumerate() {
+            res.push(flatten_ui_instruction(
+                ix,
+                &keys,
+                index,
+                Some(inner_index),
+                2,
+            )?);
+        }
+    }
+    Some(res)
+}
+
+/// Net lamport change of an owner, token accounts count toward their owner so
+/// wrapped SOL is included. The fee payer's includes the fee.
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct SolDelta {
+    #[serde_as(as = "DisplayFromStr")]
+    pub owner: Pubkey,
+    pub lamports: i128,
+}
+
+/// Net change of an owner's balance of a mint, summed over its token accounts
+#[serde_as]
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TokenDelta {
+    #[serde_as(as = "DisplayFromStr")]
+    pub owner: Pubkey,
+    #[serde_as(as = "DisplayFromStr")]
+    pub mint: Pubkey,
+    #[serde_as(as = "Option<DisplayFromStr>")]
+    pub token_program: Option<Pubkey>,
+    pub decimals: u8,
+    /// Raw amount, after any Token-2022 transfer fee
+    pub amount: i128,
+}
+
+/// What each wallet actually gained or lost in a transaction, unchanged balances
+/// are left out. Wrapped SOL is reported in `sol` only, never in `tokens`.
+#[derive(Debug, Clone, Deserialize, Serialize)]
+pub struct TxBalanceDelta {
+    pub signature: String,
+    pub sol: Vec<SolDelta>,
+    pub tokens: Vec<TokenDelta>,
+}
+
+impl TxBalanceDelta {
+    /// Built from the pre and post balances of the meta. Token balances without an
+    /// owner, recorded before owners were tracked, are skipped.
+    pub fn from_tx(tx: &EncodedTransactionWithStatusMeta, signature: &str) -> Option<Self> {
+        let meta = tx.meta.as_ref()?;
+        let keys = account_keys(tx)?;
+
+        // a token account closed or created in the transaction has its owner in
+        // only one of the two lists
+        let mut token_owners: BTreeMap<usize, Pubkey> = BTreeMap::new();
+        for balances in [&meta.pre_token_balances, &meta.post_token_balances] {
+            let OptionSerializer::Some(balances) = balances else {
+                continue;
+            };
+            for balance in balances {
+                if let OptionSerializer::Some(owner) = &balance.owner
+                    && let Ok(owner) = Pubkey::from_str(owner)
+                {
+                    token_owners.insert(balance.account_index as usize, owner);
+                }
+            }
+        }
+
+        let mut sol: BTreeMap<Pubkey, i128> = BTreeMap::new();
+        for (index, (key, (pre, post))) in keys
+            .iter()
+            .zip(meta.pre_balances.iter().zip(meta.post_balances.iter()))
+            .enumerate()
+        {
+            let owner = token_owners.get(&index).unwrap_or(key);
+            *sol.entry(*owner).or_default() += *post as i128 - *pre as i128;
+        }
+
+        let mut tokens: BTreeMap<(Pubkey, Pubkey), TokenDelta> = BTreeMap::new();
+        let mut apply = |balances: &OptionSerializer<Vec<UiTransactionTokenBalance>>,
+                         sign: i128| {
+            let OptionSerializer::Some(balances) = balances else {
+                return;
+            };
+            for balance in balances {
+                let (OptionSerializer::Some(owner), Ok(mint), Ok(amount)) = (
+                    &balance.owner,
+                    Pubkey::from_str(&balance.mint),
+                    balance.ui_token_amount.amount.parse::<u64>(),
+                ) else {
+                    continue;
+                };
+                let Ok(owner) = Pubkey::from_str(owner) else {
+                    continue;
+                };
+                // already in the lamports of the account
+                if mint == spl_token::native_mint::ID || mint == spl_token_2022::native_mint::ID {
+                    continue;
+                }
+                let token_program = match &balance.program_id {
+                    OptionSerializer::Some(x) => Pubkey::from_str(x).ok(),
+                    _ => None,
+                };
+                let delta = tokens.entry((owner, mint)).or_insert(TokenDelta {
+                    owner,
+                    mint,
+                    token_program,
+                    decimals: balance.ui_token_amount.decimals,
+                    amount: 0,
+                });
+                delta.amount += sign * amount as i128;
+            }
+        };
+        apply(&meta.pre_token_balances, -1);
+        apply(&meta.post_token_balances, 1);
+
+        Some(TxBalanceDelta {
+            signature: signature.to_string(),
+            sol: sol
+                .into_iter()
+                .filter(|(_, lamports)| *lamports != 0)
+                .map(|(owner, lamports)| SolDelta { owner, lamports })
+                .collect(),
+            tokens: tokens.into_values().filter(|x| x.amount != 0).collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use base64::{Engine, prelude::BASE64_STANDARD};
+    use serde_json::json;
+    use solana_sdk::{
+        hash::Hash,
+        instruction::CompiledInstruction,
+        message::{MessageHeader, VersionedMessage, v0},
+        signature::Signature,
+        transaction::VersionedTransaction,
+    };
+
+    use super::*;
+
+    fn meta(inner_instructions: serde_json::Value, loaded: serde_json::Value) -> serde_json::Value {
+        json!({
+            "err": null,
+            "status": { "Ok": null },
+            "fee": 5000,
+            "preBalances": [],
+            "postBalances": [],
+            "innerInstructions": inner_instructions,
+            "logMessages": [],
+            "preTokenBalances": [],
+            "postTokenBalances": [],
+            "rewards": [],
+            "loadedAddresses": loaded,
+        })
+    }
+
+    #[test]
+    fn resolves_lookup_table_accounts_of_a_binary_transaction() {
+        let keys: Vec<Pubkey> = (0..3).map(|_| Pubkey::new_unique()).collect();
+        let loaded = Pubkey::new_unique();
+        let message = v0::Message {
+            header: MessageHeader {
+                num_required_signatures: 1,
+                num_readonly_signed_accounts: 0,
+                num_readonly_unsigned_accounts: 1,
+            },
+            account_keys: keys.clone(),
+            recent_blockhash: Hash::default(),
+            instructions: vec![CompiledInstruction::new_from_raw_parts(
+                2,
+                vec![7],
+                vec![0, 1, 3],
+            )],
+            address_table_lookups: vec![v0::MessageAddressTableLookup {
+                account_key: Pubkey::new_unique(),
+                writable_indexes: vec![0],
+                readonly_indexes: vec![],
+            }],
+        };
+        let tx = VersionedTransaction {
+            signatures: vec![Signature::default()],
+            message: VersionedMessage::V0(message),
+        };
+        let encoded = BASE64_STANDARD.encode(bincode::serialize(&tx).unwrap());
+        let inner = json!([{
+            "index": 0,
+            "instructions": [
+                { "programIdIndex": 2, "accounts": [3, 0], "data": bs58::encode([9]).into_string(), "stackHeight": 2 }
+            ]
+        }]);
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(json!({
+            "transaction": [encoded, "base64"],
+            "meta": meta(inner, json!({ "writable": [loaded.to_string()], "readonly": [] })),
+        }))
+        .unwrap();
+
+        assert_eq!(account_keys(&tx).unwrap(), [&keys[..], &[loaded]].concat());
+        let instructions = flatten_instructions(&tx).unwrap();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].accounts, vec![keys[0], keys[1], loaded]);
+        assert_eq!(instructions[0].data, vec![7]);
+        assert_eq!(instructions[0].stack_height, 1);
+        assert_eq!(instructions[1].inner_index, Some(0));
+        assert_eq!(instructions[1].program_id, keys[2]);
+        assert_eq!(instructions[1].accounts, vec![loaded, keys[0]]);
+        assert_eq!(instructions[1].data, vec![9]);
+    }
+
+    #[test]
+    fn keeps_parsed_and_partially_decoded_instructions() {
+        let user = Pubkey::new_unique();
+        let pool = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        let source = Pubkey::new_unique();
+        let destination = Pubkey::new_unique();
+        let transfer = json!({
+            "program": "spl-token",
+            "programId": spl_token::ID.to_string(),
+            "parsed": {
+                "type": "transfer",
+                "info": {
+                    "source": source.to_string(),
+                    "destination": destination.to_string(),
+                    "authority": user.to_string(),
+                    "amount": "1000"
+                }
+            },
+            "stackHeight": 2
+        });
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(json!({
+            "transaction": {
+                "signatures": [Signature::default().to_string()],
+                "message": {
+                    "accountKeys": [
+                        { "pubkey": user.to_string(), "writable": true, "signer": true, "source": "transaction" },
+                        { "pubkey": pool.to_string(), "writable": true, "signer": false, "source": "transaction" },
+                        { "pubkey": program.to_string(), "writable": false, "signer": false, "source": "transaction" }
+                    ],
+                    "recentBlockhash": Hash::default().to_string(),
+                    "instructions": [{
+                        "programId": program.to_string(),
+                        "accounts": [user.to_string(), pool.to_string()],
+                        "data": bs58::encode([1, 2, 3]).into_string(),
+                        "stackHeight": null
+                    }]
+                }
+            },
+            "meta": meta(json!([{ "index": 0, "instructions": [transfer] }]), json!({ "writable": [], "readonly": [] })),
+        }))
+        .unwrap();
+
+        assert_eq!(account_keys(&tx).unwrap(), vec![user, pool, program]);
+        let instructions = flatten_instructions(&tx).unwrap();
+        assert_eq!(instructions.len(), 2);
+        assert_eq!(instructions[0].program_id, program);
+        assert_eq!(instructions[0].accounts, vec![user, pool]);
+        assert_eq!(instructions[0].data, vec![1, 2, 3]);
+        assert!(instructions[0].parsed.is_none());
+        assert_eq!(instructions[1].program_id, spl_token::ID);
+        assert_eq!(instructions[1].inner_index, Some(0));
+        assert_eq!(instructions[1].stack_height, 2);
+        assert!(instructions[1].data.is_empty());
+        assert_eq!(
+            instructions[1].parsed.as_ref().unwrap().parsed["type"],
+            "transfer"
+        );
+    }
+
+    #[test]
+    fn folds_token_account_lamports_into_their_owner() {
+        let user = Pubkey::new_unique();
+        let wsol_account = Pubkey::new_unique();
+        let vault = Pubkey::new_unique();
+        let authority = Pubkey::new_unique();
+        let mint = Pubkey::new_unique();
+        let program = Pubkey::new_unique();
+        let balance = |index: u8, mint: &Pubkey, owner: &Pubkey, amount: u64| {
+            json!({
+                "accountIndex": index,
+                "mint": mint.to_string(),
+                "uiTokenAmount": {
+                    "uiAmount": null,
+                    "decimals": 6,
+                    "amount": amount.to_string(),
+                    "uiAmountString": ""
+                },
+                "owner": owner.to_string(),
+                "programId": spl_token::ID.to_string()
+            })
+        };
+        // the user unwraps 1 SOL by closing its wrapped SOL account and pays a 5000
+        // lamport fee, a pool vault receives 5 tokens
+        let mut meta = meta(json!([]), json!({ "writable": [], "readonly": [] }));
+        meta["preBalances"] = json!([10_000_000_000u64, 1_002_039_280, 2_039_280, 1]);
+        meta["postBalances"] = json!([11_002_034_280u64, 0, 2_039_280, 1]);
+        meta["preTokenBalances"] = json!([
+            balance(1, &spl_token::native_mint::ID, &user, 1_000_000_000),
+            balance(2, &mint, &authority, 0),
+        ]);
+        meta["postTokenBalances"] = json!([balance(2, &mint, &authority, 5_000_000)]);
+        let key = |pubkey: &Pubkey, signer: bool| json!({ "pubkey": pubkey.to_string(), "writable": true, "signer": signer, "source": "transaction" });
+        let tx: EncodedTransactionWithStatusMeta = serde_json::from_value(json!({
+            "transaction": {
+                "signatures": [Signature::default().to_string()],
+                "message": {
+                    "accountKeys": [key(&user, true), key(&wsol_account, false), key(&vault, false), key(&program, false)],
+                    "recentBlockhash": Hash::default().to_string(),
+                    "instructions": []
+                }
+            },
+            "meta": meta,
+        }))
+        .unwrap();
+
+        let delta = TxBalanceDelta::from_tx(&tx, "sig").unwrap();
+        assert_eq!(delta.sol.len(), 1);
+        assert_eq!(delta.sol[0].owner, user);
+        // unwrapping moves nothing out of the user's hands
+        assert_eq!(delta.sol[0].lamports, -5_000);
+        assert_eq!(delta.tokens.len(), 1);
+        assert_eq!(delta.tokens[0].owner, authority);
+        assert_eq!(delta.tokens[0].mint, mint);
+        assert_eq!(delta.tokens[0].amount, 5_000_000);
+    }
+}
@@ -0,0 +1,400 @@
+use std::cmp::Ordering;
+
+pub mod clmm;
+
+/// Minimal 256 bits unsigned integer, enough for the Q64.64 intermediates of the
+/// on-chain AMM math. Limbs are little endian.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct U256(pub [u64; 4]);
+
+impl U256 {
+    pub const ZERO: Self = Self([0; 4]);
+    pub const ONE: Self = Self([1, 0, 0, 0]);
+    pub const MAX: Self = Self([u64::MAX; 4]);
+
+    pub fn from_u128(x: u128) -> Self {
+        Self([x as u64, (x >> 64) as u64, 0, 0])
+    }
+    pub fn to_u128(self) -> Option<u128> {
+        if self.0[2] != 0 || self.0[3] != 0 {
+            return None;
+        }
+        Some(self.0[0] as u128 | (self.0[1] as u128) << 64)
+    }
+    pub fn is_zero(&self) -> bool {
+        self.0.iter().all(|x| *x == 0)
+    }
+    /// Number of significant bits
+    pub fn bits(&self) -> u32 {
+        for i in (0..4).rev() {
+            if self.0[i] != 0 {
+                return 64 * i as u32 + 64 - self.0[i].leading_zeros();
+            }
+        }
+        0
+    }
+    fn bit(&self, i: u32) -> bool {
+        (self.0[(i / 64) as usize] >> (i % 64)) & 1 == 1
+    }
+    pub fn checked_add(self, other: Self) -> Option<Self> {
+        let mut out = [0u64; 4];
+        let mut carry = false;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let (sum, c1) = self.0[i].overflowing_add(other.0[i]);
+            let (sum, c2) = sum.overflowing_add(carry as u64);
+            *limb = sum;
+            carry = c1 || c2;
+        }
+        (!carry).then_some(Self(out))
+    }
+    fn wrapping_sub(self, other: Self) -> Self {
+        let mut out = [0u64; 4];
+        let mut borrow = false;
+        for (i, limb) in out.iter_mut().enumerate() {
+            let (diff, b1) = self.0[i].overflowing_sub(other.0[i]);
+            let (diff, b2) = diff.overflowing_sub(borrow as u64);
+            *limb = diff;
+            borrow = b1 || b2;
+        }
+        Self(out)
+    }
+    pub fn checked_sub(self, other: Self) -> Option<Self> {
+        (self >= other).then(|| self.wrapping_sub(other))
+    }
+    /// Full 512 bits product, little endian limbs
+    fn full_mul(self, other: Self) -> [u64; 8] {
+        let mut out = [0u64; 8];
+        for i in 0..4 {
+            let mut carry: u128 = 0;
+            for j in 0..4 {
+                let cur = out[i + j] as u128 + (self.0[i] as u128) * (other.0[j] as u128) + carry;
+                out[i + j] = cur as u64;
+                carry = cur >> 64;
+            }
+            out[i + 4] = carry as u64;
+        }
+        out
+    }
+    pub fn checked_mul(self, other: Self) -> Option<Self> {
+        let out = self.full_mul(other);
+        if out[4..].iter().any(|x| *x != 0) {
+            return None;
+        }
+        Some(Self([out[0], out[1], out[2], out[3]]))
+    }
+    /// Shift left, bits shifted past 256 are lost
+    #[allow(clippy::should_implement_trait)]
+    pub fn shl(self, n: u32) -> Self {
+        if n >= 256 {
+            return Self::ZERO;
+        }
+        let (limbs, bits) = ((n / 64) as usize, n % 64);
+        let mut out = [0u64; 4];
+        for i in (limbs..4).rev() {
+            out[i] = self.0[i - limbs] << bits;
+            if bits > 0 && i > limbs {
+                out[i] |= self.0[i - limbs - 1] >> (64 - bits);
+            }
+        }
+        Self(out)
+    }
+    #[allow(clippy::should_implement_trait)]
+    pub fn shr(self, n: u32) -> Self {
+        if n >= 256 {
+            return Self::ZERO;
+        }
+        let (limbs, bits) = ((n / 64) as usize, n % 64);
+        let mut out = [0u64; 4];
+        for (i, limb) in out.iter_mut().enumerate().take(4 - limbs) {
+            *limb = self.0[i + limbs] >> bits;
+            if bits > 0 && i + limbs + 1 < 4 {
+                *limb |= self.0[i + limbs + 1] << (64 - bits);
+            }
+        }
+        Self(out)
+    }
+    /// Long division, returns `None` on a zero divisor
+    pub fn div_rem(self, divisor: Self) -> Option<(Self, Self)> {
+        if divisor.is_zero() {
+            return None;
+        }
+        let mut quotient = Self::ZERO;
+        let mut remainder = Self::ZERO;
+        for i in (0..self.bits()).rev() {
+            // the remainder is below the divisor, if its top bit is set the shifted
+            // value no longer fits but is still below twice the divisor
+            let overflow = remainder.bit(255);
+            remainder = remainder.shl(1);
+            if self.bit(i) {
+                remainder.0[0] |= 1;
+            }
+            if overflow || remainder >= divisor {
+                remainder = remainder.wrapping_sub(divisor);
+                quotient.0[(i / 64) as usize] |= 1 << (i % 64);
+            }
+        }
+        Some((quotient, remainder))
+    }
+    /// Division rounding towards zero or away from it
+    pub fn div_rounding(self, divisor: Self, round_up: bool) -> Option<Self> {
+        let (quotient, remainder) = self.div_rem(divisor)?;
+        if round_up && !remainder.is_zero() {
+            return quotient.checked_add(Self::ONE);
+        }
+        Some(quotient)
+    }
+}
+
+impl PartialOrd for U256 {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for U256 {
+    fn cmp(&self, other: &Self) -> Ordering {
+        for i in (0..4).rev() {
+            match self.0[i].cmp(&other.0[i]) {
+                Ordering::Equal => continue,
+                x => return x,
+            }
+        }
+        Ordering::Equal
+    }
+}
+
+/// `a * b / denominator` with a 512 bits intermediate, `None` on a zero denominator
+/// or when the result does not fit
+pub fn mul_div_u256(a: U256, b: U256, denominator: U256, round_up: bool) -> Option<U256> {
+    if denominator.is_zero() {
+        return None;
+    }
+    let product = a.full_mul(b);
+    let mut quotient = U256::ZERO;
+    let mut remainder = U256::ZERO;
+    for i in (0..512).rev() {
+        let overflow = remainder.bit(255);
+        remainder = remainder.shl(1);
+        if (product[i / 64] >> (i % 64)) & 1 == 1 {
+            remainder.0[0] |= 1;
+        }
+        if overflow || remainder >= denominator {
+            remainder = remainder.wrapping_sub(denominator);
+            if i >= 256 {
+                return None;
+            }
+            quotient.0[i / 64] |= 1 << (i % 64);
+        }
+    }
+    if round_up && !remainder.is_zero() {
+        return quotient.checked_add(U256::ONE);
+    }
+    Some(quotient)
+}
+
+/// `a * b / denominator` with a 256 bits intermediate
+pub fn mul_div(a: u128, b: u128, denominator: u128, round_up: bool) -> Option<u128> {
+    U256::from_u128(a)
+        .checked_mul(U256::from_u128(b))?
+        .div_rounding(U256::from_u128(denominator), round_up)?
+        .to_u128()
+}
+
+/// `(a * b) >> offset`, used to multiply by a fixed point number
+pub fn mul_shr(a: u128, b: u128, offset: u32, round_up: bool) -> Option<u128> {
+    let product = U256::from_u128(a).checked_mul(U256::from_u128(b))?;
+    let shifted = product.shr(offset);
+    if round_up && shifted.shl(offset) != product {
+        return shifted.checked_add(U256::ONE)?.to_u128();
+    }
+    shifted.to_u128()
+}
+
+/// `(a << offset) / b`, used to divide by a fixed point number
+pub fn shl_div(a: u128, b: u128, offset: u32, round_up: bool) -> Option<u128> {
+    let shifted = U256::from_u128(a);
+    if shifted.bits() + offset > 256 {
+        return None;
+    }
+    shifted
+        .shl(offset)
+        .div_rounding(U256::from_u128(b), round_up)?
+        .to_u128()
+}
+
+/// 1 in Q64.64
+pub const ONE_Q64: u128 = 1 << 64;
+/// Exponents are limited to 19 bits
+pub const MAX_EXPONENTIAL: u32 = 0x80000;
+
+/// `base ^ exp` for a Q64.64 `base`, squaring per bit of `exp` and rounding down
+/// at every step like the on-chain programs do
+pub fn pow_q64(base: u128, exp: i32) -> Option<u128> {
+    if exp == 0 {
+        return Some(ONE_Q64);
+    }
+    let mut invert = exp.is_negative();
+    let exp = exp.unsigned_abs();
+    if exp >= MAX_EXPONENTIAL {
+        return None;
+    }
+    let mut squared_base = base;
+    let mut result = ONE_Q64;
+    // keep the base below one so the squares fit in 128 bits
+    if squared_base >= result {
+        squared_base = u128::MAX.checked_div(squared_base)?;
+        invert = !invert;
+    }
+    for bit in 0..MAX_EXPONENTIAL.trailing_zeros() {
+        if exp & (1 << bit) > 0 {
+            result = result.checked_mul(squared_base)? >> 64;
+        }
+        squared_base = squared_base.checked_mul(squared_base)? >> 64;
+    }
+    if result == 0 {
+        return None;
+    }
+    if invert {
+        result = u128::MAX.checked_div(result)?;
+    }
+    Some(result)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn add_carries_across_limbs() {
+        let a = U256([u64::MAX, u64::MAX, u64::MAX, 0]);
+        assert_eq!(a.checked_add(U256::ONE), Some(U256([0, 0, 0, 1])));
+        assert_eq!(U256::MAX.checked_add(U256::ONE), None);
+    }
+
+    #[test]
+    fn sub_borrows_across_limbs() {
+        let a = U256([0, 0, 0, 1]);
+        assert_eq!(
+            a.checked_sub(U256::ONE),
+            Some(U256([u64::MAX, u64::MAX, u64::MAX, 0]))
+        );
+        assert_eq!(U256::ONE.checked_sub(a), None);
+    }
+
+    #[test]
+    fn mul_carries_across_limbs() {
+        // (2^128 - 1)^2 = 2^256 - 2^129 + 1
+        let a = U256::from_u128(u128::MAX);
+        assert_eq!(a.checked_mul(a), Some(U256([1, 0, u64::MAX - 1, u64::MAX])));
+        assert_eq!(U256::ONE.shl(128).checked_mul(U256::ONE.shl(128)), None);
+    }
+
+    #[test]
+    fn shifts() {
+        let x = U256([0x0123_4567_89ab_cdef, 0xfedc_ba98_7654_3210, 1, 2]);
+        assert_eq!(x.shl(0), x);
+        assert_eq!(x.shr(0), x);
+        assert_eq!(x.shl(64), U256([0, x.0[0], x.0[1], x.0[2]]));
+        assert_eq!(x.shr(64), U256([x.0[1], x.0[2], x.0[3], 0]));
+        assert_eq!(x.shl(128), U256([0, 0, x.0[0], x.0[1]]));
+        assert_eq!(x.shr(128), U256([x.0[2], x.0[3], 0, 0]));
+        assert_eq!(U256::ONE.shl(255), U256([0, 0, 0, 1 << 63]));
+        assert_eq!(U256::MAX.shr(255), U256::ONE);
+        assert_eq!(x.shl(256), U256::ZERO);
+        assert_eq!(x.shr(256), U256::ZERO);
+        // partial limb shifts move the bits across the limb boundary
+        assert_eq!(U256([1 << 63, 0, 0, 0]).shl(1), U256([0, 1, 0, 0]));
+        assert_eq!(U256([0, 1, 0, 0]).shr(1), U256([1 << 63, 0, 0, 0]));
+    }
+
+    #[test]
+    fn div_by_a_divisor_wider_than_64_bits() {
+        let divisor = U256::from_u128((1 << 100) + 12345);
+        let quotient = U256([0xdead_beef, 0x1234, 0x5678, 0]);
+        let remainder = U256::from_u128(999);
+        let dividend = quotient
+            .checked_mul(divisor)
+            .and_then(|x| x.checked_add(remainder))
+            .unwrap();
+        assert_eq!(dividend.div_rem(divisor), Some((quotient, remainder)));
+        assert_eq!(
+            dividend.div_rounding(divisor, true),
+            quotient.checked_add(U256::ONE)
+        );
+        assert_eq!(dividend.div_rounding(divisor, false), Some(quotient));
+    }
+
+    #[test]
+    fn div_with_the_top_bit_set() {
+        let divisor = U256([1, 0, 0, 1 << 63]);
+        assert_eq!(
+            U256::MAX.div_rem(divisor),
+            Some((U256::ONE, U256::MAX.wrapping_sub(divisor)))
+        );
+        assert_eq!(U256::MAX.div_rem(U256::ZERO), None);
+    }
+
+    #[test]
+    fn mul_div_u256_uses_the_512_bits_product() {
+        // MAX * MAX / MAX fits even though the product does not
+        assert_eq!(
+            mul_div_u256(U256::MAX, U256::MAX, U256::MAX, false),
+            Some(U256::MAX)
+        );
+        assert_eq!(
+            mul_div_u256(U256::MAX, U256::MAX, U256::MAX.shr(1), false),
+            None
+        );
+        assert_eq!(mul_div_u256(U256::ONE, U256::ONE, U256::ZERO, false), None);
+        assert_eq!(
+            mul_div_u256(U256::from_u128(10), U256::ONE, U256::from_u128(3), true),
+            Some(U256::from_u128(4))
+        );
+    }
+
+    #[test]
+    fn u128_helpers() {
+        assert_eq!(
+            mul_div(u128::MAX, u128::MAX, u128::MAX, false),
+            Some(u128::MAX)
+        );
+        assert_eq!(mul_div(u128::MAX, 2, 1, false), None);
+        assert_eq!(mul_div(7, 3, 2, false), Some(10));
+        assert_eq!(mul_div(7, 3, 2, true), Some(11));
+        assert_eq!(mul_div(1, 1, 0, false), None);
+
+        assert_eq!(mul_shr(3 << 64, 5 << 64, 128, false), Some(15));
+        assert_eq!(mul_shr(3, 1 << 63, 64, false), Some(1));
+        assert_eq!(mul_shr(3, 1 << 63, 64, true), Some(2));
+        assert_eq!(mul_shr(u128::MAX, u128::MAX, 64, false), None);
+
+        assert_eq!(shl_div(3, 2, 64, false), Some(3 << 63));
+        assert_eq!(shl_div(1, 3, 0, true), Some(1));
+        assert_eq!(shl_div(u128::MAX, 1, 129, false), None);
+        assert_eq!(shl_div(u128::MAX, 1, 64, false), None);
+        assert_eq!(shl_div(1, 0, 64, false), None);
+    }
+
+    #[test]
+    fn pow_q64_rounds_down_per_squaring() {
+        // 0.99^10, the exact power of this base is 16682904682537811413
+        let base = ONE_Q64 - (100 << 64) / 10_000;
+        assert_eq!(pow_q64(base, 10), Some(16_682_904_682_537_811_411));
+        assert_eq!(pow_q64(base, 0), Some(ONE_Q64));
+        // bases above one are inverted, 1.5^3 and 1.5^-3
+        let base = ONE_Q64 + (ONE_Q64 >> 1);
+        assert_eq!(pow_q64(base, 3), Some(62_257_761_248_769_736_720));
+        assert_eq!(pow_q64(base, -3), Some(5_465_701_947_765_793_070));
+        assert_eq!(pow_q64(ONE_Q64 >> 1, 64), Some(1));
+        assert_eq!(pow_q64(ONE_Q64 >> 1, 65), None);
+        assert_eq!(pow_q64(base, MAX_EXPONENTIAL as i32), None);
+    }
+
+    #[test]
+    fn conversions() {
+        assert_eq!(U256::from_u128(u128::MAX).to_u128(), Some(u128::MAX));
+        assert_eq!(U256::ONE.shl(128).to_u128(), None);
+        assert_eq!(U256::ZERO.bits(), 0);
+        assert_eq!(U256::ONE.shl(200).bits(), 201);
+    }
+}
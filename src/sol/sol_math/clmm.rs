@@ -0,0 +1,1054 @@
+//! Concentrated liquidity swap math shared by Raydium CLMM and Orca Whirlpools.
+//! Prices are Q64.64 square roots of the price of token 0 (token a) in token 1.
+
+use super::{U256, mul_div_u256, mul_shr, shl_div};
+
+/// Fee rates of both programs are expressed over 1e6
+pub const FEE_RATE_DENOMINATOR: u128 = 1_000_000;
+pub const MIN_TICK: i32 = -443636;
+pub const MAX_TICK: i32 = 443636;
+/// Square root price at `MIN_TICK`, the same for both programs
+pub const MIN_SQRT_PRICE_X64: u128 = 4295048016;
+pub const RAYDIUM_MAX_SQRT_PRICE_X64: u128 = 79226673521066979257578248091;
+pub const WHIRLPOOL_MAX_SQRT_PRICE_X64: u128 = 79226673515401279992447579055;
+
+/// `2^64 / sqrt(1.0001) ^ (2 ^ i)`, as computed by Raydium
+const RAYDIUM_TICK_RATIOS: [u128; 19] = [
+    0xfffcb933bd6fb800,
+    0xfff97272373d4000,
+    0xfff2e50f5f657000,
+    0xffe5caca7e10f000,
+    0xffcb9843d60f7000,
+    0xff973b41fa98e800,
+    0xff2ea16466c9b000,
+    0xfe5dee046a9a3800,
+    0xfcbe86c7900bb000,
+    0xf987a7253ac65800,
+    0xf3392b0822bb6000,
+    0xe7159475a2caf000,
+    0xd097f3bdfd2f2000,
+    0xa9f746462d9f8000,
+    0x70d869a156f31c00,
+    0x31be135f97ed3200,
+    0x9aa508b5b85a500,
+    0x5d6af8dedc582c,
+    0x2216e584f5fa,
+];
+
+/// `2^64 / sqrt(1.0001) ^ (2 ^ i)` rounded down, Whirlpool's negative ticks
+const WHIRLPOOL_NEGATIVE_TICK_RATIOS: [u128; 19] = [
+    18445821805675392311,
+    18444899583751176498,
+    18443055278223354162,
+    18439367220385604838,
+    18431993317065449817,
+    18417254355718160513,
+    18387811781193591352,
+    18329067761203520168,
+    18212142134806087854,
+    17980523815641551639,
+    17526086738831147013,
+    16651378430235024244,
+    15030750278693429944,
+    12247334978882834399,
+    8131365268884726200,
+    3584323654723342297,
+    696457651847595233,
+    26294789957452057,
+    37481735321082,
+];
+
+/// `2^96 * sqrt(1.0001) ^ (2 ^ i)` rounded down, Whirlpool's positive ticks
+const WHIRLPOOL_POSITIVE_TICK_RATIOS: [u128; 19] = [
+    79232123823359799118286999567,
+    79236085330515764027303304731,
+    79244008939048815603706035061,
+    79259858533276714757314932305,
+    79291567232598584799939703904,
+    79355022692464371645785046466,
+    79482085999252804386437311141,
+    79736823300114093921829183326,
+    80248749790819932309965073892,
+    81282483887344747381513967011,
+    83390072131320151908154831281,
+    87770609709833776024991924138,
+    97234110755111693312479820773,
+    119332217159966728226237229890,
+    179736315981702064433883588727,
+    407748233172238350107850275304,
+    2098478828474011932436660412517,
+    55581415166113811149459800483533,
+    38992368544603139932233054999993551,
+];
+
+/// `2^64 / log2(sqrt(1.0001))` as Q32.32
+const LOG_B_2_X32: i128 = 59543866431248;
+/// 0.01 as Q64.64
+const LOG_B_P_ERR_MARGIN_LOWER_X64: i128 = 184467440737095516;
+/// `2^-14 / log2(sqrt(1.0001)) + 0.01` as Q64.64
+const LOG_B_P_ERR_MARGIN_UPPER_X64: i128 = 15793534762490258745;
+
+/// Tick math of the program owning the pool. Both programs round the price of a
+/// tick their own way, so quotes must use the matching one to be exact.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TickMath {
+    RaydiumClmm,
+    Whirlpool,
+}
+
+impl TickMath {
+    pub fn min_sqrt_price_x64(self) -> u128 {
+        MIN_SQRT_PRICE_X64
+    }
+    pub fn max_sqrt_price_x64(self) -> u128 {
+        match self {
+            TickMath::RaydiumClmm => RAYDIUM_MAX_SQRT_PRICE_X64,
+            TickMath::Whirlpool => WHIRLPOOL_MAX_SQRT_PRICE_X64,
+        }
+    }
+
+    /// Q64.64 square root price at `tick`
+    pub fn sqrt_price_at_tick(self, tick: i32) -> Option<u128> {
+        if !(MIN_TICK..=MAX_TICK).contains(&tick) {
+            return None;
+        }
+        match self {
+            TickMath::RaydiumClmm => Some(raydium_sqrt_price_at_tick(tick)),
+            TickMath::Whirlpool if tick >= 0 => whirlpool_sqrt_price_at_positive_tick(tick),
+            TickMath::Whirlpool => Some(whirlpool_sqrt_price_at_negative_tick(tick)),
+        }
+    }
+
+    /// Greatest tick whose square root price is at or below `sqrt_price`, through
+    /// the programs' log2 approximation
+    pub fn tick_at_sqrt_price(self, sqrt_price: u128) -> Option<i32> {
+        let (in_range, bit_precision) = match self {
+            TickMath::RaydiumClmm => (
+                (MIN_SQRT_PRICE_X64..RAYDIUM_MAX_SQRT_PRICE_X64).contains(&sqrt_price),
+                16,
+            ),
+            TickMath::Whirlpool => (
+                (MIN_SQRT_PRICE_X64..=WHIRLPOOL_MAX_SQRT_PRICE_X64).contains(&sqrt_price),
+                14,
+            ),
+        };
+        if !in_range {
+            return None;
+        }
+        // integer part of the log2 of the Q64.64 price
+        let msb = 127 - sqrt_price.leading_zeros();
+        let log2p_integer_x32 = (msb as i128 - 64) << 32;
+        // fractional part, one bit per squaring of the mantissa
+        let mut bit: i128 = 0x8000_0000_0000_0000;
+        let mut log2p_fraction_x64: i128 = 0;
+        let mut r = if msb >= 64 {
+            sqrt_price >> (msb - 63)
+        } else {
+            sqrt_price << (63 - msb)
+        };
+        for _ in 0..bit_precision {
+            r *= r;
+            let is_r_more_than_two = (r >> 127) as u32;
+            r >>= 63 + is_r_more_than_two;
+            log2p_fraction_x64 += bit * is_r_more_than_two as i128;
+            bit >>= 1;
+        }
+        let log2p_x32 = log2p_integer_x32 + (log2p_fraction_x64 >> 32);
+        let logbp_x64 = log2p_x32 * LOG_B_2_X32;
+        let tick_low = ((logbp_x64 - LOG_B_P_ERR_MARGIN_LOWER_X64) >> 64) as i32;
+        let tick_high = ((logbp_x64 + LOG_B_P_ERR_MARGIN_UPPER_X64) >> 64) as i32;
+        if tick_low == tick_high || self.sqrt_price_at_tick(tick_high)? > sqrt_price {
+            Some(tick_low)
+        } else {
+            Some(tick_high)
+        }
+    }
+}
+
+/// Raydium multiplies Q64.64 factors and inverts the ratio of positive ticks
+fn raydium_sqrt_price_at_tick(tick: i32) -> u128 {
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        RAYDIUM_TICK_RATIOS[0]
+    } else {
+        1 << 64
+    };
+    for (i, tick_ratio) in RAYDIUM_TICK_RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * tick_ratio) >> 64;
+        }
+    }
+    if tick > 0 {
+        ratio = u128::MAX / ratio;
+    }
+    ratio
+}
+
+/// Whirlpool multiplies Q32.96 factors for positive ticks
+fn whirlpool_sqrt_price_at_positive_tick(tick: i32) -> Option<u128> {
+    let mut ratio = if tick & 1 != 0 {
+        WHIRLPOOL_POSITIVE_TICK_RATIOS[0]
+    } else {
+        1 << 96
+    };
+    for (i, tick_ratio) in WHIRLPOOL_POSITIVE_TICK_RATIOS.iter().enumerate().skip(1) {
+        if tick & (1 << i) != 0 {
+            ratio = U256::from_u128(ratio)
+                .checked_mul(U256::from_u128(*tick_ratio))?
+                .shr(96)
+                .to_u128()?;
+        }
+    }
+    Some(ratio >> 32)
+}
+
+/// and Q64.64 factors for negative ones
+fn whirlpool_sqrt_price_at_negative_tick(tick: i32) -> u128 {
+    let abs_tick = tick.unsigned_abs();
+    let mut ratio = if abs_tick & 1 != 0 {
+        WHIRLPOOL_NEGATIVE_TICK_RATIOS[0]
+    } else {
+        1 << 64
+    };
+    for (i, tick_ratio) in WHIRLPOOL_NEGATIVE_TICK_RATIOS.iter().enumerate().skip(1) {
+        if abs_tick & (1 << i) != 0 {
+            ratio = (ratio * tick_ratio) >> 64;
+        }
+    }
+    ratio
+}
+
+/// Token 0 amount between two prices, `L * (upper - lower) / (upper * lower)`
+pub fn get_delta_amount_0(
+    sqrt_price_a: u128,
+    sqrt_price_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let (lower, upper) = if sqrt_price_a < sqrt_price_b {
+        (sqrt_price_a, sqrt_price_b)
+    } else {
+        (sqrt_price_b, sqrt_price_a)
+    };
+    if lower == 0 {
+        return None;
+    }
+    mul_div_u256(
+        U256::from_u128(liquidity).shl(64),
+        U256::from_u128(upper - lower),
+        U256::from_u128(upper),
+        round_up,
+    )?
+    .div_rounding(U256::from_u128(lower), round_up)?
+    .to_u128()
+}
+
+/// Token 1 amount between two prices, `L * (upper - lower)`
+pub fn get_delta_amount_1(
+    sqrt_price_a: u128,
+    sqrt_price_b: u128,
+    liquidity: u128,
+    round_up: bool,
+) -> Option<u128> {
+    let diff = sqrt_price_a.abs_diff(sqrt_price_b);
+    mul_shr(liquidity, diff, 64, round_up)
+}
+
+/// Price after adding (or removing) `amount` of token 0, rounded up
+fn next_sqrt_price_from_amount_0(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u128,
+    add: bool,
+) -> Option<u128> {
+    if amount == 0 {
+        return Some(sqrt_price);
+    }
+    let numerator = U256::from_u128(liquidity).shl(64);
+    let product = U256::from_u128(amount).checked_mul(U256::from_u128(sqrt_price))?;
+    let denominator = if add {
+        numerator.checked_add(product)?
+    } else {
+        numerator.checked_sub(product)?
+    };
+    if denominator.is_zero() {
+        return None;
+    }
+    mul_div_u256(numerator, U256::from_u128(sqrt_price), denominator, true)?.to_u128()
+}
+
+/// Price after adding (or removing) `amount` of token 1, rounded down
+fn next_sqrt_price_from_amount_1(
+    sqrt_price: u128,
+    liquidity: u128,
+    amount: u128,
+    add: bool,
+) -> Option<u128> {
+    if add {
+        sqrt_price.checked_add(shl_div(amount, liquidity, 64, false)?)
+    } else {
+        sqrt_price.checked_sub(shl_div(amount, liquidity, 64, true)?)
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SwapStep {
+    pub sqrt_price_next: u128,
+    pub amount_in: u128,
+    pub amount_out: u128,
+    pub fee_amount: u128,
+}
+
+/// Swaps within a single liquidity range, towards `sqrt_price_target`.
+///
+/// # Arguments
+/// * `amount_remaining` - Input left for base input swaps, output left otherwise
+/// * `fee_rate` - Trade fee over 1e6
+pub fn compute_swap_step(
+    sqrt_price_current: u128,
+    sqrt_price_target: u128,
+    liquidity: u128,
+    amount_remaining: u128,
+    fee_rate: u32,
+    is_base_input: bool,
+    zero_for_one: bool,
+) -> Option<SwapStep> {
+    let fee_rate = fee_rate as u128;
+    let amount_in_to_target = |round_up| {
+        if zero_for_one {
+            get_delta_amount_0(sqrt_price_target, sqrt_price_current, liquidity, round_up)
+        } else {
+            get_delta_amount_1(sqrt_price_current, sqrt_price_target, liquidity, round_up)
+        }
+    };
+    let amount_out_to_target = || {
+        if zero_for_one {
+            get_delta_amount_1(sqrt_price_target, sqrt_price_current, liquidity, false)
+        } else {
+            get_delta_amount_0(sqrt_price_current, sqrt_price_target, liquidity, false)
+        }
+    };
+
+    let mut step = SwapStep::default();
+    if is_base_input {
+        let amount_remaining_less_fee =
+            amount_remaining * (FEE_RATE_DENOMINATOR - fee_rate) / FEE_RATE_DENOMINATOR;
+        step.amount_in = amount_in_to_target(true)?;
+        step.sqrt_price_next = if amount_remaining_less_fee >= step.amount_in {
+            sqrt_price_target
+        } else if zero_for_one {
+            next_sqrt_price_from_amount_0(
+                sqrt_price_current,
+                liquidity,
+                amount_remaining_less_fee,
+                true,
+            )?
+        } else {
+            next_sqrt_price_from_amount_1(
+                sqrt_price_current,
+                liquidity,
+                amount_remaining_less_fee,
+                true,
+            )?
+        };
+    } else {
+        step.amount_out = amount_out_to_target()?;
+        step.sqrt_price_next = if amount_remaining >= step.amount_out {
+            sqrt_price_target
+        } else if zero_for_one {
+            next_sqrt_price_from_amount_1(sqrt_price_current, liquidity, amount_remaining, false)?
+        } else {
+            next_sqrt_price_from_amount_0(sqrt_price_current, liquidity, amount_remaining, false)?
+        };
+    }
+
+    let max = step.sqrt_price_next == sqrt_price_target;
+    if zero_for_one {
+        if !(max && is_base_input) {
+            step.amount_in =
+                get_delta_amount_0(step.sqrt_price_next, sqrt_price_current, liquidity, true)?;
+        }
+        if !max || is_base_input {
+            step.amount_out =
+                get_delta_amount_1(step.sqrt_price_next, sqrt_price_current, liquidity, false)?;
+        }
+    } else {
+        if !(max && is_base_input) {
+            step.amount_in =
+                get_delta_amount_1(sqrt_price_current, step.sqrt_price_next, liquidity, true)?;
+        }
+        if !max || is_base_input {
+            step.amount_out =
+                get_delta_amount_0(sqrt_price_current, step.sqrt_price_next, liquidity, false)?;
+        }
+    }
+    if !is_base_input && step.amount_out > amount_remaining {
+        step.amount_out = amount_remaining;
+    }
+
+    step.fee_amount = if is_base_input && !max {
+        // the remainder is kept as fee
+        amount_remaining.checked_sub(step.amount_in)?
+    } else {
+        (step.amount_in * fee_rate).div_ceil(FEE_RATE_DENOMINATOR - fee_rate)
+    };
+    Some(step)
+}
+
+/// Pool state the swap starts from
+#[derive(Clone, Copy, Debug)]
+pub struct ClmmPool {
+    pub sqrt_price_x64: u128,
+    pub tick_current: i32,
+    pub liquidity: u128,
+    /// Trade fee over 1e6
+    pub fee_rate: u32,
+    pub tick_math: TickMath,
+    /// First tick covered by the supplied tick arrays
+    pub tick_lower_bound: i32,
+    /// First tick past the supplied tick arrays
+    pub tick_upper_bound: i32,
+}
+
+/// An initialized tick of the supplied tick arrays
+#[derive(Clone, Copy, Debug)]
+pub struct ClmmTick {
+    pub tick: i32,
+    /// Liquidity added when crossing the tick left to right
+    pub liquidity_net: i128,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+pub struct ClmmSwapResult {
+    /// Input amount, fees included
+    pub amount_in: u64,
+    pub amount_out: u64,
+    pub fee_amount: u64,
+    pub sqrt_price_x64: u128,
+    pub tick: i32,
+    pub liquidity: u128,
+    /// Relative difference between the execution price and the spot price, fees included
+    pub price_impact: f64,
+}
+
+/// Simulates a swap across the initialized ticks.
+/// Fails when the swap would leave the range covered by the supplied tick arrays.
+///
+/// # Arguments
+/// * `ticks` - Initialized ticks, sorted by tick index
+/// * `amount` - Exact input when `is_base_input`, exact output otherwise
+/// * `sqrt_price_limit_x64` - Defaults to the price bounds of the program
+pub fn swap(
+    pool: &ClmmPool,
+    ticks: &[ClmmTick],
+    amount: u64,
+    is_base_input: bool,
+    zero_for_one: bool,
+    sqrt_price_limit_x64: Option<u128>,
+) -> Result<ClmmSwapResult, ()> {
+    let tick_math = pool.tick_math;
+    let (min_sqrt_price, max_sqrt_price) = (
+        tick_math.min_sqrt_price_x64(),
+        tick_math.max_sqrt_price_x64(),
+    );
+    // Raydium excludes the bounds from the limit, Whirlpool includes them
+    let (min_limit, max_limit) = match tick_math {
+        TickMath::RaydiumClmm => (min_sqrt_price + 1, max_sqrt_price - 1),
+        TickMath::Whirlpool => (min_sqrt_price, max_sqrt_price),
+    };
+    let sqrt_price_limit =
+        sqrt_price_limit_x64.unwrap_or(if zero_for_one { min_limit } else { max_limit });
+    let valid_limit = if zero_for_one {
+        sqrt_price_limit < pool.sqrt_price_x64 && sqrt_price_limit >= min_limit
+    } else {
+        sqrt_price_limit > pool.sqrt_price_x64 && sqrt_price_limit <= max_limit
+    };
+    if !valid_limit || pool.tick_lower_bound >= pool.tick_upper_bound {
+        return Err(());
+    }
+    // past the supplied arrays Whirlpool stops on the last tick of the last array
+    // while Raydium would move on to the next initialized array
+    let tick_upper_bound = match tick_math {
+        TickMath::RaydiumClmm => pool.tick_upper_bound,
+        TickMath::Whirlpool => pool.tick_upper_bound - 1,
+    };
+
+    let mut amount_remaining = amount as u128;
+    let mut amount_calculated: u128 = 0;
+    let mut fee_amount: u128 = 0;
+    let mut sqrt_price = pool.sqrt_price_x64;
+    let mut tick = pool.tick_current;
+    let mut liquidity = pool.liquidity;
+
+    while amount_remaining != 0
+        && sqrt_price != sqrt_price_limit
+        && (tick_math == TickMath::Whirlpool || (MIN_TICK + 1..MAX_TICK).contains(&tick))
+    {
+        let next_tick = if zero_for_one {
+            ticks.iter().rev().find(|x| x.tick <= tick)
+        } else {
+            ticks.iter().find(|x| x.tick > tick)
+        };
+        let tick_next = match next_tick {
+            Some(x) => x.tick,
+            None if zero_for_one => pool.tick_lower_bound,
+            None => tick_upper_bound,
+        }
+        .clamp(MIN_TICK, MAX_TICK);
+        let sqrt_price_next = tick_math.sqrt_price_at_tick(tick_next).ok_or(())?;
+        let sqrt_price_target = if zero_for_one {
+            sqrt_price_next.max(sqrt_price_limit)
+        } else {
+            sqrt_price_next.min(sqrt_price_limit)
+        };
+
+        let sqrt_price_start = sqrt_price;
+        let step = compute_swap_step(
+            sqrt_price,
+            sqrt_price_target,
+            liquidity,
+            amount_remaining,
+            pool.fee_rate,
+            is_base_input,
+            zero_for_one,
+        )
+        .ok_or(())?;
+        sqrt_price = step.sqrt_price_next;
+        if is_base_input {
+            amount_remaining = amount_remaining
+                .checked_sub(step.amount_in + step.fee_amount)
+                .ok_or(())?;
+            amount_calculated += step.amount_out;
+        } else {
+            amount_remaining = amount_remaining.checked_sub(step.amount_out).ok_or(())?;
+            amount_calculated += step.amount_in + step.fee_amount;
+        }
+        fee_amount += step.fee_amount;
+
+        if sqrt_price == sqrt_price_next {
+            match next_tick {
+                Some(x) => {
+                    let liquidity_net = if zero_for_one {
+                        -x.liquidity_net
+                    } else {
+                        x.liquidity_net
+                    };
+                    liquidity = if liquidity_net < 0 {
+                        liquidity.checked_sub(liquidity_net.unsigned_abs())
+                    } else {
+                        liquidity.checked_add(liquidity_net as u128)
+                    }
+                    .ok_or(())?;
+                }
+                // the next tick array was not supplied
+                None if amount_remaining != 0 && sqrt_price != sqrt_price_limit => return Err(()),
+                None => (),
+            }
+            tick = if zero_for_one {
+                tick_next - 1
+            } else {
+                tick_next
+            };
+        } else if sqrt_price != sqrt_price_start {
+            tick = tick_math.tick_at_sqrt_price(sqrt_price).ok_or(())?;
+        }
+    }
+
+    let (amount_in, amount_out) = if is_base_input {
+        (amount as u128 - amount_remaining, amount_calculated)
+    } else {
+        (amount_calculated, amount as u128 - amount_remaining)
+    };
+    let amount_in = u64::try_from(amount_in).map_err(|_| ())?;
+    let amount_out = u64::try_from(amount_out).map_err(|_| ())?;
+    Ok(ClmmSwapResult {
+        amount_in,
+        amount_out,
+        fee_amount: u64::try_from(fee_amount).map_err(|_| ())?,
+        sqrt_price_x64: sqrt_price,
+        tick,
+        liquidity,
+        price_impact: price_impact(pool.sqrt_price_x64, amount_in, amount_out, zero_for_one),
+    })
+}
+
+/// Price of token 0 in token 1 out of a Q64.64 square root price
+pub fn sqrt_price_x64_to_price(sqrt_price_x64: u128) -> f64 {
+    let sqrt_price = sqrt_price_x64 as f64 / (1u128 << 64) as f64;
+    sqrt_price * sqrt_price
+}
+
+fn price_impact(sqrt_price_x64: u128, amount_in: u64, amount_out: u64, zero_for_one: bool) -> f64 {
+    if amount_in == 0 || amount_out == 0 {
+        return 0.0;
+    }
+    let spot_price = sqrt_price_x64_to_price(sqrt_price_x64);
+    // execution price of token 0 in token 1
+    let execution_price = if zero_for_one {
+        amount_out as f64 / amount_in as f64
+    } else {
+        amount_in as f64 / amount_out as f64
+    };
+    (1.0 - execution_price / spot_price).abs()
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+    use solana_sdk::pubkey::Pubkey;
+
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::orca::{ORCA_ADDRESS, Traded};
+    use crate::sol::sol_events::sol_platforms::raydium_clmm::{
+        RAYDIUM_CLMM_ADDRESS, RaydiumClmmSwapEvent, SWAP_EVENT_DISCRIMINATOR,
+    };
+    use crate::sol::sol_events::{MutEvents, TradeEvent, event_handler};
+
+    /// Spacing 60, arrays from -32400 to -25200
+    fn raydium_pool() -> (ClmmPool, Vec<ClmmTick>) {
+        let pool = ClmmPool {
+            sqrt_price_x64: 4359947439660241652,
+            tick_current: -28850,
+            liquidity: 250_000_000_000,
+            fee_rate: 2500,
+            tick_math: TickMath::RaydiumClmm,
+            tick_lower_bound: -32400,
+            tick_upper_bound: -25200,
+        };
+        let ticks = [
+            (-30000, 200_000_000_000),
+            (-29040, -150_000_000_000),
+            (-28680, 90_000_000_000),
+            (-27000, -300_000_000_000),
+        ];
+        (pool, to_ticks(&ticks))
+    }
+
+    /// Spacing 64, arrays from -22528 to -5632
+    fn whirlpool() -> (ClmmPool, Vec<ClmmTick>) {
+        let pool = ClmmPool {
+            sqrt_price_x64: 9160702608400867966,
+            tick_current: -14000,
+            liquidity: 3_000_000_000,
+            fee_rate: 3000,
+            tick_math: TickMath::Whirlpool,
+            tick_lower_bound: -22528,
+            tick_upper_bound: -5632,
+        };
+        let ticks = [
+            (-20480, 500_000_000),
+            (-15360, 1_000_000_000),
+            (-12800, -700_000_000),
+            (-9600, -800_000_000),
+        ];
+        (pool, to_ticks(&ticks))
+    }
+
+    fn to_ticks(ticks: &[(i32, i128)]) -> Vec<ClmmTick> {
+        ticks
+            .iter()
+            .map(|(tick, liquidity_net)| ClmmTick {
+                tick: *tick,
+                liquidity_net: *liquidity_net,
+            })
+            .collect()
+    }
+
+    /// amount in, amount out, fee, sqrt price, tick, liquidity
+    fn assert_swap(result: ClmmSwapResult, expected: (u64, u64, u64, u128, i32, u128)) {
+        assert_eq!(
+            (
+                result.amount_in,
+                result.amount_out,
+                result.fee_amount,
+                result.sqrt_price_x64,
+                result.tick,
+                result.liquidity,
+            ),
+            expected
+        );
+    }
+
+    #[test]
+    fn raydium_tick_bounds() {
+        let math = TickMath::RaydiumClmm;
+        assert_eq!(math.sqrt_price_at_tick(MIN_TICK), Some(MIN_SQRT_PRICE_X64));
+        assert_eq!(
+            math.sqrt_price_at_tick(MAX_TICK),
+            Some(RAYDIUM_MAX_SQRT_PRICE_X64)
+        );
+        assert_eq!(math.sqrt_price_at_tick(0), Some(1 << 64));
+        assert_eq!(math.sqrt_price_at_tick(MAX_TICK + 1), None);
+        assert_eq!(math.sqrt_price_at_tick(1), Some(18447666387855957090));
+        assert_eq!(math.sqrt_price_at_tick(-1), Some(18445821805675395072));
+        assert_eq!(math.sqrt_price_at_tick(-1000), Some(17547129613991882732));
+        assert_eq!(
+            math.sqrt_price_at_tick(100000),
+            Some(2737055259402209284734)
+        );
+    }
+
+    #[test]
+    fn whirlpool_tick_bounds() {
+        let math = TickMath::Whirlpool;
+        assert_eq!(math.sqrt_price_at_tick(MIN_TICK), Some(MIN_SQRT_PRICE_X64));
+        assert_eq!(
+            math.sqrt_price_at_tick(MAX_TICK),
+            Some(WHIRLPOOL_MAX_SQRT_PRICE_X64)
+        );
+        assert_eq!(math.sqrt_price_at_tick(0), Some(1 << 64));
+        assert_eq!(math.sqrt_price_at_tick(MIN_TICK - 1), None);
+        assert_eq!(math.sqrt_price_at_tick(1), Some(18447666387855959850));
+        assert_eq!(math.sqrt_price_at_tick(-1), Some(18445821805675392311));
+        assert_eq!(math.sqrt_price_at_tick(-1000), Some(17547129613991598777));
+        assert_eq!(
+            math.sqrt_price_at_tick(100000),
+            Some(2737055259406582257880)
+        );
+    }
+
+    #[test]
+    fn tick_at_sqrt_price_is_the_floor_tick() {
+        for math in [TickMath::RaydiumClmm, TickMath::Whirlpool] {
+            let mut tick = MIN_TICK + 1;
+            while tick < MAX_TICK {
+                let sqrt_price = math.sqrt_price_at_tick(tick).unwrap();
+                assert_eq!(math.tick_at_sqrt_price(sqrt_price), Some(tick), "{math:?}");
+                assert_eq!(
+                    math.tick_at_sqrt_price(sqrt_price - 1),
+                    Some(tick - 1),
+                    "{math:?}"
+                );
+                tick += 997;
+            }
+            assert_eq!(math.tick_at_sqrt_price(MIN_SQRT_PRICE_X64), Some(MIN_TICK));
+            assert_eq!(math.tick_at_sqrt_price(MIN_SQRT_PRICE_X64 - 1), None);
+        }
+        assert_eq!(
+            TickMath::RaydiumClmm.tick_at_sqrt_price(RAYDIUM_MAX_SQRT_PRICE_X64),
+            None
+        );
+        assert_eq!(
+            TickMath::Whirlpool.tick_at_sqrt_price(WHIRLPOOL_MAX_SQRT_PRICE_X64),
+            Some(MAX_TICK)
+        );
+    }
+
+    #[test]
+    fn raydium_swaps() {
+        let (pool, ticks) = raydium_pool();
+        // base input, crosses -29040
+        assert_swap(
+            swap(&pool, &ticks, 30_000_000_000, true, true, None).unwrap(),
+            (
+                30_000_000_000,
+                1_633_047_358,
+                75_000_001,
+                4269178424774802342,
+                -29271,
+                400_000_000_000,
+            ),
+        );
+        // base input, crosses -28680 and -27000
+        assert_swap(
+            swap(&pool, &ticks, 8_000_000_000, true, false, None).unwrap(),
+            (
+                8_000_000_000,
+                129_236_938_941,
+                20_000_001,
+                4954952416242474527,
+                -26292,
+                40_000_000_000,
+            ),
+        );
+        // base output
+        assert_swap(
+            swap(&pool, &ticks, 1_500_000_000, false, true, None).unwrap(),
+            (
+                27_513_316_810,
+                1_500_000_000,
+                68_783_293,
+                4275314151199944046,
+                -29243,
+                400_000_000_000,
+            ),
+        );
+        assert_swap(
+            swap(&pool, &ticks, 1_000_000_000, false, false, None).unwrap(),
+            (
+                56_055_851,
+                1_000_000_000,
+                140_140,
+                4364073290833723863,
+                -28832,
+                250_000_000_000,
+            ),
+        );
+        // within the current tick range
+        assert_swap(
+            swap(&pool, &ticks, 40_000_000, true, true, None).unwrap(),
+            (
+                40_000_000,
+                2_228_843,
+                100_000,
+                4359782980037414589,
+                -28851,
+                250_000_000_000,
+            ),
+        );
+    }
+
+    #[test]
+    fn whirlpool_swaps() {
+        let (pool, ticks) = whirlpool();
+        // base input, crosses -15360 and -20480
+        assert_swap(
+            swap(&pool, &ticks, 2_000_000_000, true, true, None).unwrap(),
+            (
+                2_000_000_000,
+                344_881_134,
+                6_000_002,
+                6165912316498135023,
+                -21919,
+                1_500_000_000,
+            ),
+        );
+        // base input, crosses -12800 and -9600
+        assert_swap(
+            swap(&pool, &ticks, 500_000_000, true, false, None).unwrap(),
+            (
+                500_000_000,
+                1_419_217_052,
+                1_500_002,
+                13824641125944717437,
+                -5769,
+                1_500_000_000,
+            ),
+        );
+        // base output, crosses -15360
+        assert_swap(
+            swap(&pool, &ticks, 100_000_000, false, true, None).unwrap(),
+            (
+                435_982_800,
+                100_000_000,
+                1_307_949,
+                8539460974914453659,
+                -15405,
+                2_000_000_000,
+            ),
+        );
+        assert_swap(
+            swap(&pool, &ticks, 250_000_000, false, false, None).unwrap(),
+            (
+                64_508_673,
+                250_000_000,
+                193_527,
+                9556170953332641120,
+                -13155,
+                3_000_000_000,
+            ),
+        );
+        assert_swap(
+            swap(&pool, &ticks, 1_000_000, true, false, None).unwrap(),
+            (
+                1_000_000,
+                4_040_047,
+                3_000,
+                9166833076348030773,
+                -13987,
+                3_000_000_000,
+            ),
+        );
+    }
+
+    /// Decodes an event the way the log parser does, from its `Program data` payload
+    fn logged(program_id: &str, discriminator: [u8; 8], event: &impl BorshSerialize) -> TradeEvent {
+        let mut data = discriminator.to_vec();
+        data.extend(borsh::to_vec(event).unwrap());
+        match event_handler(program_id, &data, "sig", None) {
+            Ok(Some(MutEvents::TradeEvent(x))) => x,
+            x => panic!("not a trade: {x:?}"),
+        }
+    }
+
+    #[test]
+    fn raydium_swaps_reproduce_their_swap_events() {
+        let (pool, ticks) = raydium_pool();
+        let pool_state = Pubkey::new_unique();
+        // the program logs the amounts moved, the input including the fee
+        let events = [
+            (
+                true,
+                true,
+                30_000_000_000,
+                1_633_047_358,
+                4269178424774802342,
+                400_000_000_000,
+                -29271,
+            ),
+            (
+                false,
+                false,
+                1_000_000_000,
+                56_055_851,
+                4364073290833723863,
+                250_000_000_000,
+                -28832,
+            ),
+        ];
+        for (is_base_input, zero_for_one, amount_0, amount_1, sqrt_price_x64, liquidity, tick) in
+            events
+        {
+            let event = RaydiumClmmSwapEvent {
+                signature: String::new(),
+                pool_state,
+                sender: Pubkey::new_unique(),
+                token_account_0: Pubkey::new_unique(),
+                token_account_1: Pubkey::new_unique(),
+                amount_0,
+                transfer_fee_0: 0,
+                amount_1,
+                transfer_fee_1: 0,
+                zero_for_one,
+                sqrt_price_x64,
+                liquidity,
+                tick,
+            };
+            let TradeEvent::RaydiumClmmTrade(event) =
+                logged(RAYDIUM_CLMM_ADDRESS, SWAP_EVENT_DISCRIMINATOR, &event)
+            else {
+                panic!("not a CLMM swap");
+            };
+            let (amount_in, amount_out) = if event.zero_for_one {
+                (event.amount_0, event.amount_1)
+            } else {
+                (event.amount_1, event.amount_0)
+            };
+            let amount = if is_base_input { amount_in } else { amount_out };
+            let result = swap(
+                &pool,
+                &ticks,
+                amount,
+                is_base_input,
+                event.zero_for_one,
+                None,
+            )
+            .unwrap();
+            assert_eq!(
+                (result.amount_in, result.amount_out),
+                (amount_in, amount_out)
+            );
+            assert_eq!(result.sqrt_price_x64, event.sqrt_price_x64);
+            assert_eq!(result.liquidity, event.liquidity);
+            assert_eq!(result.tick, event.tick);
+        }
+    }
+
+    #[test]
+    fn whirlpool_swaps_reproduce_their_traded_events() {
+        let (pool, ticks) = whirlpool();
+        let events = [
+            (
+                true,
+                true,
+                2_000_000_000,
+                344_881_134,
+                6165912316498135023,
+                4_800_001,
+                1_200_001,
+            ),
+            (
+                false,
+                false,
+                64_508_673,
+                250_000_000,
+                9556170953332641120,
+                154_822,
+                38_705,
+            ),
+        ];
+        for (
+            amount_specified_is_input,
+            a_to_b,
+            input_amount,
+            output_amount,
+            post_sqrt_price,
+            lp_fee,
+            protocol_fee,
+        ) in events
+        {
+            let event = Traded {
+                signature: String::new(),
+                whirlpool: Pubkey::new_unique(),
+                a_to_b,
+                pre_sqrt_price: pool.sqrt_price_x64,
+                post_sqrt_price,
+                input_amount,
+                output_amount,
+                input_transfer_fee: 0,
+                output_transfer_fee: 0,
+                lp_fee,
+                protocol_fee,
+            };
+            let TradeEvent::OrcaTrade(event) =
+                logged(ORCA_ADDRESS, [225, 202, 73, 175, 147, 43, 160, 150], &event)
+            else {
+                panic!("not a Whirlpool trade");
+            };
+            let amount = if amount_specified_is_input {
+                event.input_amount
+            } else {
+                event.output_amount
+            };
+            let result = swap(
+                &pool,
+                &ticks,
+                amount,
+                amount_specified_is_input,
+                event.a_to_b,
+                None,
+            )
+            .unwrap();
+            assert_eq!(
+                (result.amount_in, result.amount_out),
+                (event.input_amount, event.output_amount)
+            );
+            assert_eq!(result.sqrt_price_x64, event.post_sqrt_price);
+            // the fee is split between lp and protocol at every step
+            assert_eq!(result.fee_amount, event.lp_fee + event.protocol_fee);
+        }
+    }
+
+    #[test]
+    fn swaps_fail_past_the_supplied_arrays() {
+        let (pool, ticks) = raydium_pool();
+        assert!(swap(&pool, &ticks, 10_000_000_000_000, true, true, None).is_err());
+        let (pool, ticks) = whirlpool();
+        assert!(swap(&pool, &ticks, 1_000_000_000_000, true, false, None).is_err());
+    }
+
+    #[test]
+    fn price_limits() {
+        let (pool, ticks) = raydium_pool();
+        // the limit must be on the side the price moves to
+        assert!(
+            swap(
+                &pool,
+                &ticks,
+                1_000,
+                true,
+                true,
+                Some(pool.sqrt_price_x64 + 1)
+            )
+            .is_err()
+        );
+        assert!(swap(&pool, &ticks, 1_000, true, true, Some(MIN_SQRT_PRICE_X64)).is_err());
+        let limit = pool.sqrt_price_x64 - 1_000_000_000_000;
+        let result = swap(&pool, &ticks, 30_000_000_000, true, true, Some(limit)).unwrap();
+        assert_eq!(result.sqrt_price_x64, limit);
+        assert!(result.amount_in < 30_000_000_000);
+
+        let (pool, ticks) = whirlpool();
+        assert!(swap(&pool, &ticks, 1_000, true, true, Some(MIN_SQRT_PRICE_X64)).is_ok());
+    }
+}
@@ -0,0 +1,433 @@
+use std::collections::HashMap;
+
+use futures::future::try_join_all;
+use solana_account_decoder_client_types::UiAccountEncoding;
+use solana_client::{nonblocking::rpc_client::RpcClient, rpc_config::RpcAccountInfoConfig};
+use solana_sdk::{account::Account, pubkey::Pubkey};
+
+use super::sol_events::sol_platforms::{
+    orca::Whirlpool,
+    pump_fun::{BondingCurveAccount, PumpFun},
+    raydium_clmm::PoolState,
+    raydium_cpmm::cpmm,
+};
+use super::sol_state::AccountState;
+
+/// `get_multiple_accounts` accepts at most 100 keys
+pub const MAX_ACCOUNTS_PER_REQUEST: usize = 100;
+
+/// A pool to price, by platform
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum PoolKey {
+    /// CPMM pool state address
+    Cpmm(Pubkey),
+    /// CLMM pool state address
+    Clmm(Pubkey),
+    /// Whirlpool address
+    Whirlpool(Pubkey),
+    /// Mint of the pump.fun token, the curve address is derived from it
+    PumpFunCurve(Pubkey),
+}
+
+#[derive(Clone, Debug)]
+pub enum PoolReserves {
+    /// Reserves exclude the uncollected protocol and fund fees
+    Cpmm {
+        pool: cpmm::Pool,
+        reserve_0: u64,
+        reserve_1: u64,
+    },
+    /// Vault balances, only the in range liquidity is tradable at the current price
+    Clmm {
+        pool: Box<PoolState>,
+        vault_0_amount: u64,
+        vault_1_amount: u64,
+    },
+    /// Vault balances, only the in range liquidity is tradable at the current price
+    Whirlpool {
+        pool: Whirlpool,
+        vault_a_amount: u64,
+        vault_b_amount: u64,
+    },
+    /// The curve holds its own virtual and real reserves
+    PumpFunCurve(BondingCurveAccount),
+}
+
+/// Reserves of a batch of pools
+#[derive(Clone, Debug)]
+pub struct ObservedReserves {
+    /// Oldest slot the accounts were read at
+    pub slot: u64,
+    /// Pools missing or failing to decode are left out
+    pub reserves: HashMap<PoolKey, PoolReserves>,
+}
+
+impl PoolKey {
+    fn account(&self) -> Option<Pubkey> {
+        match self {
+            PoolKey::Cpmm(x) | PoolKey::Clmm(x) | PoolKey::Whirlpool(x) => Some(*x),
+            PoolKey::PumpFunCurve(mint) => PumpFun::get_bonding_curve_pda(mint),
+        }
+    }
+}
+
+/// Loads the accounts in concurrent chunks, returning them alongside the oldest slot
+/// observed. Nodes behind `min_context_slot` fail the request rather than answer
+/// with older state.
+async fn fetch_accounts(
+    rpc: &RpcClient,
+    keys: &[Pubkey],
+    min_context_slot: Option<u64>,
+) -> Result<(u64, Vec<Option<Account>>), ()> {
+    let config = RpcAccountInfoConfig {
+        encoding: Some(UiAccountEncoding::Base64),
+        commitment: Some(rpc.commitment()),
+        min_context_slot,
+        data_slice: None,
+    };
+    let responses = try_join_all(
+        keys.chunks(MAX_ACCOUNTS_PER_REQUEST)
+            .map(|chunk| rpc.get_multiple_accounts_with_config(chunk, config.clone())),
+    )
+    .await
+    .map_err(|_| ())?;
+    let slot = responses
+        .iter()
+        .map(|x| x.context.slot)
+        .min()
+        .unwrap_or(u64::MAX);
+    Ok((slot, responses.into_iter().flat_map(|x| x.value).collect()))
+}
+
+/// Amount of a spl token or token-2022 account, both share the base layout
+fn token_account_amount(account: &Account) -> Option<u64> {
+    Some(u64::from_le_bytes(
+        account.data.get(64..72)?.try_into().ok()?,
+    ))
+}
+
+enum DecodedPool {
+    Cpmm(cpmm::Pool),
+    Clmm(Box<PoolState>),
+    Whirlpool(Whirlpool),
+}
+
+impl DecodedPool {
+    fn vaults(&self) -> [Pubkey; 2] {
+        match self {
+            DecodedPool::Cpmm(x) => [x.token0_vault, x.token1_vault],
+            DecodedPool::Clmm(x) => [x.token_vault_0, x.token_vault_1],
+            DecodedPool::Whirlpool(x) => [x.token_vault_a, x.token_vault_b],
+        }
+    }
+}
+
+/// Decodes the pool accounts, the bonding curves are complete on their own while
+/// the other pools still need their vaults. Accounts not owned by the pool's
+/// program or of another kind are left out.
+fn decode_pools(
+    pools: Vec<(PoolKey, Pubkey)>,
+    accounts: Vec<Option<Account>>,
+) -> (HashMap<PoolKey, PoolReserves>, Vec<(PoolKey, DecodedPool)>) {
+    let mut reserves = HashMap::new();
+    let mut decoded = Vec::new();
+    for ((key, _), account) in pools.into_iter().zip(accounts) {
+        // checks the owner and discriminator of each account
+        let Some(state) = account.and_then(|x| AccountState::decode(&x.owner, &x.data)) else {
+            continue;
+        };
+        match (key, state) {
+            (PoolKey::Cpmm(_), AccountState::Cpmm(pool)) => {
+                decoded.push((key, DecodedPool::Cpmm(pool)));
+            }
+            (PoolKey::Clmm(_), AccountState::Clmm(pool)) => {
+                decoded.push((key, DecodedPool::Clmm(pool)));
+            }
+            (PoolKey::Whirlpool(_), AccountState::Whirlpool(pool)) => {
+                decoded.push((key, DecodedPool::Whirlpool(pool)));
+            }
+            (PoolKey::PumpFunCurve(_), AccountState::PumpFunCurve(curve)) => {
+                reserves.insert(key, PoolReserves::PumpFunCurve(curve));
+            }
+            // an account of another kind than requested
+            _ => {}
+        }
+    }
+    (reserves, decoded)
+}
+
+/// Pairs each decoded pool with its two vaults, read in `DecodedPool::vaults` order.
+/// Pools missing a vault are left out.
+fn add_vault_reserves(
+    decoded: Vec<(PoolKey, DecodedPool)>,
+    vaults: &[Option<Account>],
+    reserves: &mut HashMap<PoolKey, PoolReserves>,
+) {
+    for ((key, pool), vaults) in decoded.into_iter().zip(vaults.chunks(2)) {
+        let [Some(vault_0), Some(vault_1)] = vaults else {
+            continue;
+        };
+        let (Some(amount_0), Some(amount_1)) =
+            (token_account_amount(vault_0), token_account_amount(vault_1))
+        else {
+            continue;
+        };
+        let pool_reserves = match pool {
+            DecodedPool::Cpmm(pool) => {
+                let Ok((reserve_0, reserve_1)) = pool.reserves(amount_0, amount_1) else {
+                    continue;
+                };
+                PoolReserves::Cpmm {
+                    pool,
+                    reserve_0,
+                    reserve_1,
+                }
+            }
+            DecodedPool::Clmm(pool) => PoolReserves::Clmm {
+                pool,
+                vault_0_amount: amount_0,
+                vault_1_amount: amount_1,
+            },
+            DecodedPool::Whirlpool(pool) => PoolReserves::Whirlpool {
+                pool,
+                vault_a_amount: amount_0,
+                vault_b_amount: amount_1,
+            },
+        };
+        reserves.insert(key, pool_reserves);
+    }
+}
+
+/// Fetches the pools then their vaults, each step batched in concurrent
+/// `get_multiple_accounts` calls of up to `MAX_ACCOUNTS_PER_REQUEST` accounts.
+/// Accounts not owned by the pool's program or of another kind are left out.
+pub async fn fetch_reserves(rpc: &RpcClient, pools: &[PoolKey]) -> Result<ObservedReserves, ()> {
+    let pools: Vec<(PoolKey, Pubkey)> = pools
+        .iter()
+        .filter_map(|key| Some((*key, key.account()?)))
+        .collect();
+    let keys: Vec<Pubkey> = pools.iter().map(|(_, address)| *address).collect();
+    let (pool_slot, accounts) = fetch_accounts(rpc, &keys, None).await?;
+
+    let (mut reserves, decoded) = decode_pools(pools, accounts);
+    if decoded.is_empty() {
+        return Ok(ObservedReserves {
+            slot: pool_slot,
+            reserves,
+        });
+    }
+
+    let vault_keys: Vec<Pubkey> = decoded.iter().flat_map(|(_, x)| x.vaults()).collect();
+    // vaults are read at or after the pools, never behind them
+    let (vault_slot, vaults) = fetch_accounts(rpc, &vault_keys, Some(pool_slot)).await?;
+    add_vault_reserves(decoded, &vaults, &mut reserves);
+
+    Ok(ObservedReserves {
+        slot: pool_slot.min(vault_slot),
+        reserves,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::{
+        orca::{WHIRLPOOL_DISCRIMINATOR, whirlpools_program_id},
+        pump_fun::{BONDING_CURVE_DISCRIMINATOR, PUMPFUN},
+        raydium_clmm::{POOL_STATE_DISCRIMINATOR, RAYDIUM_CLMM},
+        test_utils::{clmm_pool, cpmm_pool, whirlpool},
+    };
+
+    fn account(owner: Pubkey, discriminator: &[u8], state: &impl BorshSerialize) -> Account {
+        let mut data = discriminator.to_vec();
+        state.serialize(&mut data).unwrap();
+        Account {
+            lamports: 1_000_000,
+            data,
+            owner,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn token_account(amount: u64) -> Account {
+        let mut data = vec![0; 165];
+        data[64..72].copy_from_slice(&amount.to_le_bytes());
+        Account {
+            lamports: 2_039_280,
+            data,
+            owner: spl_token::ID,
+            executable: false,
+            rent_epoch: 0,
+        }
+    }
+
+    fn curve() -> BondingCurveAccount {
+        BondingCurveAccount {
+            discriminator: u64::from_le_bytes(BONDING_CURVE_DISCRIMINATOR),
+            virtual_token_reserves: 1_073_000_000_000_000,
+            virtual_sol_reserves: 30_000_000_000,
+            real_token_reserves: 793_100_000_000_000,
+            real_sol_reserves: 0,
+            token_total_supply: 1_000_000_000_000_000,
+            complete: false,
+            creator: Pubkey::new_unique(),
+        }
+    }
+
+    fn cpmm_account() -> Account {
+        account(
+            cpmm::RAYDIUM_CPMM_PROGRAM,
+            &cpmm::POOL_STATE_DISCRIMINATOR,
+            &cpmm_pool(),
+        )
+    }
+
+    /// Decodes the pools then pairs them with the given vaults, as `fetch_reserves` does
+    fn reserves(
+        pools: Vec<(PoolKey, Option<Account>)>,
+        vault_amounts: impl Fn(&Pubkey) -> Option<u64>,
+    ) -> HashMap<PoolKey, PoolReserves> {
+        let (keys, accounts) = pools
+            .into_iter()
+            .map(|(key, account)| ((key, Pubkey::new_unique()), account))
+            .unzip();
+        let (mut reserves, decoded) = decode_pools(keys, accounts);
+        let vaults: Vec<Option<Account>> = decoded
+            .iter()
+            .flat_map(|(_, x)| x.vaults())
+            .map(|x| vault_amounts(&x).map(token_account))
+            .collect();
+        add_vault_reserves(decoded, &vaults, &mut reserves);
+        reserves
+    }
+
+    #[test]
+    fn cpmm_reserves_leave_out_the_uncollected_fees() {
+        let key = PoolKey::Cpmm(Pubkey::new_unique());
+        let reserves = reserves(vec![(key, Some(cpmm_account()))], |_| Some(5_000_000_000));
+        let Some(PoolReserves::Cpmm {
+            reserve_0,
+            reserve_1,
+            ..
+        }) = reserves.get(&key)
+        else {
+            panic!("{reserves:?}");
+        };
+        // protocol and fund fees of the fixture: 4_000_000 of token 0, 600_000 of token 1
+        assert_eq!((*reserve_0, *reserve_1), (4_996_000_000, 4_999_400_000));
+    }
+
+    #[test]
+    fn clmm_and_whirlpool_reserves_are_their_vault_amounts() {
+        let clmm = clmm_pool(0, &[0]);
+        let whirlpool = whirlpool(0);
+        let amounts = HashMap::from([
+            (clmm.token_vault_0, 10),
+            (clmm.token_vault_1, 20),
+            (whirlpool.token_vault_a, 30),
+            (whirlpool.token_vault_b, 40),
+        ]);
+        let clmm_key = PoolKey::Clmm(Pubkey::new_unique());
+        let whirlpool_key = PoolKey::Whirlpool(Pubkey::new_unique());
+        let reserves = reserves(
+            vec![
+                (
+                    clmm_key,
+                    Some(account(RAYDIUM_CLMM, &POOL_STATE_DISCRIMINATOR, &clmm)),
+                ),
+                (
+                    whirlpool_key,
+                    Some(account(
+                        whirlpools_program_id(),
+                        &WHIRLPOOL_DISCRIMINATOR,
+                        &whirlpool,
+                    )),
+                ),
+            ],
+            |x| amounts.get(x).copied(),
+        );
+        assert!(matches!(
+            reserves.get(&clmm_key),
+            Some(PoolReserves::Clmm {
+                vault_0_amount: 10,
+                vault_1_amount: 20,
+                ..
+            })
+        ));
+        assert!(matches!(
+            reserves.get(&whirlpool_key),
+            Some(PoolReserves::Whirlpool {
+                vault_a_amount: 30,
+                vault_b_amount: 40,
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn bonding_curves_need_no_vaults() {
+        let key = PoolKey::PumpFunCurve(Pubkey::new_unique());
+        let curve = curve();
+        // the discriminator is the curve's first field
+        let reserves = reserves(vec![(key, Some(account(PUMPFUN, &[], &curve)))], |_| None);
+        let Some(PoolReserves::PumpFunCurve(decoded)) = reserves.get(&key) else {
+            panic!("{reserves:?}");
+        };
+        assert_eq!(decoded.virtual_sol_reserves, curve.virtual_sol_reserves);
+        assert_eq!(decoded.creator, curve.creator);
+    }
+
+    #[test]
+    fn pools_missing_a_vault_are_left_out() {
+        let pool = cpmm_pool();
+        let key = PoolKey::Cpmm(Pubkey::new_unique());
+        let account = account(
+            cpmm::RAYDIUM_CPMM_PROGRAM,
+            &cpmm::POOL_STATE_DISCRIMINATOR,
+            &pool,
+        );
+        let reserves = reserves(vec![(key, Some(account))], |x| {
+            (*x != pool.token1_vault).then_some(5_000_000_000)
+        });
+        assert!(reserves.is_empty());
+    }
+
+    #[test]
+    fn accounts_failing_to_decode_are_left_out() {
+        let truncated = {
+            let mut account = cpmm_account();
+            account.data.truncate(100);
+            account
+        };
+        let wrong_owner = {
+            let mut account = cpmm_account();
+            account.owner = Pubkey::new_unique();
+            account
+        };
+        let wrong_discriminator = account(
+            cpmm::RAYDIUM_CPMM_PROGRAM,
+            &cpmm::AMM_CONFIG_DISCRIMINATOR,
+            &cpmm_pool(),
+        );
+        let pools = [
+            None,
+            Some(truncated),
+            Some(wrong_owner),
+            Some(wrong_discriminator),
+        ]
+        .into_iter()
+        .map(|x| (PoolKey::Cpmm(Pubkey::new_unique()), x))
+        .collect();
+        assert!(reserves(pools, |_| Some(5_000_000_000)).is_empty());
+    }
+
+    #[test]
+    fn accounts_of_another_kind_than_requested_are_left_out() {
+        let key = PoolKey::Clmm(Pubkey::new_unique());
+        let reserves = reserves(vec![(key, Some(cpmm_account()))], |_| Some(5_000_000_000));
+        assert!(reserves.is_empty());
+    }
+}
@@ -0,0 +1,409 @@
+use std::collections::HashMap;
+
+use crossbeam_queue::ArrayQueue;
+use parking_lot::RwLock;
+use solana_sdk::pubkey::Pubkey;
+use yellowstone_grpc_proto::geyser::SubscribeUpdateAccount;
+
+use super::geyser::{AccountFilter, GeyserSubscription};
+use super::sol_events::sol_platforms::{
+    orca::{self, Whirlpool},
+    pump_fun::{self, BondingCurveAccount},
+    raydium_clmm::{self, PoolState},
+    raydium_cpmm::cpmm,
+};
+use super::sol_events::sol_system_ix::SYSTEM_PROGRAM;
+
+/// A decoded pool or bonding curve account
+#[derive(Clone, Debug)]
+pub enum AccountState {
+    PumpFunCurve(BondingCurveAccount),
+    Cpmm(cpmm::Pool),
+    Clmm(Box<PoolState>),
+    Whirlpool(Whirlpool),
+}
+
+#[derive(Clone, Debug)]
+pub struct TrackedAccount {
+    /// Slot the account was written at
+    pub slot: u64,
+    /// Orders the writes within a slot
+    pub write_version: u64,
+    pub state: AccountState,
+}
+
+impl AccountState {
+    /// Decodes the accounts of the tracked programs, by owner then discriminator
+    pub fn decode(owner: &Pubkey, data: &[u8]) -> Option<Self> {
+        let discriminator = data.get(..8)?;
+        let state = if *owner == pump_fun::PUMPFUN {
+            if discriminator != pump_fun::BONDING_CURVE_DISCRIMINATOR {
+                return None;
+            }
+            AccountState::PumpFunCurve(
+                solana_sdk::borsh1::try_from_slice_unchecked::<BondingCurveAccount>(data).ok()?,
+            )
+        } else if *owner == cpmm::RAYDIUM_CPMM_PROGRAM {
+            if discriminator != cpmm::POOL_STATE_DISCRIMINATOR {
+                return None;
+            }
+            AccountState::Cpmm(cpmm::Pool::from_bytes(data).ok()?)
+        } else if *owner == raydium_clmm::RAYDIUM_CLMM {
+            AccountState::Clmm(Box::new(PoolState::from_bytes(data).ok()?))
+        } else if *owner == orca::whirlpools_program_id() {
+            AccountState::Whirlpool(Whirlpool::from_bytes(data).ok()?)
+        } else {
+            return None;
+        };
+        Some(state)
+    }
+
+    /// Program owning the account
+    pub fn owner(&self) -> Pubkey {
+        match self {
+            AccountState::PumpFunCurve(_) => pump_fun::PUMPFUN,
+            AccountState::Cpmm(_) => cpmm::RAYDIUM_CPMM_PROGRAM,
+            AccountState::Clmm(_) => raydium_clmm::RAYDIUM_CLMM,
+            AccountState::Whirlpool(_) => orca::whirlpools_program_id(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct TrackedAccounts {
+    accounts: HashMap<Pubkey, TrackedAccount>,
+    /// `(slot, write_version)` the accounts were closed or handed to another owner
+    /// at, older updates replayed after a reconnect must not bring them back
+    removed: HashMap<Pubkey, (u64, u64)>,
+}
+
+/// Latest state of the tracked accounts, fed by geyser account updates
+#[derive(Default)]
+pub struct AccountStateCache {
+    accounts: RwLock<TrackedAccounts>,
+}
+
+impl AccountStateCache {
+    pub fn new() -> Self {
+        AccountStateCache::default()
+    }
+
+    /// Every bonding curve and pool of the tracked programs, one filter per program
+    pub fn subscribe_all(subscription: GeyserSubscription) -> GeyserSubscription {
+        subscription
+            .account_filter(
+                "pump_fun_curves",
+                AccountFilter::new()
+                    .owners([pump_fun::PUMPFUN])
+                    .discriminator(pump_fun::BONDING_CURVE_DISCRIMINATOR),
+            )
+            .account_filter(
+                "cpmm_pools",
+                AccountFilter::new()
+                    .owners([cpmm::RAYDIUM_CPMM_PROGRAM])
+                    .discriminator(cpmm::POOL_STATE_DISCRIMINATOR),
+            )
+            .account_filter(
+                "clmm_pools",
+                AccountFilter::new()
+                    .owners([raydium_clmm::RAYDIUM_CLMM])
+                    .discriminator(raydium_clmm::POOL_STATE_DISCRIMINATOR),
+            )
+            .account_filter(
+                "whirlpools",
+                AccountFilter::new()
+                    .owners([orca::whirlpools_program_id()])
+                    .discriminator(orca::WHIRLPOOL_DISCRIMINATOR),
+            )
+    }
+
+    /// Only the listed pools and curves, by address
+    pub fn subscribe_accounts(
+        subscription: GeyserSubscription,
+        accounts: &[Pubkey],
+    ) -> GeyserSubscription {
+        subscription.account_filter("tracked_accounts", AccountFilter::new().accounts(accounts))
+    }
+
+    /// Stores the update when it decodes and is newer than the cached write. A
+    /// newer update closing the account, or moving it to another owner, removes it.
+    /// Returns whether the cache changed.
+    pub fn apply(&self, update: &SubscribeUpdateAccount) -> bool {
+        let Some(info) = &update.account else {
+            return false;
+        };
+        let (Ok(key), Ok(owner)) = (
+            Pubkey::try_from(info.pubkey.as_slice()),
+            Pubkey::try_from(info.owner.as_slice()),
+        ) else {
+            return false;
+        };
+        let write = (update.slot, info.write_version);
+        let mut tracked = self.accounts.write();
+        let last_write = tracked
+            .accounts
+            .get(&key)
+            .map(|x| (x.slot, x.write_version))
+            .or_else(|| tracked.removed.get(&key).copied());
+        if last_write.is_some_and(|x| x >= write) {
+            return false;
+        }
+        let closed = info.lamports == 0 || info.data.is_empty() || owner == SYSTEM_PROGRAM;
+        let state = if closed {
+            None
+        } else {
+            AccountState::decode(&owner, &info.data)
+        };
+        let Some(state) = state else {
+            let reassigned = tracked
+                .accounts
+                .get(&key)
+                .is_some_and(|x| x.state.owner() != owner);
+            if !closed && !reassigned {
+                // not an account of the tracked kinds
+                return false;
+            }
+            tracked.accounts.remove(&key);
+            tracked.removed.insert(key, write);
+            return true;
+        };
+        tracked.removed.remove(&key);
+        tracked.accounts.insert(
+            key,
+            TrackedAccount {
+                slot: update.slot,
+                write_version: info.write_version,
+                state,
+            },
+        );
+        true
+    }
+
+    /// Applies the queued updates, see `UpdateQueues::accounts`, returns how many
+    /// changed the cache
+    pub fn drain(&self, queue: &ArrayQueue<SubscribeUpdateAccount>) -> usize {
+        let mut applied = 0;
+        while let Some(update) = queue.pop() {
+            if self.apply(&update) {
+                applied += 1;
+            }
+        }
+        applied
+    }
+
+    pub fn get(&self, key: &Pubkey) -> Option<TrackedAccount> {
+        self.accounts.read().accounts.get(key).cloned()
+    }
+
+    /// Curve of a pump.fun mint
+    pub fn bonding_curve(&self, mint: &Pubkey) -> Option<(u64, BondingCurveAccount)> {
+        let tracked = self.get(&pump_fun::PumpFun::get_bonding_curve_pda(mint)?)?;
+        match tracked.state {
+            AccountState::PumpFunCurve(curve) => Some((tracked.slot, curve)),
+            _ => None,
+        }
+    }
+
+    /// Stops tracking the account, a later update adds it back
+    pub fn remove(&self, key: &Pubkey) -> Option<TrackedAccount> {
+        let mut tracked = self.accounts.write();
+        tracked.removed.remove(key);
+        tracked.accounts.remove(key)
+    }
+
+    pub fn len(&self) -> usize {
+        self.accounts.read().accounts.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.accounts.read().accounts.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use borsh::BorshSerialize;
+    use yellowstone_grpc_proto::geyser::SubscribeUpdateAccountInfo;
+
+    use super::*;
+    use crate::sol::sol_events::sol_platforms::{
+        orca::WHIRLPOOL_DISCRIMINATOR,
+        pump_fun::BONDING_CURVE_DISCRIMINATOR,
+        raydium_clmm::POOL_STATE_DISCRIMINATOR,
+        test_utils::{clmm_pool, cpmm_pool, whirlpool},
+    };
+
+    fn data(discriminator: &[u8], state: &impl BorshSerialize) -> Vec<u8> {
+        let mut data = discriminator.to_vec();
+        state.serialize(&mut data).unwrap();
+        data
+    }
+
+    fn curve(virtual_sol_reserves: u64) -> Vec<u8> {
+        let curve = BondingCurveAccount {
+            discriminator: u64::from_le_bytes(BONDING_CURVE_DISCRIMINATOR),
+            virtual_token_reserves: 1_073_000_000_000_000,
+            virtual_sol_reserves,
+            real_token_reserves: 793_100_000_000_000,
+            real_sol_reserves: 0,
+            token_total_supply: 1_000_000_000_000_000,
+            complete: false,
+            creator: Pubkey::new_unique(),
+        };
+        data(&[], &curve)
+    }
+
+    fn update(
+        key: Pubkey,
+        owner: Pubkey,
+        data: Vec<u8>,
+        slot: u64,
+        write_version: u64,
+    ) -> SubscribeUpdateAccount {
+        SubscribeUpdateAccount {
+            account: Some(SubscribeUpdateAccountInfo {
+                pubkey: key.to_bytes().to_vec(),
+                lamports: 1_000_000,
+                owner: owner.to_bytes().to_vec(),
+                data,
+                write_version,
+                ..Default::default()
+            }),
+            slot,
+            ..Default::default()
+        }
+    }
+
+    fn virtual_sol_reserves(cache: &AccountStateCache, key: &Pubkey) -> Option<u64> {
+        match cache.get(key)?.state {
+            AccountState::PumpFunCurve(curve) => Some(curve.virtual_sol_reserves),
+            state => panic!("{state:?}"),
+        }
+    }
+
+    #[test]
+    fn decodes_each_tracked_program() {
+        let decoded = [
+            AccountState::decode(&pump_fun::PUMPFUN, &curve(30_000_000_000)),
+            AccountState::decode(
+                &cpmm::RAYDIUM_CPMM_PROGRAM,
+                &data(&cpmm::POOL_STATE_DISCRIMINATOR, &cpmm_pool()),
+            ),
+            AccountState::decode(
+                &raydium_clmm::RAYDIUM_CLMM,
+                &data(&POOL_STATE_DISCRIMINATOR, &clmm_pool(0, &[0])),
+            ),
+            AccountState::decode(
+                &orca::whirlpools_program_id(),
+                &data(&WHIRLPOOL_DISCRIMINATOR, &whirlpool(0)),
+            ),
+        ];
+        assert!(matches!(decoded[0], Some(AccountState::PumpFunCurve(_))));
+        assert!(matches!(decoded[1], Some(AccountState::Cpmm(_))));
+        assert!(matches!(decoded[2], Some(AccountState::Clmm(_))));
+        assert!(matches!(decoded[3], Some(AccountState::Whirlpool(_))));
+        let owners: Vec<_> = decoded.iter().flatten().map(AccountState::owner).collect();
+        assert_eq!(
+            owners,
+            [
+                pump_fun::PUMPFUN,
+                cpmm::RAYDIUM_CPMM_PROGRAM,
+                raydium_clmm::RAYDIUM_CLMM,
+                orca::whirlpools_program_id(),
+            ]
+        );
+    }
+
+    #[test]
+    fn decode_rejects_other_owners_and_discriminators() {
+        let pool = data(&cpmm::POOL_STATE_DISCRIMINATOR, &cpmm_pool());
+        assert!(AccountState::decode(&pump_fun::PUMPFUN, &pool).is_none());
+        assert!(AccountState::decode(&Pubkey::new_unique(), &pool).is_none());
+        let mut curve = curve(30_000_000_000);
+        curve[0] ^= 1;
+        assert!(AccountState::decode(&pump_fun::PUMPFUN, &curve).is_none());
+        assert!(AccountState::decode(&pump_fun::PUMPFUN, &curve[..7]).is_none());
+    }
+
+    #[test]
+    fn keeps_the_latest_write() {
+        let cache = AccountStateCache::default();
+        let key = Pubkey::new_unique();
+        assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(10), 100, 5)));
+        // older slot, then same slot with an older or equal write version
+        assert!(!cache.apply(&update(key, pump_fun::PUMPFUN, curve(11), 99, 9)));
+        assert!(!cache.apply(&update(key, pump_fun::PUMPFUN, curve(12), 100, 4)));
+        assert!(!cache.apply(&update(key, pump_fun::PUMPFUN, curve(13), 100, 5)));
+        assert_eq!(virtual_sol_reserves(&cache, &key), Some(10));
+        assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(14), 100, 6)));
+        assert_eq!(virtual_sol_reserves(&cache, &key), Some(14));
+        assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(15), 101, 0)));
+        assert_eq!(virtual_sol_reserves(&cache, &key), Some(15));
+        assert_eq!(
+            cache.get(&key).map(|x| (x.slot, x.write_version)),
+            Some((101, 0))
+        );
+    }
+
+    #[test]
+    fn ignores_accounts_it_cannot_decode() {
+        let cache = AccountStateCache::default();
+        let key = Pubkey::new_unique();
+        assert!(!cache.apply(&update(key, Pubkey::new_unique(), curve(10), 100, 0)));
+        assert!(!cache.apply(&SubscribeUpdateAccount::default()));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn closing_removes_the_account() {
+        let closes: [fn(&mut SubscribeUpdateAccountInfo); 3] = [
+            |x| x.lamports = 0,
+            |x| x.data.clear(),
+            |x| x.owner = SYSTEM_PROGRAM.to_bytes().to_vec(),
+        ];
+        for close in closes {
+            let cache = AccountStateCache::default();
+            let key = Pubkey::new_unique();
+            assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(10), 100, 0)));
+            let mut closed = update(key, pump_fun::PUMPFUN, curve(10), 100, 1);
+            close(closed.account.as_mut().unwrap());
+            assert!(cache.apply(&closed));
+            assert!(cache.get(&key).is_none());
+        }
+    }
+
+    #[test]
+    fn a_stale_close_keeps_the_account() {
+        let cache = AccountStateCache::default();
+        let key = Pubkey::new_unique();
+        assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(10), 100, 1)));
+        let mut closed = update(key, pump_fun::PUMPFUN, vec![], 100, 0);
+        closed.account.as_mut().unwrap().lamports = 0;
+        assert!(!cache.apply(&closed));
+        assert_eq!(virtual_sol_reserves(&cache, &key), Some(10));
+    }
+
+    #[test]
+    fn an_owner_change_removes_the_account() {
+        let cache = AccountStateCache::default();
+        let key = Pubkey::new_unique();
+        assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(10), 100, 0)));
+        assert!(cache.apply(&update(key, Pubkey::new_unique(), curve(10), 101, 0)));
+        assert!(cache.get(&key).is_none());
+    }
+
+    #[test]
+    fn writes_older_than_the_close_do_not_bring_the_account_back() {
+        let cache = AccountStateCache::default();
+        let key = Pubkey::new_unique();
+        assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(10), 100, 0)));
+        assert!(cache.apply(&update(key, SYSTEM_PROGRAM, vec![], 102, 0)));
+        // replayed after a reconnect
+        assert!(!cache.apply(&update(key, pump_fun::PUMPFUN, curve(11), 101, 0)));
+        assert!(!cache.apply(&update(key, pump_fun::PUMPFUN, curve(12), 102, 0)));
+        assert!(cache.get(&key).is_none());
+        // reopened
+        assert!(cache.apply(&update(key, pump_fun::PUMPFUN, curve(13), 103, 0)));
+        assert_eq!(virtual_sol_reserves(&cache, &key), Some(13));
+    }
+}